@@ -1,7 +1,6 @@
 use main_app::loader::{Assets, LocalFileLoader};
-use minifb::{Key, Scale, ScaleMode, Window, WindowOptions};
+use minifb::{Key, KeyRepeat, Scale, ScaleMode, Window, WindowOptions};
 use std::collections::HashMap;
-use std::time::Instant;
 const WIDTH: usize = 320;
 const HEIGHT: usize = 200;
 
@@ -28,29 +27,39 @@ fn main() {
         panic!("{}", e);
     });
 
-    // Limit to max ~60 fps update rate
+    // Limit to max ~60 fps update rate. The game runs at the same speed
+    // with any frame rate, this only saves some CPU
     window.limit_update_rate(Some(std::time::Duration::from_micros(16600)));
     raycast.init();
     raycast.assets.init();
-    raycast.assets.load();
-    raycast.correct_background_colors(); //some workaround to speed up drawing the background
-    let mut average_execution_time: u128 = 0;
-    let mut fps_counter_reset: u128 = 0;
-    let samples = 20;
+    raycast.load_textures();
+    let mut screenshots = 0;
     while window.is_open() && !window.is_key_down(Key::Escape) {
-        let start = Instant::now();
+        // the frame time is shown by the HUD
         raycast.game_step(&window);
-        average_execution_time += start.elapsed().as_micros();
-        if fps_counter_reset % samples == 0 {
-            println!("Frame time {} ms", average_execution_time / 1000 / samples);
-            average_execution_time = 0;
+        // there is no audio yet. The sounds are taken anyway, or they would
+        // pile up for the whole game
+        let _ = raycast.take_sound_events();
+        // the same with the pickups touched, nothing reacts to them here
+        let _ = raycast.take_touch_events();
+        if window.is_key_pressed(Key::F12, KeyRepeat::No) {
+            let path = format!("screenshot_{}.ff", screenshots);
+            match raycast.save_screenshot(&path) {
+                Ok(_) => raycast
+                    .hud()
+                    .show_message(&format!("SCREENSHOT SAVED TO {}", path), 2.0),
+                Err(error) => {
+                    raycast
+                        .hud()
+                        .show_message("COULD NOT SAVE THE SCREENSHOT", 2.0);
+                    eprintln!("Could not save the screenshot: {}", error);
+                }
+            }
+            screenshots += 1;
         }
-        // open and close the doors
-        raycast.move_doors_demo();
         // We unwrap here as we want this code to exit if it fails. Real applications may want to handle this in a different way
         window
             .update_with_buffer(raycast.get_buffer_to_print(), WIDTH, HEIGHT)
             .unwrap();
-        fps_counter_reset += 1;
     }
 }
@@ -0,0 +1,447 @@
+// Enemies: what they are (EnemyKind), what they are doing (EnemyState) and
+// how they decide what to do next. An enemy only decides, from what it
+// senses in a tick. The game moves it along its path, opens the doors in
+// its way and applies its attacks, see GameWindow::think_enemy.
+// Random choices come from a seeded Rng, so a game started with the same
+// seed plays the same way every time.
+
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use std::f32::consts::{FRAC_PI_3, FRAC_PI_4, PI};
+
+// ticks an attack takes, it hits in the middle
+const ATTACK_TICKS: u32 = 20;
+const PAIN_TICKS: u32 = 12;
+// how far an enemy that isn't alert sees, and how much to the sides
+const SIGHT_DISTANCE: f32 = 1024.0;
+const SIGHT_HALF_ANGLE: f32 = FRAC_PI_3;
+// the chance (out of 100) every tick that a chasing enemy in range attacks
+const ATTACK_CHANCE: u32 = 10;
+const WALK_TICKS_PER_FRAME: u32 = 6;
+// columns of the walking animation in the sprite sheets
+const WALK_FRAMES: u32 = 8;
+
+// xorshift, small and the same on every machine
+#[derive(Clone, Debug, PartialEq)]
+pub struct Rng {
+    state: u32,
+}
+
+impl Rng {
+    pub fn new(seed: u32) -> Self {
+        // xorshift stays at 0 forever
+        Rng { state: seed.max(1) }
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 17;
+        self.state ^= self.state << 5;
+        self.state
+    }
+
+    // a number from 0 to n - 1
+    pub fn below(&mut self, n: u32) -> u32 {
+        if n == 0 {
+            0
+        } else {
+            self.next_u32() % n
+        }
+    }
+
+    // true `percent` times out of 100
+    pub fn chance(&mut self, percent: u32) -> bool {
+        self.below(100) < percent
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EnemyKind {
+    Guard,
+    Skeleton,
+    Golem,
+}
+
+impl EnemyKind {
+    /**
+     * The sprite sheet, 8 walking frames in every row. The rows are the
+     * front, the back, walking to the left and walking to the right.
+     */
+    pub fn texture_id(self) -> u32 {
+        match self {
+            EnemyKind::Guard => 43,
+            EnemyKind::Skeleton => 72,
+            EnemyKind::Golem => 49,
+        }
+    }
+
+    // the size of the sprite in world units
+    pub fn size(self) -> (u8, u8) {
+        match self {
+            EnemyKind::Guard | EnemyKind::Skeleton => (40, 64),
+            EnemyKind::Golem => (64, 80),
+        }
+    }
+
+    pub fn radius(self) -> f32 {
+        match self {
+            EnemyKind::Guard | EnemyKind::Skeleton => 12.0,
+            EnemyKind::Golem => 20.0,
+        }
+    }
+
+    pub fn health(self) -> i32 {
+        match self {
+            EnemyKind::Guard => 25,
+            EnemyKind::Skeleton => 40,
+            EnemyKind::Golem => 150,
+        }
+    }
+
+    // world units per tick
+    pub fn speed(self) -> f32 {
+        match self {
+            EnemyKind::Guard => 2.0,
+            EnemyKind::Skeleton => 1.5,
+            EnemyKind::Golem => 1.0,
+        }
+    }
+
+    // guards shoot from far away, the others have to get close
+    pub fn attack_range(self) -> f32 {
+        match self {
+            EnemyKind::Guard => 320.0,
+            EnemyKind::Skeleton => 56.0,
+            EnemyKind::Golem => 72.0,
+        }
+    }
+
+    pub fn damage(self) -> i32 {
+        match self {
+            EnemyKind::Guard => 8,
+            EnemyKind::Skeleton => 12,
+            EnemyKind::Golem => 30,
+        }
+    }
+
+    // ticks between seeing the player and going after it
+    pub fn reaction_ticks(self) -> u32 {
+        match self {
+            EnemyKind::Guard => 20,
+            EnemyKind::Skeleton => 30,
+            EnemyKind::Golem => 45,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EnemyState {
+    // waiting to see the player
+    Idle,
+    // it saw the player, and goes after it when it reacts
+    Alert,
+    Chase,
+    Attack,
+    // it was hurt, it stops for a moment
+    Pain,
+    Dead,
+}
+
+// what an enemy knows in a tick, the game finds it out
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Senses {
+    // nothing of the map is between the enemy and the player
+    pub sees_player: bool,
+    pub player_distance: f32,
+    // from the enemy to the player, in radians
+    pub player_angle: f32,
+    pub health: i32,
+}
+
+// what an enemy decided to do in a tick
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Intent {
+    Stand,
+    // goes towards the player, along its path when it can't see it
+    Chase,
+    // the player is hit, with this damage
+    Attack(i32),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Enemy {
+    pub kind: EnemyKind,
+    pub state: EnemyState,
+    // ticks since it got into the state
+    pub ticks: u32,
+    // where it looks, in radians
+    pub facing: f32,
+    // the cells to walk through to get to the player, the next one first
+    pub path: Vec<(i32, i32)>,
+    // ticks until the path is searched again
+    pub repath: u32,
+    // ticks it waits in Alert, a bit different every time
+    reaction: u32,
+    // the health in the last tick, to know when it's hurt. It starts full
+    last_health: i32,
+}
+
+impl Enemy {
+    pub fn new(kind: EnemyKind, facing: f32) -> Self {
+        Enemy {
+            kind,
+            state: EnemyState::Idle,
+            ticks: 0,
+            facing,
+            path: Vec::new(),
+            repath: 0,
+            reaction: 0,
+            last_health: kind.health(),
+        }
+    }
+
+    fn set_state(&mut self, state: EnemyState) {
+        self.state = state;
+        self.ticks = 0;
+    }
+
+    /**
+     * Decides what to do this tick. Getting hurt wakes it up, and dead
+     * enemies stay dead.
+     */
+    pub fn think(&mut self, senses: Senses, rng: &mut Rng) -> Intent {
+        self.ticks += 1;
+        let hurt = senses.health < self.last_health;
+        self.last_health = senses.health;
+        if self.state == EnemyState::Dead {
+            return Intent::Stand;
+        }
+        if senses.health <= 0 {
+            self.set_state(EnemyState::Dead);
+            return Intent::Stand;
+        }
+        if hurt {
+            self.set_state(EnemyState::Pain);
+        }
+        let in_range = senses.sees_player && senses.player_distance <= self.kind.attack_range();
+        match self.state {
+            EnemyState::Idle => {
+                let turn = angle_between(self.facing, senses.player_angle);
+                if senses.sees_player
+                    && senses.player_distance <= SIGHT_DISTANCE
+                    && turn <= SIGHT_HALF_ANGLE
+                {
+                    self.reaction = self.kind.reaction_ticks() + rng.below(10);
+                    self.set_state(EnemyState::Alert);
+                }
+                Intent::Stand
+            }
+            EnemyState::Alert => {
+                if senses.sees_player {
+                    self.facing = senses.player_angle;
+                }
+                if self.ticks >= self.reaction {
+                    self.set_state(EnemyState::Chase);
+                    return Intent::Chase;
+                }
+                Intent::Stand
+            }
+            EnemyState::Chase => {
+                if in_range && rng.chance(ATTACK_CHANCE) {
+                    self.facing = senses.player_angle;
+                    self.set_state(EnemyState::Attack);
+                    return Intent::Stand;
+                }
+                Intent::Chase
+            }
+            EnemyState::Attack => {
+                let mut intent = Intent::Stand;
+                if self.ticks == ATTACK_TICKS / 2 {
+                    // the farther away, the easier to miss
+                    let hit_chance = (90.0 - senses.player_distance / 8.0).max(20.0) as u32;
+                    if in_range && rng.chance(hit_chance) {
+                        intent = Intent::Attack(self.kind.damage());
+                    }
+                }
+                if self.ticks >= ATTACK_TICKS {
+                    self.set_state(EnemyState::Chase);
+                }
+                intent
+            }
+            EnemyState::Pain => {
+                if self.ticks >= PAIN_TICKS {
+                    self.set_state(EnemyState::Chase);
+                }
+                Intent::Stand
+            }
+            EnemyState::Dead => Intent::Stand,
+        }
+    }
+
+    /**
+     * The frame of the sprite sheet to draw, as (column, row), for someone
+     * looking from `viewer_angle` (the angle from the enemy to the viewer).
+     */
+    pub fn frame(&self, viewer_angle: f32) -> (u32, u32) {
+        let column = if self.state == EnemyState::Chase {
+            (self.ticks / WALK_TICKS_PER_FRAME) % WALK_FRAMES
+        } else {
+            0
+        };
+        // how much it's turned away from the viewer, positive is to the
+        // left of the viewer
+        let turn = (self.facing - viewer_angle + PI).rem_euclid(2.0 * PI) - PI;
+        let row = if turn.abs() <= FRAC_PI_4 {
+            0
+        } else if turn.abs() >= 3.0 * FRAC_PI_4 {
+            1
+        } else if turn > 0.0 {
+            2
+        } else {
+            3
+        };
+        (column, row)
+    }
+}
+
+// from 0 to PI
+fn angle_between(a: f32, b: f32) -> f32 {
+    ((a - b + PI).rem_euclid(2.0 * PI) - PI).abs()
+}
+
+/**
+ * The cheapest way from `start` to `goal` over the cells of a grid, going
+ * north, south, east and west (A*). `cost` is what walking into a cell
+ * costs, None if it can't be walked into. The path doesn't have `start`
+ * and ends at `goal`. It gives up after looking at `max_cells` cells, so a
+ * goal that can't be reached doesn't go through the whole map.
+ */
+pub fn find_path(
+    start: (i32, i32),
+    goal: (i32, i32),
+    max_cells: usize,
+    cost: impl Fn(i32, i32) -> Option<u32>,
+) -> Option<Vec<(i32, i32)>> {
+    let estimate = |(x, y): (i32, i32)| (x - goal.0).unsigned_abs() + (y - goal.1).unsigned_abs();
+    // the cells to look at, cheapest first. Ties go the same way every time
+    let mut open = BinaryHeap::new();
+    let mut best: HashMap<(i32, i32), u32> = HashMap::new();
+    let mut came_from: HashMap<(i32, i32), (i32, i32)> = HashMap::new();
+    best.insert(start, 0);
+    open.push(Reverse((estimate(start), 0, start)));
+    let mut looked_at = 0;
+    while let Some(Reverse((_, so_far, cell))) = open.pop() {
+        if cell == goal && goal == start {
+            return Some(Vec::new());
+        }
+        if cell == goal {
+            let mut path = vec![goal];
+            let mut cell = goal;
+            while let Some(&previous) = came_from.get(&cell) {
+                if previous == start {
+                    break;
+                }
+                path.push(previous);
+                cell = previous;
+            }
+            path.reverse();
+            return Some(path);
+        }
+        // it was found cheaper after this was queued
+        if best.get(&cell).is_some_and(|&best| so_far > best) {
+            continue;
+        }
+        looked_at += 1;
+        if looked_at > max_cells {
+            return None;
+        }
+        for (dx, dy) in [(0, -1), (1, 0), (0, 1), (-1, 0)] {
+            let next = (cell.0 + dx, cell.1 + dy);
+            let step = match cost(next.0, next.1) {
+                Some(step) => step,
+                None => continue,
+            };
+            let total = so_far + step;
+            if best.get(&next).is_none_or(|&old| total < old) {
+                best.insert(next, total);
+                came_from.insert(next, cell);
+                open.push(Reverse((total + estimate(next), total, next)));
+            }
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn paths_go_around_walls_and_through_doors() {
+        // D is a door, it costs more than the floor but less than going round
+        let map = [
+            "#########", //
+            "#...#...#", //
+            "#...D...#", //
+            "#...#...#", //
+            "#########",
+        ];
+        let cost = |blocked: char| {
+            move |x: i32, y: i32| match map[y as usize].as_bytes()[x as usize] as char {
+                '.' => Some(1),
+                'D' if blocked != 'D' => Some(3),
+                _ => None,
+            }
+        };
+        let path = find_path((1, 2), (7, 2), 100, cost(' ')).unwrap();
+        assert_eq!(path.len(), 6);
+        assert_eq!(path[2], (4, 2));
+        assert_eq!(path.last(), Some(&(7, 2)));
+        // without the door it can't get there
+        assert_eq!(find_path((1, 2), (7, 2), 100, cost('D')), None);
+        assert_eq!(find_path((1, 2), (1, 2), 100, cost(' ')), Some(Vec::new()));
+    }
+
+    #[test]
+    fn enemies_see_react_attack_and_die() {
+        let run = |seed| {
+            let mut rng = Rng::new(seed);
+            let mut enemy = Enemy::new(EnemyKind::Skeleton, 0.0);
+            let mut senses = Senses {
+                sees_player: true,
+                player_distance: 40.0,
+                // behind it, it doesn't see the player there
+                player_angle: PI,
+                health: 40,
+            };
+            let mut intents = Vec::new();
+            for tick in 0..200 {
+                if tick == 5 {
+                    senses.player_angle = 0.2;
+                }
+                intents.push(enemy.think(senses, &mut rng));
+            }
+            (enemy, intents)
+        };
+        let (enemy, intents) = run(7);
+        assert!(intents[..6].iter().all(|&intent| intent == Intent::Stand));
+        assert!(intents.contains(&Intent::Chase));
+        assert!(intents.contains(&Intent::Attack(12)));
+        // the same seed does the same
+        assert_eq!(run(7), (enemy.clone(), intents));
+
+        let mut rng = Rng::new(7);
+        let mut enemy = enemy;
+        let mut senses = Senses {
+            health: 30,
+            ..Senses::default()
+        };
+        enemy.think(senses, &mut rng);
+        assert_eq!(enemy.state, EnemyState::Pain);
+        senses.health = 0;
+        enemy.think(senses, &mut rng);
+        senses.health = 40;
+        enemy.think(senses, &mut rng);
+        assert_eq!(enemy.state, EnemyState::Dead);
+    }
+}
@@ -0,0 +1,355 @@
+// The full screen map. It shares the fog of war with the minimap (only the
+// cells the player has seen are drawn), and adds what the minimap is too
+// small for: door states, items, exits and markers placed by the player.
+// The view can be panned and zoomed. It follows the player until it's
+// panned, and stays put after that until follow_player is set again.
+use crate::doors::{door_index, DoorState, Doors};
+use crate::keys::KeyColor;
+use crate::minimap::{blend_pixel, draw_line, Minimap};
+use crate::pixel_format::PixelFormat;
+
+const MIN_ZOOM: f32 = 2.0;
+const MAX_ZOOM: f32 = 32.0;
+const BACKGROUND: (u8, u8, u8, u8) = (255, 0, 0, 0);
+const FLOOR: (u8, u8, u8, u8) = (255, 40, 40, 40);
+const WALL: (u8, u8, u8, u8) = (255, 200, 200, 200);
+const DOOR_CLOSED: (u8, u8, u8, u8) = (255, 200, 50, 50);
+const DOOR_MOVING: (u8, u8, u8, u8) = (255, 220, 200, 50);
+const DOOR_OPEN: (u8, u8, u8, u8) = (255, 50, 200, 50);
+const PLAYER: (u8, u8, u8, u8) = (255, 255, 255, 0);
+const MARKER: (u8, u8, u8, u8) = (255, 0, 200, 255);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MapIconKind {
+    // RGB color of the key
+    Key(u8, u8, u8),
+    Exit,
+    Item,
+}
+
+// something worth showing on the automap, in world coordinates
+#[derive(Clone, Copy, Debug)]
+pub struct MapIcon {
+    pub x: f32,
+    pub y: f32,
+    pub kind: MapIconKind,
+}
+
+pub struct Automap {
+    pub open: bool,
+    // stop the game while the automap is open
+    pub pause_game: bool,
+    // pixels per map cell
+    pub zoom: f32,
+    pub follow_player: bool,
+    // the world position at the center of the screen
+    pub center_x: f32,
+    pub center_y: f32,
+    // keys, exits and items. They show up once their cell has been seen
+    pub icons: Vec<MapIcon>,
+    markers: Vec<(f32, f32)>,
+}
+
+impl Default for Automap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Automap {
+    pub fn new() -> Self {
+        Automap {
+            open: false,
+            pause_game: true,
+            zoom: 8.0,
+            follow_player: true,
+            center_x: 0.0,
+            center_y: 0.0,
+            icons: Vec::new(),
+            markers: Vec::new(),
+        }
+    }
+
+    // opening the automap always starts on the player
+    pub fn toggle(&mut self) {
+        self.open = !self.open;
+        if self.open {
+            self.follow_player = true;
+        }
+    }
+
+    /**
+     * Moves the view by the given amount of screen pixels.
+     * The view stops following the player.
+     */
+    pub fn pan(&mut self, dx: f32, dy: f32, tile_size: f32) {
+        self.follow_player = false;
+        self.center_x += dx / self.zoom * tile_size;
+        self.center_y += dy / self.zoom * tile_size;
+    }
+
+    pub fn zoom_by(&mut self, factor: f32) {
+        self.zoom = (self.zoom * factor).clamp(MIN_ZOOM, MAX_ZOOM);
+    }
+
+    // the markers are placed at the center of the screen
+    pub fn add_marker(&mut self) {
+        self.markers.push((self.center_x, self.center_y));
+    }
+
+    pub fn remove_last_marker(&mut self) {
+        self.markers.pop();
+    }
+
+    pub fn markers(&self) -> &[(f32, f32)] {
+        &self.markers
+    }
+
+    // called every frame, so the view can follow the player
+    pub fn update(&mut self, player_x: f32, player_y: f32) {
+        if self.follow_player {
+            self.center_x = player_x;
+            self.center_y = player_y;
+        }
+    }
+
+    /**
+     * Draws the automap over the entire screen. `cell(x, y)` returns the
+     * map value of a cell (only called for cells inside the map), the
+     * minimap knows which cells have been seen, and the doors are colored
+     * by what they are doing.
+     */
+    #[allow(clippy::too_many_arguments)]
+    pub fn draw(
+        &self,
+        canvas: &mut [u32],
+        width: usize,
+        height: usize,
+        format: PixelFormat,
+        cell: impl Fn(i32, i32) -> u32,
+        explored: &Minimap,
+        doors: &Doors,
+        player: (f32, f32, f32),
+        tile_size: f32,
+    ) {
+        canvas.fill(format.pack(BACKGROUND.0, BACKGROUND.1, BACKGROUND.2, BACKGROUND.3));
+        let screen = (0, 0, width as i32, height as i32);
+        let to_screen = |x: f32, y: f32| {
+            (
+                (x - self.center_x) / tile_size * self.zoom + width as f32 / 2.0,
+                (y - self.center_y) / tile_size * self.zoom + height as f32 / 2.0,
+            )
+        };
+        // only the cells that can be on the screen
+        let (first_x, first_y) = self.to_cell(0.0, 0.0, width, height, tile_size);
+        let (last_x, last_y) = self.to_cell(width as f32, height as f32, width, height, tile_size);
+        for cell_y in first_y..=last_y {
+            for cell_x in first_x..=last_x {
+                if !explored.is_explored(cell_x, cell_y) {
+                    continue;
+                }
+                let value = cell(cell_x, cell_y);
+                let color = match value & 0xf {
+                    0 => FLOOR,
+                    2 => match doors.get(door_index(value)).map(|door| door.state) {
+                        Some(DoorState::Open) => DOOR_OPEN,
+                        Some(DoorState::Opening) | Some(DoorState::Closing) => DOOR_MOVING,
+                        // locked doors show the color of their key
+                        _ => KeyColor::lock_of(value).map_or(DOOR_CLOSED, KeyColor::color),
+                    },
+                    _ => WALL,
+                };
+                let (left, top) = to_screen(cell_x as f32 * tile_size, cell_y as f32 * tile_size);
+                let (right, bottom) = to_screen(
+                    (cell_x + 1) as f32 * tile_size,
+                    (cell_y + 1) as f32 * tile_size,
+                );
+                fill_rectangle(
+                    canvas, width, height, format, left, top, right, bottom, color,
+                );
+            }
+        }
+
+        for icon in self.icons.iter() {
+            let cell_x = (icon.x / tile_size).floor() as i32;
+            let cell_y = (icon.y / tile_size).floor() as i32;
+            if !explored.is_explored(cell_x, cell_y) {
+                continue;
+            }
+            let color = match icon.kind {
+                MapIconKind::Key(red, green, blue) => (255, red, green, blue),
+                MapIconKind::Exit => (255, 0, 255, 255),
+                MapIconKind::Item => (255, 255, 160, 0),
+            };
+            let (x, y) = to_screen(icon.x, icon.y);
+            let size = (self.zoom / 4.0).max(1.5);
+            fill_rectangle(
+                canvas,
+                width,
+                height,
+                format,
+                x - size,
+                y - size,
+                x + size,
+                y + size,
+                color,
+            );
+        }
+
+        for &(marker_x, marker_y) in self.markers.iter() {
+            let (x, y) = to_screen(marker_x, marker_y);
+            draw_line(
+                canvas,
+                width,
+                height,
+                format,
+                screen,
+                (x - 2.0, y - 2.0),
+                (x + 2.0, y + 2.0),
+                MARKER,
+            );
+            draw_line(
+                canvas,
+                width,
+                height,
+                format,
+                screen,
+                (x - 2.0, y + 2.0),
+                (x + 2.0, y - 2.0),
+                MARKER,
+            );
+        }
+
+        // the player arrow
+        let (player_x, player_y, angle) = player;
+        let (x, y) = to_screen(player_x, player_y);
+        let length = (self.zoom * 0.6).max(4.0);
+        let point = |angle: f32, length: f32| (x + angle.cos() * length, y + angle.sin() * length);
+        let tip = point(angle, length);
+        let tail = point(angle + std::f32::consts::PI, length);
+        let left = point(angle - 2.5, length * 0.8);
+        let right = point(angle + 2.5, length * 0.8);
+        draw_line(canvas, width, height, format, screen, tail, tip, PLAYER);
+        draw_line(canvas, width, height, format, screen, left, tip, PLAYER);
+        draw_line(canvas, width, height, format, screen, right, tip, PLAYER);
+
+        // where a marker would go, when it's not on the player
+        if !self.follow_player {
+            let (x, y) = (width as f32 / 2.0, height as f32 / 2.0);
+            draw_line(
+                canvas,
+                width,
+                height,
+                format,
+                screen,
+                (x - 3.0, y),
+                (x + 3.0, y),
+                MARKER,
+            );
+            draw_line(
+                canvas,
+                width,
+                height,
+                format,
+                screen,
+                (x, y - 3.0),
+                (x, y + 3.0),
+                MARKER,
+            );
+        }
+    }
+
+    // the map cell under a screen position
+    fn to_cell(&self, x: f32, y: f32, width: usize, height: usize, tile_size: f32) -> (i32, i32) {
+        (
+            (((x - width as f32 / 2.0) / self.zoom * tile_size + self.center_x) / tile_size).floor()
+                as i32,
+            (((y - height as f32 / 2.0) / self.zoom * tile_size + self.center_y) / tile_size)
+                .floor() as i32,
+        )
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn fill_rectangle(
+    canvas: &mut [u32],
+    width: usize,
+    height: usize,
+    format: PixelFormat,
+    left: f32,
+    top: f32,
+    right: f32,
+    bottom: f32,
+    color: (u8, u8, u8, u8),
+) {
+    let left = (left.floor() as i32).max(0);
+    let top = (top.floor() as i32).max(0);
+    let right = (right.floor() as i32).min(width as i32);
+    let bottom = (bottom.floor() as i32).min(height as i32);
+    for y in top..bottom {
+        for x in left..right {
+            blend_pixel(canvas, y as usize * width + x as usize, color, format);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn panning_detaches_the_view_and_places_markers() {
+        let mut automap = Automap::new();
+        automap.toggle();
+        automap.update(100.0, 50.0);
+        automap.add_marker();
+        // 8 pixels at 8 pixels per cell is one cell
+        automap.pan(8.0, -8.0, 64.0);
+        automap.update(500.0, 500.0);
+        automap.add_marker();
+        assert_eq!(automap.markers(), &[(100.0, 50.0), (164.0, -14.0)]);
+        // opening it again goes back to the player
+        automap.toggle();
+        automap.toggle();
+        automap.update(500.0, 500.0);
+        assert_eq!((automap.center_x, automap.center_y), (500.0, 500.0));
+    }
+
+    #[test]
+    fn only_explored_cells_and_door_states_are_drawn() {
+        // a wall, a closed door and an open door in a row
+        let map = |x: i32, _y: i32| match x {
+            0 => 1,
+            1 => 2,
+            2 => 2 | (1 << 8),
+            _ => 0,
+        };
+        let mut explored = Minimap::new(4, 1);
+        explored.reveal_ray(0.0, 32.0, 191.0, 32.0, (2, 0), false, 64.0);
+        let mut automap = Automap::new();
+        automap.zoom = 10.0;
+        automap.update(128.0, 32.0);
+        let mut doors = Doors::new(2);
+        doors.get_mut(1).unwrap().set_open(true);
+        let mut canvas = vec![0; 40 * 10];
+        automap.draw(
+            &mut canvas,
+            40,
+            10,
+            PixelFormat::Bgra8,
+            map,
+            &explored,
+            &doors,
+            (128.0, 32.0, 0.0),
+            64.0,
+        );
+        let at = |x: usize| canvas[5 * 40 + x];
+        let color = |c: (u8, u8, u8, u8)| PixelFormat::Bgra8.pack(c.0, c.1, c.2, c.3);
+        assert_eq!(at(1), color(WALL));
+        assert_eq!(at(11), color(DOOR_CLOSED));
+        // the player arrow is in the middle of the open door
+        assert_eq!(at(29), color(DOOR_OPEN));
+        // never seen
+        assert_eq!(at(35), color(BACKGROUND));
+    }
+}
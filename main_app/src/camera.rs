@@ -0,0 +1,164 @@
+// A point of view in the world and the part of the screen it's drawn into.
+// The player's camera is built from the player every frame, other cameras
+// (split screen, security cameras, rear view mirrors) are kept as views and
+// drawn on top of it. Every camera is drawn the same way, with its own
+// z-buffer, so they all see the same map, sprites and decals.
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Camera {
+    // position in world units
+    pub x: f32,
+    pub y: f32,
+    // radians, 0 looks to the right (east) and it grows clockwise (down)
+    pub angle: f32,
+    // eye height above the floor, in world units
+    pub height: f32,
+    // moves the horizon up (negative) or down (positive).
+    // It's a fraction of the viewport height, so 0.5 puts the horizon
+    // on the bottom edge no matter how big the viewport is
+    pub pitch: f32,
+    // horizontal field of view in radians
+    pub fov: f32,
+}
+
+impl Camera {
+    // 60 degrees of FOV at the height of the player
+    pub fn new(x: f32, y: f32, angle: f32) -> Self {
+        Camera {
+            x,
+            y,
+            angle,
+            height: 32.0,
+            pitch: 0.0,
+            fov: std::f32::consts::PI / 3.0,
+        }
+    }
+
+    /**
+     * How far the projection plane is from the eye, in pixels, so that
+     * `width` pixels cover the field of view. 277 for 320 pixels at 60 degrees
+     */
+    pub fn projection_distance(&self, width: u32) -> f32 {
+        width as f32 / 2.0 / (self.fov / 2.0).tan()
+    }
+
+    /**
+     * The angle of the ray of a column, relative to the camera angle.
+     * Columns are spread over the projection plane (not in equal angles)
+     * so straight walls stay straight.
+     */
+    #[inline]
+    pub fn column_angle(&self, column: f32, width: u32) -> f32 {
+        ((column + 0.5 - width as f32 / 2.0) / self.projection_distance(width)).atan()
+    }
+
+    // the screen row of the horizon in a viewport of this height
+    #[inline]
+    pub fn horizon(&self, height: u32) -> f32 {
+        height as f32 * (0.5 + self.pitch)
+    }
+}
+
+// a rectangle of the target buffer, in pixels
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Viewport {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Viewport {
+    pub fn new(x: u32, y: u32, width: u32, height: u32) -> Self {
+        Viewport {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    // the whole target
+    pub fn full(width: u32, height: u32) -> Self {
+        Viewport::new(0, 0, width, height)
+    }
+
+    /**
+     * Returns a copy that fits inside a target of the given size.
+     * It can end up empty.
+     */
+    pub fn clip(&self, width: u32, height: u32) -> Self {
+        let x = self.x.min(width);
+        let y = self.y.min(height);
+        Viewport::new(x, y, self.width.min(width - x), self.height.min(height - y))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.width == 0 || self.height == 0
+    }
+
+    // true if the target pixel is inside
+    pub fn contains(&self, x: u32, y: u32) -> bool {
+        x >= self.x && y >= self.y && x < self.x + self.width && y < self.y + self.height
+    }
+}
+
+// an extra camera drawn over the player's view
+#[derive(Clone, Copy, Debug)]
+pub struct View {
+    pub camera: Camera,
+    pub viewport: Viewport,
+    // Some(angle) moves the camera with the player every frame, looking
+    // at the player's angle plus this one. Some(PI) is a rear view
+    pub follow_player: Option<f32>,
+    // flips the image horizontally, like a mirror
+    pub mirrored: bool,
+}
+
+impl View {
+    // a camera that stays where it is, for split screen or security cameras
+    pub fn new(camera: Camera, viewport: Viewport) -> Self {
+        View {
+            camera,
+            viewport,
+            follow_player: None,
+            mirrored: false,
+        }
+    }
+
+    // what is behind the player, flipped like a mirror
+    pub fn rear_view_mirror(viewport: Viewport) -> Self {
+        View {
+            camera: Camera::new(0.0, 0.0, 0.0),
+            viewport,
+            follow_player: Some(std::f32::consts::PI),
+            mirrored: true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn columns_cover_the_field_of_view() {
+        let camera = Camera::new(0.0, 0.0, 0.0);
+        assert!((camera.projection_distance(320) - 277.1).abs() < 0.1);
+        // the edges of the first and last columns are half the FOV away
+        let left = camera.column_angle(-0.5, 320);
+        let right = camera.column_angle(319.5, 320);
+        assert!((left + camera.fov / 2.0).abs() < 1e-5);
+        assert!((right - camera.fov / 2.0).abs() < 1e-5);
+        // the same camera in a smaller viewport sees the same angles
+        assert!((camera.column_angle(-0.5, 100) - left).abs() < 1e-5);
+    }
+
+    #[test]
+    fn viewports_are_clipped_to_the_target() {
+        let viewport = Viewport::new(200, 150, 200, 100).clip(320, 200);
+        assert_eq!(viewport, Viewport::new(200, 150, 120, 50));
+        assert!(viewport.contains(319, 199) && !viewport.contains(199, 150));
+        assert!(Viewport::new(400, 0, 10, 10).clip(320, 200).is_empty());
+    }
+}
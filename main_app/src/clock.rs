@@ -0,0 +1,141 @@
+// The simulation runs in fixed steps (ticks), no matter how often frames
+// are drawn. Every frame adds the time that passed to an accumulator and
+// runs as many ticks as fit in it, the rest waits for the next frame.
+// The time comes from a Clock, so tests can move it by hand and get the
+// same result on every machine.
+use instant::Instant;
+use std::cell::Cell;
+use std::rc::Rc;
+
+pub trait Clock {
+    // seconds since some point in the past. It never goes back
+    fn now(&self) -> f64;
+}
+
+// the real time
+pub struct SystemClock {
+    start: Instant,
+}
+
+impl Default for SystemClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SystemClock {
+    pub fn new() -> Self {
+        SystemClock {
+            start: Instant::now(),
+        }
+    }
+}
+
+impl Clock for SystemClock {
+    fn now(&self) -> f64 {
+        self.start.elapsed().as_secs_f64()
+    }
+}
+
+/**
+ * A clock that only moves when it's told to, for tests and tools.
+ * Clones share the same time, so one can be given to the game
+ * and the other one kept to move it.
+ */
+#[derive(Clone, Default)]
+pub struct ManualClock {
+    time: Rc<Cell<f64>>,
+}
+
+impl ManualClock {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn advance(&self, seconds: f64) {
+        self.time.set(self.time.get() + seconds);
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> f64 {
+        self.time.get()
+    }
+}
+
+pub struct FixedTimestep {
+    // seconds per tick
+    pub tick: f64,
+    // The most ticks run in a single frame. After a long pause (or on a
+    // machine too slow to keep up) the extra time is dropped instead of
+    // freezing the game while it catches up
+    pub max_ticks_per_frame: u32,
+    accumulator: f64,
+    last_time: Option<f64>,
+}
+
+impl FixedTimestep {
+    pub fn new(ticks_per_second: f64) -> Self {
+        FixedTimestep {
+            tick: 1.0 / ticks_per_second,
+            max_ticks_per_frame: 8,
+            accumulator: 0.0,
+            last_time: None,
+        }
+    }
+
+    /**
+     * Adds the time since the previous call and returns how many ticks
+     * have to run now. The first call only starts counting.
+     */
+    pub fn advance(&mut self, now: f64) -> u32 {
+        if let Some(last_time) = self.last_time {
+            self.accumulator += (now - last_time).max(0.0);
+        }
+        self.last_time = Some(now);
+        let mut ticks = 0;
+        while self.accumulator >= self.tick {
+            self.accumulator -= self.tick;
+            ticks += 1;
+            if ticks == self.max_ticks_per_frame {
+                self.accumulator %= self.tick;
+                break;
+            }
+        }
+        ticks
+    }
+
+    /**
+     * How far the time is between the last tick and the next one, from 0
+     * to 1. Used to draw positions in between ticks.
+     */
+    pub fn alpha(&self) -> f32 {
+        (self.accumulator / self.tick).min(1.0) as f32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ticks_only_depend_on_the_time() {
+        let mut timestep = FixedTimestep::new(4.0);
+        assert_eq!(timestep.advance(0.0), 0);
+        assert_eq!(timestep.advance(0.625), 2);
+        assert_eq!(timestep.alpha(), 0.5);
+        assert_eq!(timestep.advance(0.75), 1);
+        assert_eq!(timestep.alpha(), 0.0);
+        // a long pause doesn't run a lot of ticks to catch up
+        assert_eq!(timestep.advance(100.0), 8);
+        assert_eq!(timestep.advance(100.125), 0);
+    }
+
+    #[test]
+    fn manual_clocks_share_their_time() {
+        let clock = ManualClock::new();
+        let game_clock: Box<dyn Clock> = Box::new(clock.clone());
+        clock.advance(1.5);
+        assert_eq!(game_clock.now(), 1.5);
+    }
+}
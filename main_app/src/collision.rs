@@ -0,0 +1,425 @@
+// Collision of circles (the player, later the actors) against the map.
+// Every cell has a shape: nothing, the whole cell, or a thin wall inside
+// it (a door). A move is split in steps shorter than the radius, so fast
+// movement can't jump over a thin wall, and after every step the circle
+// is pushed out of whatever it overlaps. Pushing it out along the normal
+// of the surface is what makes it slide along walls instead of stopping.
+// Objects standing on the floor (bodies) block the same way walls do, and
+// a spatial hash finds the ones close to a move so hundreds of them don't
+// have to be checked every time.
+use std::collections::HashMap;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CellShape {
+    Empty,
+    // the whole cell
+    Solid,
+    // a thin wall inside the cell, from (x0, y0) to (x1, y1) in world units
+    Segment(f32, f32, f32, f32),
+    // two thin walls (like the halves of a split door)
+    Segments((f32, f32, f32, f32), (f32, f32, f32, f32)),
+}
+
+// the shape of an object on the floor, centered on its position
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BodyShape {
+    Circle(f32),
+    // a square aligned to the map, with this half side
+    Square(f32),
+}
+
+impl BodyShape {
+    // how far from the center the shape reaches
+    pub fn extent(&self) -> f32 {
+        match *self {
+            BodyShape::Circle(radius) => radius,
+            BodyShape::Square(half) => half * std::f32::consts::SQRT_2,
+        }
+    }
+}
+
+// something that blocks circles, see move_circle
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Body {
+    pub x: f32,
+    pub y: f32,
+    pub shape: BodyShape,
+}
+
+impl Body {
+    pub fn new(x: f32, y: f32, shape: BodyShape) -> Self {
+        Body { x, y, shape }
+    }
+
+    // the point of the body closest to x, y. For a circle it's on its edge
+    fn closest_point(&self, x: f32, y: f32) -> (f32, f32) {
+        match self.shape {
+            BodyShape::Circle(radius) => {
+                let (away_x, away_y) = (x - self.x, y - self.y);
+                let distance = away_x.hypot(away_y);
+                if distance <= radius {
+                    (x, y)
+                } else {
+                    (
+                        self.x + away_x / distance * radius,
+                        self.y + away_y / distance * radius,
+                    )
+                }
+            }
+            BodyShape::Square(half) => (
+                x.clamp(self.x - half, self.x + half),
+                y.clamp(self.y - half, self.y + half),
+            ),
+        }
+    }
+
+    // true if a circle overlaps the body. Touching doesn't count
+    pub fn overlaps(&self, x: f32, y: f32, radius: f32) -> bool {
+        let (closest_x, closest_y) = self.closest_point(x, y);
+        (x - closest_x).hypot(y - closest_y) < radius - 0.001
+    }
+}
+
+/**
+ * Finds the objects close to a point without looking at all of them.
+ * Every object is stored in all the buckets its shape reaches, so a query
+ * only has to look at the buckets the query reaches.
+ */
+pub struct SpatialHash<T = usize> {
+    bucket_size: f32,
+    buckets: HashMap<(i32, i32), Vec<T>>,
+}
+
+impl<T: Copy + Ord> SpatialHash<T> {
+    pub fn new(bucket_size: f32) -> Self {
+        SpatialHash {
+            bucket_size,
+            buckets: HashMap::new(),
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.buckets.clear();
+    }
+
+    // stores an object that reaches `extent` units around x, y
+    pub fn insert(&mut self, object: T, x: f32, y: f32, extent: f32) {
+        for bucket in self.buckets_around(x, y, extent) {
+            self.buckets.entry(bucket).or_default().push(object);
+        }
+    }
+
+    /**
+     * The objects that may reach the area `extent` units around x, y,
+     * without repeating them and sorted. Some of
+     * them may be a bit farther away, the caller checks the real shapes.
+     */
+    pub fn query(&self, x: f32, y: f32, extent: f32) -> Vec<T> {
+        let mut found = Vec::new();
+        for bucket in self.buckets_around(x, y, extent) {
+            if let Some(objects) = self.buckets.get(&bucket) {
+                found.extend_from_slice(objects);
+            }
+        }
+        found.sort_unstable();
+        found.dedup();
+        found
+    }
+
+    fn buckets_around(&self, x: f32, y: f32, extent: f32) -> impl Iterator<Item = (i32, i32)> {
+        let first_x = ((x - extent) / self.bucket_size).floor() as i32;
+        let last_x = ((x + extent) / self.bucket_size).floor() as i32;
+        let first_y = ((y - extent) / self.bucket_size).floor() as i32;
+        let last_y = ((y + extent) / self.bucket_size).floor() as i32;
+        (first_y..=last_y).flat_map(move |y| (first_x..=last_x).map(move |x| (x, y)))
+    }
+}
+
+// the longest step, as a fraction of the radius
+const SUBSTEP: f32 = 0.5;
+// Smaller circles move as if they were this big. A radius of 0 would take
+// infinite steps
+const MIN_RADIUS: f32 = 0.5;
+// Overlaps are resolved a few times per step, because getting out of one
+// shape can push the circle into another one (in corners)
+const ITERATIONS: usize = 4;
+
+/**
+ * Moves a circle centered at x, y by dx, dy and returns where it ends up.
+ * `shape_at(cell_x, cell_y)` returns the shape of a cell, it must be
+ * Solid outside of the map. The bodies block it too, only the ones
+ * close to the move need to be there. The radius is at least MIN_RADIUS.
+ */
+#[allow(clippy::too_many_arguments)]
+pub fn move_circle(
+    x: f32,
+    y: f32,
+    dx: f32,
+    dy: f32,
+    radius: f32,
+    tile_size: f32,
+    bodies: &[Body],
+    shape_at: impl Fn(i32, i32) -> CellShape,
+) -> (f32, f32) {
+    let radius = radius.max(MIN_RADIUS);
+    let steps = (dx.hypot(dy) / (radius * SUBSTEP)).ceil().max(1.0);
+    let (step_x, step_y) = (dx / steps, dy / steps);
+    let (mut x, mut y) = (x, y);
+    for _ in 0..steps as usize {
+        let from = (x, y);
+        x += step_x;
+        y += step_y;
+        for _ in 0..ITERATIONS {
+            // Only the deepest overlap is resolved at a time. Resolving every
+            // cell in order would catch the corners where two walls meet,
+            // and push the circle sideways along a flat wall
+            let mut deepest: Option<(f32, f32)> = None;
+            // only the cells the circle can touch
+            let first_x = ((x - radius) / tile_size).floor() as i32;
+            let last_x = ((x + radius) / tile_size).floor() as i32;
+            let first_y = ((y - radius) / tile_size).floor() as i32;
+            let last_y = ((y + radius) / tile_size).floor() as i32;
+            for cell_y in first_y..=last_y {
+                for cell_x in first_x..=last_x {
+                    let shape = shape_at(cell_x, cell_y);
+                    if let Some(closest) = closest_point(shape, cell_x, cell_y, tile_size, x, y) {
+                        if let Some(push) = separation(closest, (x, y), radius, from) {
+                            keep_deepest(&mut deepest, push);
+                        }
+                    }
+                }
+            }
+            for body in bodies {
+                let closest = body.closest_point(x, y);
+                if let Some(push) = separation(closest, (x, y), radius, from) {
+                    keep_deepest(&mut deepest, push);
+                }
+            }
+            match deepest {
+                Some((push_x, push_y)) => {
+                    x += push_x;
+                    y += push_y;
+                }
+                None => break,
+            }
+        }
+    }
+    (x, y)
+}
+
+/**
+ * True if a circle overlaps the shape of a cell. Touching doesn't count,
+ * so a circle pushed out of a wall is not overlapping it anymore.
+ */
+pub fn overlaps(
+    shape: CellShape,
+    cell_x: i32,
+    cell_y: i32,
+    tile_size: f32,
+    x: f32,
+    y: f32,
+    radius: f32,
+) -> bool {
+    match closest_point(shape, cell_x, cell_y, tile_size, x, y) {
+        None => false,
+        Some((closest_x, closest_y)) => (x - closest_x).hypot(y - closest_y) < radius - 0.001,
+    }
+}
+
+// the point of a shape closest to x, y. None for empty cells
+fn closest_point(
+    shape: CellShape,
+    cell_x: i32,
+    cell_y: i32,
+    tile_size: f32,
+    x: f32,
+    y: f32,
+) -> Option<(f32, f32)> {
+    match shape {
+        CellShape::Empty => None,
+        CellShape::Solid => {
+            let left = cell_x as f32 * tile_size;
+            let top = cell_y as f32 * tile_size;
+            Some((
+                x.clamp(left, left + tile_size),
+                y.clamp(top, top + tile_size),
+            ))
+        }
+        CellShape::Segment(x0, y0, x1, y1) => Some(closest_on_segment((x0, y0, x1, y1), x, y)),
+        CellShape::Segments(first, second) => {
+            let first = closest_on_segment(first, x, y);
+            let second = closest_on_segment(second, x, y);
+            if (x - first.0).hypot(y - first.1) <= (x - second.0).hypot(y - second.1) {
+                Some(first)
+            } else {
+                Some(second)
+            }
+        }
+    }
+}
+
+fn closest_on_segment((x0, y0, x1, y1): (f32, f32, f32, f32), x: f32, y: f32) -> (f32, f32) {
+    let (segment_x, segment_y) = (x1 - x0, y1 - y0);
+    let length = segment_x * segment_x + segment_y * segment_y;
+    let t = if length > 0.0 {
+        (((x - x0) * segment_x + (y - y0) * segment_y) / length).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+    (x0 + segment_x * t, y0 + segment_y * t)
+}
+
+fn keep_deepest(deepest: &mut Option<(f32, f32)>, push: (f32, f32)) {
+    if deepest.is_none_or(|(x, y)| push.0.hypot(push.1) > x.hypot(y)) {
+        *deepest = Some(push);
+    }
+}
+
+// How much the circle has to move to stop overlapping a shape, given the
+// point of the shape closest to its center. None if it doesn't overlap it
+fn separation(
+    (closest_x, closest_y): (f32, f32),
+    (x, y): (f32, f32),
+    radius: f32,
+    from: (f32, f32),
+) -> Option<(f32, f32)> {
+    let (away_x, away_y) = (x - closest_x, y - closest_y);
+    let distance = away_x.hypot(away_y);
+    if distance >= radius {
+        return None;
+    }
+    if distance > f32::EPSILON {
+        let push = radius - distance;
+        return Some((away_x / distance * push, away_y / distance * push));
+    }
+    // The center is on the shape (or inside a solid cell), there is no
+    // normal to follow. Steps are short, so going back to where this
+    // step started is always safe
+    Some((from.0 - x, from.1 - y))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TILE: f32 = 64.0;
+
+    // . is empty, # is solid, | is a door along the middle of the cell
+    fn shape_of(map: &[&str], cell_x: i32, cell_y: i32) -> CellShape {
+        let row = match map.get(cell_y as usize) {
+            Some(row) if cell_y >= 0 => row.as_bytes(),
+            _ => return CellShape::Solid,
+        };
+        match row.get(cell_x as usize) {
+            Some(b'.') if cell_x >= 0 => CellShape::Empty,
+            Some(b'|') if cell_x >= 0 => {
+                let middle = (cell_x as f32 + 0.5) * TILE;
+                let top = cell_y as f32 * TILE;
+                CellShape::Segment(middle, top, middle, top + TILE)
+            }
+            _ => CellShape::Solid,
+        }
+    }
+
+    #[test]
+    fn circles_slide_along_walls() {
+        let map = ["#####", "#...#", "#...#", "#####"];
+        // going up and right against the top wall keeps moving right
+        let (x, y) = move_circle(100.0, 80.0, 40.0, -40.0, 8.0, TILE, &[], |x, y| {
+            shape_of(&map, x, y)
+        });
+        assert_eq!((x, y), (140.0, 72.0));
+    }
+
+    #[test]
+    fn fast_circles_do_not_go_through_thin_walls() {
+        let map = ["#####", "#.|.#", "#####"];
+        let (x, _) = move_circle(80.0, 96.0, 300.0, 0.0, 8.0, TILE, &[], |x, y| {
+            shape_of(&map, x, y)
+        });
+        assert_eq!(x, 2.5 * TILE - 8.0);
+    }
+
+    #[test]
+    fn points_move_like_tiny_circles() {
+        let map = ["#####", "#...#", "#####"];
+        let (x, y) = move_circle(80.0, 96.0, 300.0, 0.0, 0.0, TILE, &[], |x, y| {
+            shape_of(&map, x, y)
+        });
+        assert_eq!((x, y), (4.0 * TILE - MIN_RADIUS, 96.0));
+    }
+
+    #[test]
+    fn circles_never_end_up_inside_walls() {
+        let map = [
+            "##########",
+            "#....#...#",
+            "#.##.|.#.#",
+            "#..#...#.#",
+            "##.#.###.#",
+            "#....|...#",
+            "##########",
+        ];
+        let shape = |x, y| shape_of(&map, x, y);
+        // a small deterministic pseudo random generator
+        let mut seed: u32 = 12345;
+        let mut random = move || {
+            seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+            (seed >> 8) as f32 / (1 << 24) as f32
+        };
+        for _ in 0..50 {
+            let (mut x, mut y) = (1.5 * TILE, 1.5 * TILE);
+            let radius = 4.0 + random() * 20.0;
+            for _ in 0..100 {
+                // up to 3 cells in a single move
+                let angle = random() * std::f32::consts::PI * 2.0;
+                let length = random() * TILE * 3.0;
+                (x, y) = move_circle(
+                    x,
+                    y,
+                    angle.cos() * length,
+                    angle.sin() * length,
+                    radius,
+                    TILE,
+                    &[],
+                    shape,
+                );
+                for cell_y in -1..=map.len() as i32 {
+                    for cell_x in -1..=map[0].len() as i32 {
+                        assert!(
+                            !overlaps(shape(cell_x, cell_y), cell_x, cell_y, TILE, x, y, radius),
+                            "({}, {}) radius {} overlaps cell ({}, {})",
+                            x,
+                            y,
+                            radius,
+                            cell_x,
+                            cell_y
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn bodies_block_and_are_found_by_the_spatial_hash() {
+        let map = ["#######", "#.....#", "#.....#", "#######"];
+        let bodies = [
+            Body::new(200.0, 96.0, BodyShape::Circle(16.0)),
+            Body::new(1000.0, 1000.0, BodyShape::Square(16.0)),
+        ];
+        let mut hash = SpatialHash::new(TILE);
+        for (index, body) in bodies.iter().enumerate() {
+            hash.insert(index, body.x, body.y, body.shape.extent());
+        }
+        // a move from 100 to 300 passes over the first one only
+        let near = hash.query(200.0, 96.0, 100.0 + 8.0);
+        assert_eq!(near, vec![0]);
+        let near_bodies: Vec<Body> = near.iter().map(|&index| bodies[index]).collect();
+        let (x, y) = move_circle(100.0, 96.0, 200.0, 0.0, 8.0, TILE, &near_bodies, |x, y| {
+            shape_of(&map, x, y)
+        });
+        assert_eq!((x, y), (200.0 - 16.0 - 8.0, 96.0));
+        assert!(!bodies[0].overlaps(x, y, 8.0) && bodies[0].overlaps(x + 1.0, y, 8.0));
+    }
+}
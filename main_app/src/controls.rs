@@ -0,0 +1,85 @@
+// How the player turns and looks around. The turn keys rotate at a
+// constant speed, the mouse turns by how much it moved since the last
+// frame and moves the horizon up and down (the same thing the look up
+// and look down keys do).
+
+pub struct LookControls {
+    // the mouse turns and looks, and the turn keys strafe instead
+    pub mouse_look: bool,
+    // radians per update while a turn key is held
+    pub turn_speed: f32,
+    // radians per pixel the mouse moves horizontally
+    pub mouse_sensitivity_x: f32,
+    // horizon pixels per pixel the mouse moves vertically
+    pub mouse_sensitivity_y: f32,
+    // moving the mouse up looks down
+    pub invert_y: bool,
+    last_mouse_position: Option<(f32, f32)>,
+}
+
+impl Default for LookControls {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LookControls {
+    pub fn new() -> Self {
+        LookControls {
+            mouse_look: false,
+            // 5 degrees
+            turn_speed: std::f32::consts::PI / 36.0,
+            mouse_sensitivity_x: 0.01,
+            mouse_sensitivity_y: 1.0,
+            invert_y: false,
+            last_mouse_position: None,
+        }
+    }
+
+    /**
+     * How much the mouse moved since the previous call, in pixels.
+     * Nothing moves when the mouse leaves the window and comes back.
+     */
+    pub fn mouse_delta(&mut self, position: Option<(f32, f32)>) -> (f32, f32) {
+        let delta = match (self.last_mouse_position, position) {
+            (Some((last_x, last_y)), Some((x, y))) => (x - last_x, y - last_y),
+            _ => (0.0, 0.0),
+        };
+        self.last_mouse_position = position;
+        delta
+    }
+
+    /**
+     * Converts a mouse movement to (radians to turn, pixels to move the
+     * horizon). Moving the mouse up raises the horizon (looks up)
+     * unless the vertical axis is inverted.
+     */
+    pub fn look_delta(&self, dx: f32, dy: f32) -> (f32, f32) {
+        let pitch = -dy * self.mouse_sensitivity_y;
+        (
+            dx * self.mouse_sensitivity_x,
+            if self.invert_y { -pitch } else { pitch },
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mouse_movement_turns_and_looks() {
+        let mut controls = LookControls::new();
+        assert_eq!(controls.mouse_delta(Some((10.0, 10.0))), (0.0, 0.0));
+        assert_eq!(controls.mouse_delta(Some((15.0, 7.0))), (5.0, -3.0));
+        // left the window
+        assert_eq!(controls.mouse_delta(None), (0.0, 0.0));
+        assert_eq!(controls.mouse_delta(Some((0.0, 0.0))), (0.0, 0.0));
+
+        let (yaw, pitch) = controls.look_delta(5.0, -3.0);
+        assert!((yaw - 0.05).abs() < 1e-6);
+        assert_eq!(pitch, 3.0);
+        controls.invert_y = true;
+        assert_eq!(controls.look_delta(5.0, -3.0).1, -3.0);
+    }
+}
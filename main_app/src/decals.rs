@@ -0,0 +1,177 @@
+// Decals are small images stuck on a wall face: bullet holes, blood, signs.
+// They are placed with the same u/v coordinates the wall texture uses on
+// that face, and the wall renderer draws them over the wall texture, so
+// they get the same shading. There is a maximum amount of them, the oldest
+// one goes away when a new one doesn't fit.
+use std::collections::VecDeque;
+
+pub const BULLET_HOLE_TEXTURE_ID: u32 = 183;
+pub const BLOOD_TEXTURE_ID: u32 = 184;
+
+const DEFAULT_CAPACITY: usize = 64;
+
+// the side of a map cell, named after the direction it faces
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Side {
+    North,
+    South,
+    East,
+    West,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct WallFace {
+    pub cell_x: i32,
+    pub cell_y: i32,
+    pub side: Side,
+}
+
+impl WallFace {
+    /**
+     * The wall u grows with the world coordinates, so on north and east faces
+     * it goes from right to left as seen by the player. Decals are flipped
+     * there, so text reads the right way on every face.
+     */
+    pub fn is_mirrored(&self) -> bool {
+        matches!(self.side, Side::North | Side::East)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Decal {
+    pub face: WallFace,
+    // center of the decal, in the wall texture coordinates (0 to 1)
+    pub u: f32,
+    pub v: f32,
+    // how much of the face it covers, 1 is the whole face
+    pub size: f32,
+    pub texture_id: u32,
+}
+
+impl Decal {
+    /**
+     * Returns the horizontal texture coordinate of the decal for the wall u
+     * of a column, or None if the decal doesn't cover that column.
+     */
+    #[inline]
+    pub fn decal_u(&self, wall_u: f32) -> Option<f32> {
+        let u = (wall_u - self.u) / self.size + 0.5;
+        if !(0.0..1.0).contains(&u) {
+            return None;
+        }
+        Some(if self.face.is_mirrored() { 1.0 - u } else { u })
+    }
+
+    // same as decal_u, for the rows
+    #[inline]
+    pub fn decal_v(&self, wall_v: f32) -> Option<f32> {
+        let v = (wall_v - self.v) / self.size + 0.5;
+        (0.0..1.0).contains(&v).then_some(v)
+    }
+}
+
+pub struct Decals {
+    capacity: usize,
+    decals: VecDeque<Decal>,
+}
+
+impl Default for Decals {
+    fn default() -> Self {
+        Self::new(DEFAULT_CAPACITY)
+    }
+}
+
+impl Decals {
+    pub fn new(capacity: usize) -> Self {
+        Decals {
+            capacity,
+            decals: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    // adds a decal, removing the oldest one when there are too many
+    pub fn add(&mut self, decal: Decal) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.decals.len() == self.capacity {
+            self.decals.pop_front();
+        }
+        self.decals.push_back(decal);
+    }
+
+    pub fn clear(&mut self) {
+        self.decals.clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.decals.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.decals.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Decal> {
+        self.decals.iter()
+    }
+
+    /**
+     * The decals covering a column of a face, oldest first
+     * (so newer decals are drawn on top)
+     */
+    pub fn on_column(&self, face: WallFace, wall_u: f32) -> impl Iterator<Item = &Decal> {
+        self.decals
+            .iter()
+            .filter(move |decal| decal.face == face && decal.decal_u(wall_u).is_some())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decal(cell_x: i32, side: Side, u: f32) -> Decal {
+        Decal {
+            face: WallFace {
+                cell_x,
+                cell_y: 0,
+                side,
+            },
+            u,
+            v: 0.5,
+            size: 0.25,
+            texture_id: BULLET_HOLE_TEXTURE_ID,
+        }
+    }
+
+    #[test]
+    fn oldest_decals_are_evicted() {
+        let mut decals = Decals::new(3);
+        for cell_x in 0..5 {
+            decals.add(decal(cell_x, Side::South, 0.5));
+        }
+        assert_eq!(decals.len(), 3);
+        let cells: Vec<i32> = decals.iter().map(|d| d.face.cell_x).collect();
+        assert_eq!(cells, vec![2, 3, 4]);
+    }
+
+    #[test]
+    fn decals_only_cover_their_face_and_area() {
+        let mut decals = Decals::default();
+        decals.add(decal(1, Side::South, 0.5));
+        decals.add(decal(1, Side::North, 0.5));
+        let south = WallFace {
+            cell_x: 1,
+            cell_y: 0,
+            side: Side::South,
+        };
+        assert_eq!(decals.on_column(south, 0.5).count(), 1);
+        assert_eq!(decals.on_column(south, 0.7).count(), 0);
+        // the left edge of the decal, flipped on the mirrored face
+        let left = decal(1, Side::South, 0.5).decal_u(0.4).unwrap();
+        let mirrored = decal(1, Side::North, 0.5).decal_u(0.4).unwrap();
+        assert!((left - 0.1).abs() < 1e-5 && (mirrored - 0.9).abs() < 1e-5);
+        assert_eq!(decal(1, Side::South, 0.5).decal_v(0.1), None);
+    }
+}
@@ -0,0 +1,324 @@
+// Doors. A door cell of the map has the index of its door in the bits 8
+// to 15, and the door keeps how open it is and what it's doing. Doors open
+// when the player uses them, stay open for a while and close by themselves.
+// A door never closes on something standing in the doorway, it opens again
+// instead.
+// The panel is a thin wall along the middle of the cell, and the kind of
+// door says which part of that line it covers as it opens.
+
+// the door textures made by tools/gen_doors.py
+pub const SPLIT_DOOR_TEXTURE_ID: u32 = 193;
+pub const LIFT_DOOR_TEXTURE_ID: u32 = 194;
+pub const DOOR_JAMB_TEXTURE_ID: u32 = 195;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DoorState {
+    Closed,
+    Opening,
+    Open,
+    Closing,
+}
+
+// How a door opens. Left and right are along the door line, towards
+// smaller and bigger coordinates
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DoorKind {
+    SlideRight,
+    SlideLeft,
+    // two halves that slide away from the middle
+    Split,
+    // goes up into the ceiling, things can go under it when it's high enough
+    Lift,
+    // Turns on a hinge on the left side. It's drawn on the door line,
+    // getting narrower as it opens, which looks close enough from the front
+    Swing,
+}
+
+// a lift door lets things go under it when it's this open
+pub const LIFT_CLEARANCE: f32 = 0.75;
+
+#[derive(Clone, Debug)]
+pub struct Door {
+    pub kind: DoorKind,
+    // None uses the wall texture of the door cell
+    pub texture_id: Option<u32>,
+    // the faces of the walls around the doorway (the jambs).
+    // None leaves the texture of those walls
+    pub jamb_texture_id: Option<u32>,
+    pub state: DoorState,
+    // from 0 (closed) to 1 (open)
+    pub position: f32,
+    // how much the position changes per tick
+    pub speed: f32,
+    // ticks it stays open before closing by itself. None stays open
+    pub auto_close: Option<u32>,
+    // ticks since it finished opening
+    open_ticks: u32,
+}
+
+impl Default for Door {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Door {
+    // a closed door that opens in half a second and closes after 3 seconds
+    pub fn new() -> Self {
+        Door {
+            kind: DoorKind::SlideRight,
+            texture_id: None,
+            jamb_texture_id: None,
+            state: DoorState::Closed,
+            position: 0.0,
+            speed: 1.0 / 32.0,
+            auto_close: Some(180),
+            open_ticks: 0,
+        }
+    }
+
+    // opens or closes it right away, without moving
+    pub fn set_open(&mut self, open: bool) {
+        if open {
+            self.state = DoorState::Open;
+            self.position = 1.0;
+        } else {
+            self.state = DoorState::Closed;
+            self.position = 0.0;
+        }
+        self.open_ticks = 0;
+    }
+
+    // what happens when the player uses it: it opens, or closes if it's open
+    pub fn activate(&mut self) {
+        self.state = match self.state {
+            DoorState::Closed | DoorState::Closing => DoorState::Opening,
+            DoorState::Open | DoorState::Opening => DoorState::Closing,
+        };
+    }
+
+    // starts opening, unless it's already open or opening
+    pub fn open(&mut self) {
+        if matches!(self.state, DoorState::Closed | DoorState::Closing) {
+            self.state = DoorState::Opening;
+        }
+    }
+
+    // starts closing, unless it's already closed or closing
+    pub fn close(&mut self) {
+        if matches!(self.state, DoorState::Open | DoorState::Opening) {
+            self.state = DoorState::Closing;
+        }
+    }
+
+    /**
+     * The texture u of the panel at `offset` along the door line (both
+     * from 0 to 1), or None if that part of the door is open. The texture
+     * moves with the panel. Lift doors cover the whole line until they are
+     * fully open, the renderer draws the part that went up.
+     */
+    pub fn panel_u(&self, offset: f32) -> Option<f32> {
+        let open = self.position;
+        match self.kind {
+            DoorKind::SlideRight => (offset >= open).then_some(offset - open),
+            DoorKind::SlideLeft => (offset < 1.0 - open).then_some(offset + open),
+            DoorKind::Split => {
+                let half = (1.0 - open) / 2.0;
+                if offset < half {
+                    Some(offset + open / 2.0)
+                } else if offset >= 1.0 - half {
+                    Some(offset - open / 2.0)
+                } else {
+                    None
+                }
+            }
+            DoorKind::Lift => (open < 1.0).then_some(offset),
+            DoorKind::Swing => {
+                let width = self.swing_width();
+                (offset < width).then(|| offset / width)
+            }
+        }
+    }
+
+    /**
+     * The parts of the door line that block movement, as (from, to) from
+     * 0 to 1. Split doors have two.
+     */
+    pub fn closed_parts(&self) -> [Option<(f32, f32)>; 2] {
+        let open = self.position;
+        let part = |from: f32, to: f32| (to > from).then_some((from, to));
+        match self.kind {
+            DoorKind::SlideRight => [part(open, 1.0), None],
+            DoorKind::SlideLeft => [part(0.0, 1.0 - open), None],
+            DoorKind::Split => {
+                let half = (1.0 - open) / 2.0;
+                [part(0.0, half), part(1.0 - half, 1.0)]
+            }
+            DoorKind::Lift => [(open < LIFT_CLEARANCE).then_some((0.0, 1.0)), None],
+            DoorKind::Swing => [part(0.0, self.swing_width()), None],
+        }
+    }
+
+    // how much of the door line a swinging door still covers
+    fn swing_width(&self) -> f32 {
+        (self.position * std::f32::consts::FRAC_PI_2).cos().max(0.0)
+    }
+
+    /**
+     * Moves the door one tick. `blocked` is true when something is
+     * standing in the doorway, then the door doesn't close.
+     */
+    pub fn tick(&mut self, blocked: bool) {
+        match self.state {
+            DoorState::Closed => {}
+            DoorState::Opening => {
+                self.position += self.speed;
+                if self.position >= 1.0 {
+                    self.position = 1.0;
+                    self.state = DoorState::Open;
+                    self.open_ticks = 0;
+                }
+            }
+            DoorState::Open => {
+                self.open_ticks = self.open_ticks.saturating_add(1);
+                if !blocked
+                    && self
+                        .auto_close
+                        .is_some_and(|ticks| self.open_ticks >= ticks)
+                {
+                    self.state = DoorState::Closing;
+                }
+            }
+            DoorState::Closing => {
+                if blocked {
+                    self.state = DoorState::Opening;
+                    return;
+                }
+                self.position -= self.speed;
+                if self.position <= 0.0 {
+                    self.position = 0.0;
+                    self.state = DoorState::Closed;
+                }
+            }
+        }
+    }
+}
+
+// the door index stored in a door cell of the map
+pub fn door_index(cell: u32) -> usize {
+    ((cell >> 8) & 0xff) as usize
+}
+
+pub struct Doors {
+    doors: Vec<Door>,
+    // how far the player can be from a door to use it, in world units
+    pub reach: f32,
+}
+
+impl Doors {
+    // `count` closed doors
+    pub fn new(count: usize) -> Self {
+        Doors {
+            doors: vec![Door::new(); count],
+            reach: 64.0,
+        }
+    }
+
+    pub fn get(&self, index: usize) -> Option<&Door> {
+        self.doors.get(index)
+    }
+
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut Door> {
+        self.doors.get_mut(index)
+    }
+
+    // how much a door is open, from 0 to 1. Doors that don't exist are closed
+    pub fn position(&self, index: usize) -> f32 {
+        self.get(index).map_or(0.0, |door| door.position)
+    }
+
+    // puts every door back to closed
+    pub fn reset(&mut self) {
+        for door in self.doors.iter_mut() {
+            *door = Door::new();
+        }
+    }
+
+    // moves every door one tick. `blocked(index)` tells if a doorway is busy
+    pub fn tick(&mut self, blocked: impl Fn(usize) -> bool) {
+        for (index, door) in self.doors.iter_mut().enumerate() {
+            door.tick(blocked(index));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn door_kinds_open_different_parts() {
+        let mut door = Door::new();
+        door.position = 0.5;
+        assert_eq!(door.panel_u(0.25), None);
+        assert_eq!(door.panel_u(0.75), Some(0.25));
+        assert_eq!(door.closed_parts(), [Some((0.5, 1.0)), None]);
+
+        door.kind = DoorKind::SlideLeft;
+        assert_eq!(door.panel_u(0.25), Some(0.75));
+        assert_eq!(door.panel_u(0.75), None);
+
+        door.kind = DoorKind::Split;
+        assert_eq!(door.panel_u(0.1), Some(0.35));
+        assert_eq!(door.panel_u(0.5), None);
+        assert_eq!(door.panel_u(0.9), Some(0.65));
+        assert_eq!(door.closed_parts(), [Some((0.0, 0.25)), Some((0.75, 1.0))]);
+
+        // a half open lift door is drawn everywhere, but can't be walked under
+        door.kind = DoorKind::Lift;
+        assert_eq!(door.panel_u(0.5), Some(0.5));
+        assert_eq!(door.closed_parts(), [Some((0.0, 1.0)), None]);
+        door.position = 0.8;
+        assert_eq!(door.closed_parts(), [None, None]);
+
+        door.kind = DoorKind::Swing;
+        door.position = 1.0;
+        assert_eq!(door.panel_u(0.0), None);
+        assert_eq!(door.closed_parts(), [None, None]);
+    }
+
+    #[test]
+    fn doors_open_wait_and_close() {
+        let mut door = Door::new();
+        door.speed = 0.25;
+        door.auto_close = Some(2);
+        door.activate();
+        for _ in 0..4 {
+            door.tick(false);
+        }
+        assert_eq!((door.state, door.position), (DoorState::Open, 1.0));
+        door.tick(false);
+        assert_eq!(door.state, DoorState::Open);
+        door.tick(false);
+        assert_eq!(door.state, DoorState::Closing);
+        door.tick(false);
+        assert_eq!(door.position, 0.75);
+
+        // something in the doorway opens it again
+        door.tick(true);
+        assert_eq!(door.state, DoorState::Opening);
+        door.tick(true);
+        // and it stays open while it's there
+        for _ in 0..10 {
+            door.tick(true);
+        }
+        assert_eq!((door.state, door.position), (DoorState::Open, 1.0));
+
+        // using an open door closes it
+        door.activate();
+        for _ in 0..4 {
+            door.tick(false);
+        }
+        assert_eq!((door.state, door.position), (DoorState::Closed, 0.0));
+    }
+}
@@ -0,0 +1,318 @@
+// The things in the world that aren't the map: decorations, pickups,
+// actors and projectiles. Every entity has an id that stays the same while
+// it lives and is never given to another one, so the game can hold on to
+// ids (what the player is touching, who fired a projectile) while entities
+// come and go. They're kept in the order they were spawned, so every run
+// goes through them the same way.
+
+use crate::ai::{Enemy, EnemyKind};
+use crate::collision::{Body, BodyShape};
+use crate::keys::KeyColor;
+use std::collections::BTreeMap;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct EntityId(u32);
+
+// what the renderer draws for an entity, a billboard this big in world units
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Sprite {
+    pub texture_id: u32,
+    pub width: u8,
+    pub height: u8,
+    // the texture is a sheet of this many columns and rows of frames of
+    // the same size, (1, 1) is a single image
+    pub sheet: (u32, u32),
+    // the column and the row of the frame drawn
+    pub frame: (u32, u32),
+}
+
+impl Sprite {
+    pub fn new(texture_id: u32, width: u8, height: u8) -> Self {
+        Sprite {
+            texture_id,
+            width,
+            height,
+            sheet: (1, 1),
+            frame: (0, 0),
+        }
+    }
+
+    // the part of the texture with the frame, as (left, top, right, bottom) from 0 to 1
+    pub fn frame_rect(&self) -> (f32, f32, f32, f32) {
+        let (columns, rows) = (self.sheet.0.max(1) as f32, self.sheet.1.max(1) as f32);
+        let (column, row) = (self.frame.0 as f32, self.frame.1 as f32);
+        (
+            column / columns,
+            row / rows,
+            (column + 1.0) / columns,
+            (row + 1.0) / rows,
+        )
+    }
+}
+
+// the sprite goes through the frames, and starts again after the last one
+#[derive(Clone, Debug, PartialEq)]
+pub struct Animation {
+    pub frames: Vec<u32>,
+    pub ticks_per_frame: u32,
+    ticks: u32,
+}
+
+impl Animation {
+    pub fn new(frames: Vec<u32>, ticks_per_frame: u32) -> Self {
+        Animation {
+            frames,
+            ticks_per_frame: ticks_per_frame.max(1),
+            ticks: 0,
+        }
+    }
+
+    pub fn tick(&mut self) {
+        self.ticks = self.ticks.wrapping_add(1);
+    }
+
+    // the texture of the current frame, None without frames
+    pub fn frame(&self) -> Option<u32> {
+        if self.frames.is_empty() {
+            return None;
+        }
+        let frame = (self.ticks / self.ticks_per_frame) as usize % self.frames.len();
+        Some(self.frames[frame])
+    }
+}
+
+// what an entity does by itself every tick, see GameWindow::think_entities
+#[derive(Clone, Debug, PartialEq)]
+pub enum Behaviour {
+    // nothing, it stays where it is
+    Still,
+    // flies dx, dy every tick and hurts the first blocking entity it hits.
+    // It's gone after hitting anything, walls included
+    Projectile {
+        dx: f32,
+        dy: f32,
+        damage: i32,
+        // it doesn't hit the entity that fired it
+        owner: Option<EntityId>,
+    },
+    // looks for the player and goes after it, see ai
+    Enemy(Enemy),
+    // is shown for this many ticks and goes away, impacts and puffs
+    Effect {
+        ticks: u32,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Entity {
+    pub x: f32,
+    pub y: f32,
+    // raise the sprite above the ground, its bottom is at z - height / 2
+    pub z: f32,
+    // None is invisible
+    pub sprite: Option<Sprite>,
+    // changes the texture of the sprite every few ticks
+    pub animation: Option<Animation>,
+    // None can't be touched at all (decoration)
+    pub shape: Option<BodyShape>,
+    // Blocking entities stop the player and the actors. The ones that
+    // don't block (pickups) send a TouchEvent when the player walks in
+    pub blocking: bool,
+    // None can't be hurt. It's taken out of the world at 0, enemies sink
+    // into the floor first
+    pub health: Option<i32>,
+    // a key goes into the inventory when the player touches it, and the
+    // entity is taken out of the world
+    pub key: Option<KeyColor>,
+    pub behaviour: Behaviour,
+}
+
+impl Entity {
+    // an invisible entity that doesn't do anything, fill in the rest
+    pub fn new(x: f32, y: f32, z: f32) -> Self {
+        Entity {
+            x,
+            y,
+            z,
+            sprite: None,
+            animation: None,
+            shape: None,
+            blocking: false,
+            health: None,
+            key: None,
+            behaviour: Behaviour::Still,
+        }
+    }
+
+    // a sprite, give it a shape to make it solid or a pickup
+    pub fn decoration(x: f32, y: f32, z: f32, width: u8, height: u8, texture_id: u32) -> Self {
+        Entity {
+            sprite: Some(Sprite::new(texture_id, width, height)),
+            ..Entity::new(x, y, z)
+        }
+    }
+
+    // a key lying on the floor, for the player to pick up
+    pub fn key(x: f32, y: f32, key: KeyColor) -> Self {
+        Entity {
+            shape: Some(BodyShape::Circle(16.0)),
+            key: Some(key),
+            ..Entity::decoration(x, y, 16.0, 32, 32, key.texture_id())
+        }
+    }
+
+    // an enemy standing on the floor, looking at `facing` (radians)
+    pub fn enemy(x: f32, y: f32, kind: EnemyKind, facing: f32) -> Self {
+        let (width, height) = kind.size();
+        Entity {
+            sprite: Some(Sprite {
+                sheet: (8, 4),
+                ..Sprite::new(kind.texture_id(), width, height)
+            }),
+            shape: Some(BodyShape::Circle(kind.radius())),
+            blocking: true,
+            health: Some(kind.health()),
+            behaviour: Behaviour::Enemy(Enemy::new(kind, facing)),
+            ..Entity::new(x, y, height as f32 / 2.0)
+        }
+    }
+
+    pub fn position(&self) -> (f32, f32) {
+        (self.x, self.y)
+    }
+
+    pub fn body(&self) -> Option<Body> {
+        self.shape.map(|shape| Body::new(self.x, self.y, shape))
+    }
+
+    // the texture drawn now, the frame of the animation if it has one
+    pub fn texture_id(&self) -> Option<u32> {
+        let sprite = self.sprite?;
+        Some(
+            self.animation
+                .as_ref()
+                .and_then(Animation::frame)
+                .unwrap_or(sprite.texture_id),
+        )
+    }
+
+    // takes health away, true if that killed it
+    pub fn hurt(&mut self, damage: i32) -> bool {
+        match self.health.as_mut() {
+            Some(health) if *health > 0 => {
+                *health -= damage;
+                *health <= 0
+            }
+            _ => false,
+        }
+    }
+
+    pub fn is_dead(&self) -> bool {
+        self.health.is_some_and(|health| health <= 0)
+    }
+}
+
+#[derive(Default)]
+pub struct Entities {
+    entities: BTreeMap<EntityId, Entity>,
+    next_id: u32,
+}
+
+impl Entities {
+    pub fn new() -> Self {
+        Entities::default()
+    }
+
+    // puts an entity in the world, with an id nobody had before
+    pub fn spawn(&mut self, entity: Entity) -> EntityId {
+        let id = EntityId(self.next_id);
+        self.next_id += 1;
+        self.entities.insert(id, entity);
+        id
+    }
+
+    // takes an entity out of the world. Its id isn't used again
+    pub fn despawn(&mut self, id: EntityId) -> Option<Entity> {
+        self.entities.remove(&id)
+    }
+
+    pub fn get(&self, id: EntityId) -> Option<&Entity> {
+        self.entities.get(&id)
+    }
+
+    pub fn get_mut(&mut self, id: EntityId) -> Option<&mut Entity> {
+        self.entities.get_mut(&id)
+    }
+
+    // in the order they were spawned
+    pub fn iter(&self) -> impl Iterator<Item = (EntityId, &Entity)> {
+        self.entities.iter().map(|(&id, entity)| (id, entity))
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (EntityId, &mut Entity)> {
+        self.entities.iter_mut().map(|(&id, entity)| (id, entity))
+    }
+
+    // the ids alive now, to go through them while spawning and despawning
+    pub fn ids(&self) -> Vec<EntityId> {
+        self.entities.keys().copied().collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entities.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entities.is_empty()
+    }
+
+    // takes every entity out, for a new level. The ids still aren't reused
+    pub fn clear(&mut self) {
+        self.entities.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ids_stay_the_same_and_are_not_reused() {
+        let mut entities = Entities::new();
+        let first = entities.spawn(Entity::decoration(1.0, 1.0, 0.0, 8, 8, 1));
+        let second = entities.spawn(Entity::key(2.0, 2.0, KeyColor::Red));
+        let third = entities.spawn(Entity::new(3.0, 3.0, 0.0));
+        assert!(entities.despawn(second).is_some());
+        assert!(entities.despawn(second).is_none());
+        let fourth = entities.spawn(Entity::new(4.0, 4.0, 0.0));
+        assert!(fourth != second);
+        assert_eq!(entities.ids(), vec![first, third, fourth]);
+        assert_eq!(entities.get(third).unwrap().position(), (3.0, 3.0));
+        assert_eq!(entities.get(third).unwrap().texture_id(), None);
+
+        entities.clear();
+        assert!(entities.is_empty());
+        assert!(entities.spawn(Entity::new(0.0, 0.0, 0.0)) > fourth);
+    }
+
+    #[test]
+    fn animations_go_round_and_health_runs_out() {
+        let mut entity = Entity {
+            animation: Some(Animation::new(vec![10, 11], 2)),
+            health: Some(5),
+            ..Entity::decoration(0.0, 0.0, 0.0, 8, 8, 1)
+        };
+        let mut frames = Vec::new();
+        for _ in 0..5 {
+            frames.push(entity.texture_id().unwrap());
+            entity.animation.as_mut().unwrap().tick();
+        }
+        assert_eq!(frames, vec![10, 10, 11, 11, 10]);
+
+        assert!(!entity.hurt(3));
+        assert!(entity.hurt(3));
+        assert!(entity.is_dead());
+        // dead things don't die again
+        assert!(!entity.hurt(3));
+    }
+}
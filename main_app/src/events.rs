@@ -0,0 +1,336 @@
+// Level events. Triggers (areas of the floor) and switches (wall faces the
+// player uses) fire named events into the event bus, and the level says
+// what every event does as a list of actions. The bus only turns names into
+// actions, the game applies them during the tick, so a level can be tested
+// without a game.
+
+use crate::decals::WallFace;
+use crate::entities::Entity;
+use std::collections::{HashMap, HashSet, VecDeque};
+
+// events a single dispatch goes through, so events that fire each other
+// don't hang the game. The rest wait for the next tick
+const MAX_EVENTS_PER_DISPATCH: usize = 64;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Action {
+    OpenDoor(usize),
+    CloseDoor(usize),
+    // how bright the level is, see GameWindow::base_light_value
+    SetLight(i32),
+    Spawn(Entity),
+    SetWallTexture {
+        cell_x: i32,
+        cell_y: i32,
+        texture_id: u32,
+    },
+    // moves the player, angle in radians
+    Teleport {
+        x: f32,
+        y: f32,
+        angle: f32,
+    },
+    EndLevel,
+    // shown in the HUD
+    Message(String),
+    // fires another event
+    Fire(String),
+}
+
+#[derive(Default)]
+pub struct EventBus {
+    handlers: HashMap<String, Vec<Action>>,
+    queue: VecDeque<String>,
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        EventBus::default()
+    }
+
+    // adds an action to what an event does
+    pub fn on(&mut self, event: &str, action: Action) {
+        self.handlers
+            .entry(event.to_string())
+            .or_default()
+            .push(action);
+    }
+
+    // the event happens in the next dispatch
+    pub fn fire(&mut self, event: &str) {
+        self.queue.push_back(event.to_string());
+    }
+
+    /**
+     * The actions of the events fired since the last dispatch, in order.
+     * Fire actions are followed here instead of being returned. Events
+     * nobody listens to do nothing.
+     */
+    pub fn dispatch(&mut self) -> Vec<Action> {
+        let mut actions = Vec::new();
+        for _ in 0..MAX_EVENTS_PER_DISPATCH {
+            let event = match self.queue.pop_front() {
+                Some(event) => event,
+                None => break,
+            };
+            for action in self.handlers.get(&event).into_iter().flatten() {
+                match action {
+                    Action::Fire(event) => self.queue.push_back(event.clone()),
+                    action => actions.push(action.clone()),
+                }
+            }
+        }
+        actions
+    }
+
+    // true if the event ends the level, itself or through the events it fires
+    pub fn ends_level(&self, event: &str) -> bool {
+        let mut events = vec![event];
+        let mut seen = HashSet::new();
+        while let Some(event) = events.pop() {
+            if !seen.insert(event) {
+                continue;
+            }
+            for action in self.handlers.get(event).into_iter().flatten() {
+                match action {
+                    Action::EndLevel => return true,
+                    Action::Fire(next) => events.push(next),
+                    _ => {}
+                }
+            }
+        }
+        false
+    }
+
+    // forgets the handlers and the queued events, for a new level
+    pub fn clear(&mut self) {
+        self.handlers.clear();
+        self.queue.clear();
+    }
+}
+
+// a part of the floor, in world units
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Area {
+    Cell(i32, i32),
+    Rectangle {
+        left: f32,
+        top: f32,
+        right: f32,
+        bottom: f32,
+    },
+}
+
+impl Area {
+    pub fn center(&self, tile_size: f32) -> (f32, f32) {
+        match *self {
+            Area::Cell(cell_x, cell_y) => (
+                (cell_x as f32 + 0.5) * tile_size,
+                (cell_y as f32 + 0.5) * tile_size,
+            ),
+            Area::Rectangle {
+                left,
+                top,
+                right,
+                bottom,
+            } => ((left + right) / 2.0, (top + bottom) / 2.0),
+        }
+    }
+
+    pub fn contains(&self, x: f32, y: f32, tile_size: f32) -> bool {
+        match *self {
+            Area::Cell(cell_x, cell_y) => {
+                (x / tile_size).floor() as i32 == cell_x && (y / tile_size).floor() as i32 == cell_y
+            }
+            Area::Rectangle {
+                left,
+                top,
+                right,
+                bottom,
+            } => (left..right).contains(&x) && (top..bottom).contains(&y),
+        }
+    }
+}
+
+// fires an event when the player walks into its area
+#[derive(Clone, Debug)]
+pub struct Trigger {
+    pub area: Area,
+    pub event: String,
+    // fires only the first time
+    pub once: bool,
+    fired: bool,
+    // the player was inside in the last update, it fires again after leaving
+    inside: bool,
+}
+
+impl Trigger {
+    pub fn new(area: Area, event: &str) -> Self {
+        Trigger {
+            area,
+            event: event.to_string(),
+            once: false,
+            fired: false,
+            inside: false,
+        }
+    }
+
+    pub fn once(mut self) -> Self {
+        self.once = true;
+        self
+    }
+
+    // fires the event if the player at x, y just walked in
+    pub fn update(&mut self, x: f32, y: f32, tile_size: f32, bus: &mut EventBus) {
+        let inside = self.area.contains(x, y, tile_size);
+        if inside && !self.inside && !(self.once && self.fired) {
+            bus.fire(&self.event);
+            self.fired = true;
+        }
+        self.inside = inside;
+    }
+}
+
+/**
+ * The triggers and the switches of a level, and what their events do. It's
+ * level data like the map, see GameWindow::set_level_events.
+ */
+#[derive(Clone, Debug, Default)]
+pub struct LevelEvents {
+    pub triggers: Vec<Trigger>,
+    pub switches: Vec<Switch>,
+    // an event and one of its actions, they run in this order
+    pub actions: Vec<(String, Action)>,
+}
+
+// a wall face the player turns on and off with the use key
+#[derive(Clone, Debug)]
+pub struct Switch {
+    pub face: WallFace,
+    pub off_texture_id: u32,
+    pub on_texture_id: u32,
+    pub on: bool,
+    // fired when it's turned on, and off
+    pub on_event: Option<String>,
+    pub off_event: Option<String>,
+}
+
+impl Switch {
+    pub fn new(face: WallFace, off_texture_id: u32, on_texture_id: u32) -> Self {
+        Switch {
+            face,
+            off_texture_id,
+            on_texture_id,
+            on: false,
+            on_event: None,
+            off_event: None,
+        }
+    }
+
+    pub fn texture_id(&self) -> u32 {
+        if self.on {
+            self.on_texture_id
+        } else {
+            self.off_texture_id
+        }
+    }
+
+    // turns it on or off and fires its event
+    pub fn toggle(&mut self, bus: &mut EventBus) {
+        self.on = !self.on;
+        let event = if self.on {
+            &self.on_event
+        } else {
+            &self.off_event
+        };
+        if let Some(event) = event {
+            bus.fire(event);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decals::Side;
+
+    #[test]
+    fn events_turn_into_actions_in_order() {
+        let mut bus = EventBus::new();
+        bus.on("secret", Action::Message("A SECRET".to_string()));
+        bus.on("secret", Action::Fire("lights".to_string()));
+        bus.on("lights", Action::SetLight(100));
+        bus.fire("nothing");
+        bus.fire("secret");
+        assert_eq!(
+            bus.dispatch(),
+            vec![
+                Action::Message("A SECRET".to_string()),
+                Action::SetLight(100)
+            ]
+        );
+        assert!(bus.dispatch().is_empty());
+
+        // an event that fires itself doesn't hang
+        bus.on("loop", Action::Fire("loop".to_string()));
+        bus.fire("loop");
+        assert!(bus.dispatch().is_empty());
+    }
+
+    #[test]
+    fn events_that_end_the_level_are_found() {
+        let mut bus = EventBus::new();
+        bus.on("exit", Action::Fire("warp".to_string()));
+        bus.on("warp", Action::EndLevel);
+        bus.on("loop", Action::Fire("loop".to_string()));
+        assert!(bus.ends_level("exit"));
+        assert!(bus.ends_level("warp"));
+        assert!(!bus.ends_level("loop"));
+        assert!(!bus.ends_level("nothing"));
+        assert_eq!(Area::Cell(1, 2).center(64.0), (96.0, 160.0));
+    }
+
+    #[test]
+    fn triggers_and_switches_fire_events() {
+        let mut bus = EventBus::new();
+        let mut trigger = Trigger::new(Area::Cell(1, 1), "enter");
+        let mut once = Trigger::new(
+            Area::Rectangle {
+                left: 0.0,
+                top: 0.0,
+                right: 32.0,
+                bottom: 32.0,
+            },
+            "corner",
+        )
+        .once();
+        for (x, y) in [
+            (96.0, 96.0),
+            (100.0, 100.0),
+            (16.0, 16.0),
+            (96.0, 96.0),
+            (16.0, 16.0),
+        ] {
+            trigger.update(x, y, 64.0, &mut bus);
+            once.update(x, y, 64.0, &mut bus);
+        }
+        assert_eq!(
+            bus.queue,
+            ["enter", "corner", "enter"].map(String::from).to_vec()
+        );
+
+        let face = WallFace {
+            cell_x: 2,
+            cell_y: 0,
+            side: Side::South,
+        };
+        let mut switch = Switch::new(face, 10, 11);
+        switch.on_event = Some("on".to_string());
+        bus.queue.clear();
+        switch.toggle(&mut bus);
+        assert_eq!(switch.texture_id(), 11);
+        switch.toggle(&mut bus);
+        assert_eq!(switch.texture_id(), 10);
+        assert_eq!(bus.queue, ["on"].map(String::from).to_vec());
+    }
+}
@@ -1,7 +1,34 @@
+use crate::ai::{find_path, Enemy, EnemyKind, EnemyState, Intent, Rng, Senses};
+use crate::automap::{Automap, MapIcon, MapIconKind};
+use crate::camera::{Camera, View, Viewport};
+use crate::clock::{Clock, FixedTimestep, SystemClock};
+use crate::collision::{self, Body, BodyShape, CellShape, SpatialHash};
+use crate::controls::LookControls;
+use crate::decals::{Decal, Decals, Side, WallFace, BLOOD_TEXTURE_ID, BULLET_HOLE_TEXTURE_ID};
+use crate::doors::{
+    door_index, DoorKind, Doors, DOOR_JAMB_TEXTURE_ID, LIFT_DOOR_TEXTURE_ID, SPLIT_DOOR_TEXTURE_ID,
+};
+use crate::entities::{Behaviour, Entities, Entity, EntityId, Sprite};
+use crate::events::{Action, Area, EventBus, LevelEvents, Switch, Trigger};
+use crate::hud::Hud;
+use crate::keys::{Inventory, KeyColor};
 use crate::loader::Assets;
-use minifb::{Key, Window};
+use crate::minimap::{Minimap, MinimapOrientation};
+use crate::physics::{VerticalBody, VerticalInput};
+use crate::pixel_format::PixelFormat;
+use crate::portals::{Portals, RaySegment, Teleporter, TELEPORTER_TEXTURE_ID};
+use crate::postprocess::{
+    ColorGrading, DamageFlash, PaletteShift, PostProcess, Scanlines, ScreenShake, Vignette,
+};
+use crate::pushwalls::{push_distance, PushWalls};
+use crate::screenshot;
+use crate::sound::SoundEvent;
+use crate::texture::TextureFilter;
+use crate::weapons::{AttackKind, WeaponEvent, WeaponInput, Weapons};
+use minifb::{Key, KeyRepeat, MouseMode, Window};
 use std::cmp::Ordering;
 use std::collections::BTreeSet;
+use std::io::Write;
 /**********************************************
 Raycasting implementation in Rust.
 Original port: https://github.com/permadi-com/ray-cast/tree/master/demo/7
@@ -43,6 +70,16 @@ OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SO
 ***********************************************/
 
 const MAX_DOORS: usize = 64;
+// world units between a portal and where the ray goes on after it
+const PORTAL_GAP: f32 = 0.01;
+// how many times per second the simulation runs
+const TICKS_PER_SECOND: f64 = 60.0;
+// the random choices of the enemies start from this, see set_seed
+const DEFAULT_SEED: u32 = 0x5eed_1234;
+// cells the enemies look at to find a way to the player, and the ticks
+// they follow a path before looking for a new one
+const MAX_PATH_CELLS: usize = 400;
+const REPATH_TICKS: u32 = 30;
 
 //*******************************************************************//
 //* Convert arc to radian
@@ -53,12 +90,23 @@ const MAX_DOORS: usize = 64;
 fn arc_to_rad(arc_angle: i32, proj_plane_width: f32) -> f32 {
     //projectionplanewidth (320)        PI/3
     //arc_angle = x
-    return (arc_angle as f32 * std::f32::consts::PI / 3.0) / proj_plane_width;
+    (arc_angle as f32 * std::f32::consts::PI / 3.0) / proj_plane_width
 }
 fn rad_to_arc(rad_angle: f32, proj_plane_width: f32) -> i32 {
     //PI/3.0 (60 degrees of FOV)                320
     //angle                                     x
-    return (rad_angle * proj_plane_width / (std::f32::consts::PI / 3.0)) as i32;
+    (rad_angle * proj_plane_width / (std::f32::consts::PI / 3.0)) as i32
+}
+// Looks up an angle in radians in one of the trigonometric tables.
+// Angles between two arcs get a value in between.
+#[inline]
+fn table_lookup(table: &[f32], angle: f32, proj_plane_width: f32) -> f32 {
+    let arc = angle.rem_euclid(std::f32::consts::PI * 2.0) * proj_plane_width
+        / (std::f32::consts::PI / 3.0);
+    let last = table.len() - 1;
+    let index = (arc.floor() as usize).min(last);
+    let fraction = arc - index as f32;
+    table[index] * (1.0 - fraction) + table[(index + 1).min(last)] * fraction
 }
 /*fn arc_to_deg(arc_angle: i32, proj_plane_width: f32) -> f32 {
     //projectionplanewidth (320)        60
@@ -68,47 +116,14 @@ fn rad_to_arc(rad_angle: f32, proj_plane_width: f32) -> i32 {
 
 #[inline]
 pub fn clamp_i32_to_u8(value: i32) -> u8 {
-    let mut x = value;
-    if x < 0 {
-        x = 0;
-    }
-    if x > 255 {
-        x = 255;
-    }
-    x as u8
-}
-
-#[inline]
-pub fn u8_to_color(alpha: u8, red: u8, green: u8, blue: u8) -> u32 {
-    #[cfg(not(feature = "web"))]
-    {
-        ((alpha as u32) << 24) | ((red as u32) << 16) | ((green as u32) << 8) | (blue as u32)
-    }
-    #[cfg(feature = "web")]
-    {
-        ((alpha as u32) << 24) | ((blue as u32) << 16) | ((green as u32) << 8) | (red as u32)
-    }
-}
-
-#[cfg(feature = "web")]
-macro_rules! argb_to_buffer {
-    // This macro stores argb values in the ABGR buffer
-    ($a:expr, $r:expr, $g:expr, $b:expr, $buffer:expr, $index:expr) => {
-        $buffer[$index] = $r;
-        $buffer[$index + 1] = $g;
-        $buffer[$index + 2] = $b;
-        $buffer[$index + 3] = $a;
-    };
+    value.clamp(0, 255) as u8
 }
 
-#[cfg(not(feature = "web"))]
 macro_rules! argb_to_buffer {
-    // This macro stores argb values in the 0rgb buffer
-    ($a:expr, $r:expr, $g:expr, $b:expr, $buffer:expr, $index:expr) => {
-        $buffer[$index] = $b;
-        $buffer[$index + 1] = $g;
-        $buffer[$index + 2] = $r;
-        $buffer[$index + 3] = $a;
+    // This macro stores argb values in the pixel buffer, one u32 per pixel.
+    // The pixel format takes care of the channel order.
+    ($a:expr, $r:expr, $g:expr, $b:expr, $buffer:expr, $index:expr, $format:expr) => {
+        $buffer[$index] = $format.pack($a, $r, $g, $b);
     };
 }
 
@@ -121,20 +136,47 @@ pub fn clamp_u32_to_u8(value: u32) -> u8 {
     x as u8
 }
 
-#[derive(Clone)]
-pub struct Drawable {
+// a sprite the renderer draws this frame, made from an entity
+#[derive(Clone, Debug)]
+struct Drawable {
     x: f32,
     y: f32,
-    z: f32,            // raise objects above the ground
-    texture_width: u8, //used for scaling
+    z: f32, // raise objects above the ground
     width: u8,
     height: u8,
     texture_id: u32,
+    // the part of the texture drawn, see Sprite::frame_rect
+    frame: (f32, f32, f32, f32),
     real_distance: f32,
     x_distance: f32,
     angle: f32,
 }
 
+impl Drawable {
+    // what the entity looks like now, None if it's invisible
+    fn of(entity: &Entity) -> Option<Self> {
+        let sprite = entity.sprite?;
+        Some(Drawable {
+            x: entity.x,
+            y: entity.y,
+            z: entity.z,
+            width: sprite.width,
+            height: sprite.height,
+            texture_id: entity.texture_id()?,
+            frame: sprite.frame_rect(),
+            real_distance: f32::MAX,
+            x_distance: f32::MAX,
+            angle: 0.0,
+        })
+    }
+}
+
+// the player started touching an entity that doesn't block
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TouchEvent {
+    pub entity: EntityId,
+}
+
 //implement ordering for drawing from farther to closer textures
 // Notice this is an ugly hack to use BTreeSet on a temporary array
 impl Ord for Drawable {
@@ -165,13 +207,33 @@ impl PartialEq for Drawable {
 // yes, we have an f32 element, but it can be ignored
 impl Eq for Drawable {}
 
+// where a ray stopped, see GameWindow::cast_ray
+#[derive(Clone, Copy, Debug)]
+pub struct RayHit {
+    // from the origin of the ray to the hit point, in world units
+    pub distance: f32,
+    pub x: f32,
+    pub y: f32,
+    // the cell and the side of it that was hit
+    pub face: WallFace,
+    // true when it hit a vertical grid line (east or west faces)
+    pub vertical: bool,
+    // the horizontal texture coordinate on the face, from 0 to 1
+    pub u: f32,
+    // the index of the door, when it hit a door
+    pub door: Option<usize>,
+}
+
 pub struct GameWindow {
     width: u32,
     height: u32,
     //framerate: u32,
-    buffer_n: usize,
-    area_size: usize,
-    canvas: Vec<u8>,
+    // the game draws into the back buffer (canvas) while the front buffer
+    // holds the last complete frame. present() swaps them.
+    canvas: Vec<u32>,
+    front_buffer: Vec<u32>,
+    // how the u32 pixels of both buffers are laid out
+    pixel_format: PixelFormat,
     pub assets: Assets,
     // size of tile (wall height)
     tile_size: f32,
@@ -183,30 +245,18 @@ pub struct GameWindow {
 
     // We use FOV of 60 degrees.  So we use this FOV basis of the table, taking into account
     // that we need to cast 320 rays (PROJECTIONPLANEWIDTH) within that 60 degree FOV.
-    angle60: f32,
     // You must make sure these values are integers because we're using loopup tables.
-    angle30: f32,
     //angle15: f32,
-    angle90: f32,
-    angle180: f32,
-    angle270: f32,
     //angle330: f32,
     angle360: f32,
-    angle0: f32,
     //angle5: f32,
     //angle3: u32,
     //angle10: u32,
     //angle45: u32,
     //arc_angle60: i32,
-    arc_angle30: i32,
     //arc_angle15: i32,
-    arc_angle90: i32,
-    arc_angle180: i32,
-    arc_angle270: i32,
     //arc_angle330: i32,
     arc_angle360: i32,
-    arc_angle0: i32,
-    arc_angle5: i32,
     //arc_angle3: i32,
     //arc_angle10: i32,
     //arc_angle45: i32,
@@ -216,49 +266,61 @@ pub struct GameWindow {
     // without adding if conditions in the code for every 0, 90, 180, 270, 360
     // depending on the trigonometric function we need
     f_sin_table: Vec<f32>,
-    f_isin_table: Vec<f32>,
     f_cos_table: Vec<f32>,
-    f_icos_table: Vec<f32>,
-    f_tan_table: Vec<f32>,
-    f_itan_table: Vec<f32>,
-    f_fish_table: Vec<f32>,
-    f_xstep_table: Vec<f32>,
-    f_ystep_table: Vec<f32>,
 
     // player's attributes
     f_player_x: f32,
     f_player_y: f32,
-    f_player_arc: i32,
     f_player_angle: f32,
-    f_player_distance_to_the_projection_plane: f32,
-    f_player_height: f32,
+    // jumping, crouching and falling. The eye is the camera height
+    vertical: VerticalBody,
     f_player_speed: f32,
-    f_player_to_wall_dist: Vec<f32>,
-    drawable_objects: Vec<Drawable>,
+    // the player is a circle this big when it collides with walls
+    f_player_radius: f32,
+    f_player_fov: f32,
+    entities: Entities,
+    // the entities with a shape, rebuilt every tick
+    object_hash: SpatialHash<EntityId>,
+    // the entities the player is touching, to only send a TouchEvent when
+    // it starts
+    touching: BTreeSet<EntityId>,
+    touch_events: Vec<TouchEvent>,
+    // the keys the player picked up
+    inventory: Inventory,
+    // for the frontends to play, see take_sound_events
+    sound_events: Vec<SoundEvent>,
 
     // Half of the screen height
     f_projection_plane_ycenter: f32,
 
-    // the following variables are used to keep the player coordinate in the overhead map
-    f_player_map_x: f32,
-    f_player_map_y: f32,
-    f_minimap_width: f32,
-
     // movement flag
     f_key_up: bool,
     f_key_down: bool,
     f_key_left: bool,
     f_key_right: bool,
+    f_key_strafe_left: bool,
+    f_key_strafe_right: bool,
     f_key_look_up: bool,
     f_key_look_down: bool,
+    f_key_jump: bool,
+    f_key_crouch: bool,
     f_key_fly_up: bool,
     f_key_fly_down: bool,
+    // use was pressed since the last tick
+    f_key_use: bool,
+    // fire and reload were pressed, and the weapon asked for, since the last tick
+    f_key_fire: bool,
+    f_key_reload: bool,
+    weapon_switch: Option<usize>,
 
     // 2 dimensional map
     f_map: [[u32; 20]; 20],
     map_width: f32,
     map_height: f32,
     map_background_img: u32,
+    // the background in the pixel format of the canvas, it's copied as it
+    // is. See convert_background
+    background_pixels: Vec<u32>,
     map_wall_img: [[u32; 20]; 20],
     map_floor_img: [[u32; 20]; 20],
     map_ceiling_img: [[u32; 20]; 20],
@@ -267,54 +329,92 @@ pub struct GameWindow {
     //f_background_image_angle: f32,
     base_light_value: i32,
 
-    // the position goes from 0 (closed) to tile_size(fully open)
-    door_positions: [u8; MAX_DOORS],
-    // this is just for demo purposes
-    door_opening: bool,
+    // turn speed, mouse look and sensitivity
+    controls: LookControls,
+
+    // texture quality, can be changed at runtime
+    mipmapping: bool,
+    texture_filter: TextureFilter,
+
+    // effects applied to the final frame
+    post_process: PostProcess,
+
+    decals: Decals,
+
+    // where the player's camera is drawn, the whole screen by default
+    player_viewport: Viewport,
+    // other cameras, drawn on top of the player's one
+    views: Vec<View>,
+    // the distance to the wall of each column of the viewport being drawn
+    z_buffer: Vec<f32>,
+
+    // text and widgets drawn over the final frame
+    hud: Hud,
+    minimap: Minimap,
+    automap: Automap,
+    // where the time comes from, and the ticks of the simulation
+    clock: Box<dyn Clock>,
+    timestep: FixedTimestep,
+    // when the previous frame started, to measure the frame time
+    last_frame: Option<f64>,
+    // draw the player between the last two ticks, see set_interpolation
+    interpolation: bool,
+    // (x, y, angle) of the player before the last tick
+    previous_pose: (f32, f32, f32),
+
+    doors: Doors,
+    // the secret walls that are moving
+    pushwalls: PushWalls,
+
+    // the level events, and what fires them
+    events: EventBus,
+    triggers: Vec<Trigger>,
+    switches: Vec<Switch>,
+    // an EndLevel action happened
+    level_ended: bool,
+
+    teleporters: Vec<Teleporter>,
+    portals: Portals,
+    // the cell the player was in at the end of the last tick. Teleporters
+    // only send the player when it walks into them
+    player_cell: (i32, i32),
+    player_health: i32,
+    weapons: Weapons,
+    // the random choices of the simulation, from the seed at every init
+    seed: u32,
+    rng: Rng,
 }
 
 impl GameWindow {
+    // draws in the format minifb expects on this platform
     pub fn new(width: usize, height: usize, assets: Assets) -> Self {
-        let buffer_len: usize = (width * height) * 4 * 2; // twice the buffer because I was doing
-                                                          // double buffer at some point
-        let canvas: Vec<u8> = vec![0; buffer_len];
+        GameWindow::with_pixel_format(width, height, assets, PixelFormat::minifb())
+    }
+
+    pub fn with_pixel_format(
+        width: usize,
+        height: usize,
+        assets: Assets,
+        pixel_format: PixelFormat,
+    ) -> Self {
+        let buffer_len: usize = width * height;
+        let canvas: Vec<u32> = vec![0; buffer_len];
+        let front_buffer: Vec<u32> = vec![0; buffer_len];
         let projectionplanewidth = 320.0;
         let projectionplaneheight = 200.0;
         let angle180 = std::f32::consts::PI;
         let angle360 = angle180 * 2.0;
         let angle60 = angle180 / 3.0;
-        let angle30 = angle180 / 6.0;
-        //let angle15 = angle30 / 2;
-        let angle90 = angle180 / 2.0;
-        let angle270 = angle360 - angle90;
-        //let angle330 = angle360 - angle30;
-        let angle0 = 0.0;
-        let angle5 = angle30 / 6.0;
-        //let angle3 = angle30 / 10;
-        //let angle10 = angle5 * 2;
-        //let angle45 = angle15 * 3;
-        let arc_angle60 = rad_to_arc(angle60, projectionplanewidth);
-        let arc_angle30 = rad_to_arc(angle30, projectionplanewidth);
-        //let arc_angle15: f32 = rad_to_arc(angle15, projectionplanewidth);
-        let arc_angle90 = rad_to_arc(angle90, projectionplanewidth);
-        let arc_angle180 = rad_to_arc(angle180, projectionplanewidth);
-        let arc_angle270 = rad_to_arc(angle270, projectionplanewidth);
-        //let arc_angle330 = rad_to_arc(angle330, projectionplanewidth);
         let arc_angle360 = rad_to_arc(angle360, projectionplanewidth);
-        let arc_angle0 = rad_to_arc(angle0, projectionplanewidth);
-        let arc_angle5 = rad_to_arc(angle5, projectionplanewidth);
-        //let arc_angle3 = rad_to_arc(angle3, projectionplanewidth);
-        //let arc_angle10 = rad_to_arc(angle10, projectionplanewidth);
-        //let arc_angle45 = rad_to_arc(angle45, projectionplanewidth);
 
-        let gw = GameWindow {
+        GameWindow {
             width: width as u32,
             height: height as u32,
             //framerate: 24,
-            buffer_n: 0,
-            area_size: (width * height),
             // create the main canvas
             canvas,
+            front_buffer,
+            pixel_format,
             assets,
             // size of tile (wall height)
             tile_size: 64.0,
@@ -326,117 +426,68 @@ impl GameWindow {
 
             // We use FOV of 60 degrees.  So we use this FOV basis of the table, taking into account
             // that we need to cast 320 rays (PROJECTIONPLANEWIDTH) within that 60 degree FOV.
-            angle60,
-            angle30,
             //angle15,
-            angle90,
-            angle180,
-            angle270,
             //angle330,
             angle360,
-            angle0,
             //angle5,
             //angle3,
             //angle10,
             //angle45,
 
             //arc_angle60,
-            arc_angle30,
             //arc_angle15,
-            arc_angle90,
-            arc_angle180,
-            arc_angle270,
             //arc_angle330,
             arc_angle360,
-            arc_angle0,
-            arc_angle5,
             //arc_angle3,
             //arc_angle10,
             //arc_angle45,
 
             // trigonometric tables (the ones with "I" such as ISiTable are "Inverse" table)
             f_sin_table: vec![0.0; angle360 as usize + 1],
-            f_isin_table: vec![0.0; angle360 as usize + 1],
             f_cos_table: vec![0.0; angle360 as usize + 1],
-            f_icos_table: vec![0.0; angle360 as usize + 1],
-            f_tan_table: vec![0.0; angle360 as usize + 1],
-            f_itan_table: vec![0.0; angle360 as usize + 1],
-            f_fish_table: vec![0.0; angle360 as usize + 1],
-            f_xstep_table: vec![0.0; angle360 as usize + 1],
-            f_ystep_table: vec![0.0; angle360 as usize + 1],
 
             // player's attributes
             f_player_x: 100.0,
             f_player_y: 160.0,
-            f_player_arc: arc_angle60,
             f_player_angle: angle60,
-            f_player_distance_to_the_projection_plane: 277.0,
-            f_player_height: 32.0,
+            vertical: VerticalBody::new(),
             f_player_speed: 16.0,
-            f_player_to_wall_dist: vec![f32::MAX; projectionplanewidth as usize + 1],
-            // TODO: I hardcoded a list of drawables here, just to test
-            drawable_objects: vec![
-                Drawable {
-                    x: 620.0,
-                    y: 620.0,
-                    z: 25.0,
-                    texture_width: 32,
-                    width: 32,
-                    height: 50,
-                    texture_id: 163,
-                    real_distance: f32::MAX,
-                    x_distance: f32::MAX,
-                    angle: 0.0,
-                },
-                Drawable {
-                    x: 600.0,
-                    y: 690.0,
-                    z: 25.0,
-                    texture_width: 32,
-                    width: 60,
-                    height: 32,
-                    texture_id: 163,
-                    real_distance: f32::MAX,
-                    x_distance: f32::MAX,
-                    angle: 0.0,
-                },
-                Drawable {
-                    x: 300.0,
-                    y: 1120.0,
-                    z: 25.0,
-                    texture_width: 32,
-                    width: 60,
-                    height: 32,
-                    texture_id: 42,
-                    real_distance: f32::MAX,
-                    x_distance: f32::MAX,
-                    angle: 0.0,
-                },
-            ],
+            f_player_radius: 8.0,
+            f_player_fov: angle60,
+            entities: Entities::new(),
+            object_hash: SpatialHash::new(64.0),
+            touching: BTreeSet::new(),
+            touch_events: Vec::new(),
+            inventory: Inventory::new(),
+            sound_events: Vec::new(),
 
             // Half of the screen height
             f_projection_plane_ycenter: projectionplaneheight / 2.0,
 
-            // the following variables are used to keep the player coordinate in the overhead map
-            f_player_map_x: 0.0,
-            f_player_map_y: 0.0,
-            f_minimap_width: 5.0,
-
             // movement flag
             f_key_up: false,
             f_key_down: false,
             f_key_left: false,
             f_key_right: false,
+            f_key_strafe_left: false,
+            f_key_strafe_right: false,
             f_key_look_up: false,
             f_key_look_down: false,
+            f_key_jump: false,
+            f_key_crouch: false,
             f_key_fly_up: false,
             f_key_fly_down: false,
+            f_key_use: false,
+            f_key_fire: false,
+            f_key_reload: false,
+            weapon_switch: None,
 
             // 2 dimensional map
             f_map: [[0; 20]; 20],
             map_width: 20.0,
             map_height: 20.0,
             map_background_img: 110,
+            background_pixels: Vec::new(),
             map_wall_img: [[0; 20]; 20],
             map_floor_img: [[0; 20]; 20],
             map_ceiling_img: [[0; 20]; 20],
@@ -448,349 +499,614 @@ impl GameWindow {
             //f_background_image_angle: 0.0,
             base_light_value: 180,
             //base_light_value_delta: 1,
-            door_positions: [0; MAX_DOORS],
-            door_opening: true,
-        };
-        return gw;
+            controls: LookControls::new(),
+            mipmapping: true,
+            texture_filter: TextureFilter::Nearest,
+            post_process: GameWindow::default_post_process(),
+            decals: Decals::default(),
+            player_viewport: Viewport::full(width as u32, height as u32),
+            views: Vec::new(),
+            z_buffer: Vec::with_capacity(width),
+            hud: Hud::new(),
+            minimap: Minimap::new(20, 20),
+            automap: Automap::new(),
+            clock: Box::new(SystemClock::new()),
+            // the speeds are per tick
+            timestep: FixedTimestep::new(TICKS_PER_SECOND),
+            last_frame: None,
+            interpolation: true,
+            previous_pose: (100.0, 160.0, angle60),
+            doors: Doors::new(MAX_DOORS),
+            pushwalls: PushWalls::new(),
+            events: EventBus::new(),
+            triggers: Vec::new(),
+            switches: Vec::new(),
+            level_ended: false,
+            teleporters: Vec::new(),
+            portals: Portals::new(),
+            player_cell: (1, 2),
+            player_health: 100,
+            weapons: Weapons::default(),
+            seed: DEFAULT_SEED,
+            rng: Rng::new(DEFAULT_SEED),
+        }
     }
 
-    #[inline]
-    pub fn map_index(&mut self, x: i32, y: i32) -> u32 {
-        (y * self.tile_size as i32 + x) as u32
+    // The flash and the shake do nothing until they are triggered,
+    // the rest are there to be enabled at will
+    fn default_post_process() -> PostProcess {
+        let mut post_process = PostProcess::new();
+        post_process.push(Box::new(ScreenShake::new()), true);
+        post_process.push(Box::new(PaletteShift::new(0, 0, 0)), false);
+        post_process.push(Box::new(ColorGrading::identity(16)), false);
+        post_process.push(Box::new(DamageFlash::new()), true);
+        post_process.push(Box::new(Vignette::new()), false);
+        post_process.push(Box::new(Scanlines::new()), false);
+        post_process
     }
 
-    //*******************************************************************//
-    //* Mostly used to draw in the overhead map. Doesn't have other uses now.
-    //*******************************************************************//
-    fn draw_line(
-        &mut self,
-        start_x: i32,
-        start_y: i32,
-        end_x: i32,
-        end_y: i32,
-        red: u8,
-        green: u8,
-        blue: u8,
-        alpha: u8,
-    ) {
-        let default_increment: i32 = 4; // we access the canvas 4 bytes at a time
-        let x_increment: i32;
-        let y_increment: i32;
-
-        let canvas_len = self.canvas.len() as i32;
-        // calculate Ydistance
-        let mut dy: i32 = end_y - start_y;
-
-        // if moving negative dir (up)
-        // note that we can simplify this function if we can guarantee that
-        // the line will always move in one direction only
-        if dy < 0 {
-            // get abs
-            dy = -dy;
-            // negative movement
-            y_increment = -(self.width as i32) * default_increment;
-        } else {
-            y_increment = self.width as i32 * default_increment;
+    /**
+     * Effects that run on the finished frame. Gameplay code can enable,
+     * disable or trigger them, e.g.:
+     * game.post_process().get_mut::<DamageFlash>().unwrap().trigger(0.8);
+     */
+    pub fn post_process(&mut self) -> &mut PostProcess {
+        &mut self.post_process
+    }
+
+    // health, ammo and messages are set through here
+    pub fn hud(&mut self) -> &mut Hud {
+        &mut self.hud
+    }
+
+    // position, size, zoom, orientation, fog of war and debug rays
+    pub fn minimap(&mut self) -> &mut Minimap {
+        &mut self.minimap
+    }
+
+    // the full screen map. The keys and the exits of the level are added to its icons
+    pub fn automap(&mut self) -> &mut Automap {
+        &mut self.automap
+    }
+
+    // the decals on the walls. Gameplay code can add its own
+    pub fn decals(&mut self) -> &mut Decals {
+        &mut self.decals
+    }
+
+    // the camera of the player, as it's drawn this frame
+    pub fn player_camera(&self) -> Camera {
+        Camera {
+            x: self.f_player_x,
+            y: self.f_player_y,
+            angle: self.f_player_angle,
+            height: self.vertical.eye(),
+            pitch: (self.f_projection_plane_ycenter - self.projectionplaneheight / 2.0)
+                / self.projectionplaneheight,
+            fov: self.f_player_fov,
         }
-        // calc x distance
-        let mut dx: i32 = end_x - start_x;
+    }
 
-        // if negative dir (left)
-        // note that we can simplify this function if we can guarantee that
-        // the line will always move in one direction only
-        if dx < 0 {
-            dx = -dx;
-            x_increment = -(default_increment);
-        } else {
-            x_increment = default_increment;
-        }
-        // deflation
-        let mut error = 0;
-        let mut target_index: i32 =
-            default_increment * self.width as i32 * start_y + default_increment * start_x;
-
-        // if movement in x direction is larger than in y
-        // ie: width > height
-        // we draw each row one by one
-        if dx > dy {
-            // length = width +1
-            let length = dx;
-
-            for _i in 0..length {
-                if target_index < 0 {
-                    break;
-                }
-                if target_index < canvas_len {
-                    argb_to_buffer!(alpha, red, green, blue, self.canvas, target_index as usize);
-                }
+    // the player's camera between the last two ticks, see set_interpolation
+    fn interpolated_camera(&self) -> Camera {
+        let mut camera = self.player_camera();
+        if !self.interpolation {
+            return camera;
+        }
+        let alpha = self.timestep.alpha();
+        let (x, y, angle) = self.previous_pose;
+        camera.x = x + (camera.x - x) * alpha;
+        camera.y = y + (camera.y - y) * alpha;
+        // the short way around, from 350 to 10 degrees goes through 0
+        let half_turn = std::f32::consts::PI;
+        let turn = (camera.angle - angle + half_turn).rem_euclid(self.angle360) - half_turn;
+        camera.angle = angle + turn * alpha;
+        camera
+    }
 
-                // either move left/right
-                target_index = target_index + x_increment;
-                // cumulate error term
-                error += dy;
+    pub fn set_player_fov(&mut self, fov: f32) {
+        self.f_player_fov = fov;
+    }
 
-                // is it time to move y direction (chage row)
-                if error >= dx {
-                    error -= dx;
-                    // move to next row
-                    target_index = target_index + y_increment as i32;
-                }
+    /**
+     * The decorations, pickups, actors and projectiles in the world. The
+     * ones with a sprite are drawn, the ones with a shape collide, see
+     * Entity. Changes are picked up on the next tick.
+     */
+    pub fn entities(&mut self) -> &mut Entities {
+        &mut self.entities
+    }
+
+    /**
+     * The pickups the player walked into since the last call. The
+     * frontends take them after every game_step, they are kept until then.
+     */
+    pub fn take_touch_events(&mut self) -> Vec<TouchEvent> {
+        std::mem::take(&mut self.touch_events)
+    }
+
+    /**
+     * The sounds to play since the last call. The frontends take them
+     * after every game_step, they are kept until then.
+     */
+    pub fn take_sound_events(&mut self) -> Vec<SoundEvent> {
+        std::mem::take(&mut self.sound_events)
+    }
+
+    pub fn inventory(&mut self) -> &mut Inventory {
+        &mut self.inventory
+    }
+
+    // what the level events do, fire events here to run them on the next tick
+    pub fn events(&mut self) -> &mut EventBus {
+        &mut self.events
+    }
+
+    pub fn triggers(&mut self) -> &mut Vec<Trigger> {
+        &mut self.triggers
+    }
+
+    pub fn switches(&mut self) -> &mut Vec<Switch> {
+        &mut self.switches
+    }
+
+    // replaces the triggers, the switches and the events with the ones of a level
+    pub fn set_level_events(&mut self, level: LevelEvents) {
+        self.events.clear();
+        for (event, action) in level.actions {
+            self.events.on(&event, action);
+        }
+        self.triggers = level.triggers;
+        self.switches = level.switches;
+    }
+
+    // the player got to the end of the level
+    pub fn level_ended(&self) -> bool {
+        self.level_ended
+    }
+
+    pub fn teleporters(&mut self) -> &mut Vec<Teleporter> {
+        &mut self.teleporters
+    }
+
+    // the wall faces linked by portals, see cast_ray
+    pub fn portals(&mut self) -> &mut Portals {
+        &mut self.portals
+    }
+
+    pub fn player_health(&self) -> i32 {
+        self.player_health
+    }
+
+    // the weapons the player carries, and the ammo
+    pub fn weapons(&mut self) -> &mut Weapons {
+        &mut self.weapons
+    }
+
+    // takes health from the player, and flashes the screen
+    pub fn hurt_player(&mut self, damage: i32) {
+        self.player_health = (self.player_health - damage).max(0);
+        self.hud.health = Some(self.player_health);
+        if let Some(flash) = self.post_process.get_mut::<DamageFlash>() {
+            flash.trigger(0.6);
+        }
+    }
+
+    /**
+     * The seed of the random choices of the enemies. A game with the same
+     * seed and the same input plays the same way. It starts at the next init.
+     */
+    pub fn set_seed(&mut self, seed: u32) {
+        self.seed = seed;
+    }
+
+    /**
+     * Where the teleporter of the cell at x, y sends what walked into it,
+     * as (x, y, angle). `actor` is false for the player, the teleporters
+     * that aren't for actors only send the player.
+     */
+    pub fn teleport_destination(
+        &self,
+        x: f32,
+        y: f32,
+        actor: bool,
+    ) -> Option<(f32, f32, Option<f32>)> {
+        let cell_x = (x / self.tile_size).floor() as i32;
+        let cell_y = (y / self.tile_size).floor() as i32;
+        self.teleporters
+            .iter()
+            .find(|teleporter| {
+                teleporter.cell_x == cell_x
+                    && teleporter.cell_y == cell_y
+                    && (!actor || teleporter.actors)
+            })
+            .map(|teleporter| {
+                let (x, y) = teleporter.destination_point(self.tile_size);
+                (x, y, teleporter.angle)
+            })
+    }
+
+    // sends the player away if it walked into a teleporter
+    fn use_teleporters(&mut self) {
+        let cell = (
+            (self.f_player_x / self.tile_size).floor() as i32,
+            (self.f_player_y / self.tile_size).floor() as i32,
+        );
+        if cell != self.player_cell {
+            if let Some((x, y, angle)) =
+                self.teleport_destination(self.f_player_x, self.f_player_y, false)
+            {
+                self.set_camera(x, y, angle.unwrap_or(self.f_player_angle));
+                self.sound_events.push(SoundEvent::Teleport);
             }
         }
-        // if movement in y direction is larger than in x
-        // ie: height > width
-        // we draw each column one by one
-        // note that a diagonal line will go here because xdiff = ydiff
-        else
-        //(YDiff>=XDiff)
-        {
-            let length = dy;
+        self.player_cell = (
+            (self.f_player_x / self.tile_size).floor() as i32,
+            (self.f_player_y / self.tile_size).floor() as i32,
+        );
+    }
 
-            for _i in 0..length {
-                if target_index < 0 {
-                    break;
-                }
+    /**
+     * Moves a circle (the player, an actor) by dx, dy colliding with the
+     * map, the blocking objects and the moving pushwalls, and returns
+     * where it ends up.
+     * `ignore` is the entity doing the move, so it doesn't block itself.
+     */
+    pub fn move_body(
+        &self,
+        x: f32,
+        y: f32,
+        dx: f32,
+        dy: f32,
+        radius: f32,
+        ignore: Option<EntityId>,
+    ) -> (f32, f32) {
+        // every entity the circle can touch along the whole move
+        let reach = dx.hypot(dy) / 2.0 + radius;
+        let bodies: Vec<Body> = self
+            .object_hash
+            .query(x + dx / 2.0, y + dy / 2.0, reach)
+            .into_iter()
+            .filter(|&id| Some(id) != ignore)
+            .filter_map(|id| self.entities.get(id))
+            .filter(|entity| entity.blocking)
+            .filter_map(|entity| entity.body())
+            .chain(
+                self.pushwalls
+                    .moving()
+                    .iter()
+                    .map(|wall| wall.body(self.tile_size)),
+            )
+            .collect();
+        collision::move_circle(x, y, dx, dy, radius, self.tile_size, &bodies, |x, y| {
+            self.cell_shape(x, y)
+        })
+    }
 
-                if target_index < canvas_len {
-                    argb_to_buffer!(alpha, red, green, blue, self.canvas, target_index as usize);
-                }
-                target_index = target_index + y_increment as i32;
-                error += dx;
+    fn update_object_hash(&mut self) {
+        self.object_hash.clear();
+        for (id, entity) in self.entities.iter() {
+            if let Some(shape) = entity.shape {
+                self.object_hash
+                    .insert(id, entity.x, entity.y, shape.extent());
+            }
+        }
+    }
+
+    /**
+     * Sends a TouchEvent for every pickup the player started touching.
+     * Keys don't send one, they go into the inventory instead.
+     */
+    fn touch_objects(&mut self) {
+        let (x, y, radius) = (self.f_player_x, self.f_player_y, self.f_player_radius);
+        let touching: BTreeSet<EntityId> = self
+            .object_hash
+            .query(x, y, radius)
+            .into_iter()
+            .filter(|&id| {
+                self.entities.get(id).is_some_and(|entity| {
+                    !entity.blocking
+                        && entity
+                            .body()
+                            .is_some_and(|body| body.overlaps(x, y, radius))
+                })
+            })
+            .collect();
+        let mut keys = Vec::new();
+        for &entity in touching.difference(&self.touching) {
+            if self.entities.get(entity).is_some_and(|e| e.key.is_some()) {
+                keys.push(entity);
+            } else {
+                self.touch_events.push(TouchEvent { entity });
+            }
+        }
+        self.touching = touching;
+        if !keys.is_empty() {
+            self.pick_up_keys(&keys);
+        }
+    }
+
+    /**
+     * Shows the keys lying in the level and the triggers that end it in
+     * the automap. The key icons go away when they're picked up.
+     */
+    fn add_map_icons(&mut self) {
+        self.automap.icons.clear();
+        for (_, entity) in self.entities.iter() {
+            if let Some(key) = entity.key {
+                let (_, red, green, blue) = key.color();
+                self.automap.icons.push(MapIcon {
+                    x: entity.x,
+                    y: entity.y,
+                    kind: MapIconKind::Key(red, green, blue),
+                });
+            }
+        }
+        for trigger in self.triggers.iter() {
+            if self.events.ends_level(&trigger.event) {
+                let (x, y) = trigger.area.center(self.tile_size);
+                self.automap.icons.push(MapIcon {
+                    x,
+                    y,
+                    kind: MapIconKind::Exit,
+                });
+            }
+        }
+    }
 
-                if error >= dy {
-                    error -= dy;
-                    target_index = target_index + x_increment as i32;
+    // takes the key entities out of the world into the inventory
+    fn pick_up_keys(&mut self, entities: &[EntityId]) {
+        for &id in entities {
+            if let Some(entity) = self.entities.despawn(id) {
+                // its icon in the automap goes with it
+                let position = entity.position();
+                self.automap
+                    .icons
+                    .retain(|icon| (icon.x, icon.y) != position);
+                if let Some(key) = entity.key {
+                    self.inventory.add_key(key);
+                    self.hud
+                        .show_message(&format!("PICKED UP THE {} KEY", key.name()), 2.0);
+                    self.sound_events.push(SoundEvent::KeyPickup);
                 }
             }
+            self.touching.remove(&id);
+        }
+        self.update_object_hash();
+    }
+
+    // jumping, crouching, gravity and the fly mode of the player
+    pub fn vertical(&mut self) -> &mut VerticalBody {
+        &mut self.vertical
+    }
+
+    /**
+     * The height of the floor at a point of the world, where the player
+     * lands. This is a stub: the map doesn't have floor heights yet, so it
+     * always returns 0.
+     */
+    pub fn floor_height(&self, _x: f32, _y: f32) -> f32 {
+        0.0
+    }
+
+    pub fn doors(&mut self) -> &mut Doors {
+        &mut self.doors
+    }
+
+    // how close the player can get to walls and doors
+    pub fn set_player_radius(&mut self, radius: f32) {
+        self.f_player_radius = radius;
+    }
+
+    /**
+     * Where the player's camera is drawn. Use half of the screen for split
+     * screen, and put the other player's camera in the other half (see views)
+     */
+    pub fn set_player_viewport(&mut self, viewport: Viewport) {
+        self.player_viewport = viewport;
+    }
+
+    pub fn player_viewport(&self) -> Viewport {
+        self.player_viewport
+    }
+
+    /**
+     * Extra cameras drawn after the player's one, in order, so the last
+     * ones end up on top: the other half of a split screen, security
+     * cameras, rear view mirrors...
+     */
+    pub fn views(&mut self) -> &mut Vec<View> {
+        &mut self.views
+    }
+
+    // the screen position under the crosshair, the center of the player's view
+    fn crosshair(&self) -> (u32, u32) {
+        let viewport = self.player_viewport;
+        (
+            viewport.x + viewport.width / 2,
+            viewport.y + self.player_camera().horizon(viewport.height).max(0.0) as u32,
+        )
+    }
+
+    /**
+     * Puts a decal on the wall the player sees at a screen position,
+     * for example a bullet impact under the crosshair.
+     * Returns false if there is no wall there.
+     */
+    pub fn spawn_decal_at_screen(&mut self, x: u32, y: u32, texture_id: u32, size: f32) -> bool {
+        let camera = self.player_camera();
+        let viewport = self.player_viewport.clip(self.width, self.height);
+        if !viewport.contains(x, y) {
+            return false;
+        }
+        let column_angle = camera.column_angle((x - viewport.x) as f32, viewport.width);
+        let hit = match self.cast_ray(camera.x, camera.y, camera.angle + column_angle) {
+            Some(hit) => hit,
+            None => return false,
+        };
+        let (top_of_wall, bottom_of_wall) =
+            self.project_wall(&camera, viewport, hit.distance * column_angle.cos());
+        let v = ((y - viewport.y) as f32 + 0.5 - top_of_wall) / (bottom_of_wall - top_of_wall);
+        if !(0.0..1.0).contains(&v) {
+            return false;
         }
+        self.decals.add(Decal {
+            face: hit.face,
+            u: hit.u,
+            v,
+            size,
+            texture_id,
+        });
+        true
+    }
+
+    /**
+     * The game doesn't move while the automap is open,
+     * unless the automap is set to not pause it
+     */
+    pub fn is_paused(&self) -> bool {
+        self.automap.open && self.automap.pause_game
     }
+
+    #[inline]
+    pub fn map_index(&mut self, x: i32, y: i32) -> u32 {
+        (y * self.tile_size as i32 + x) as u32
+    }
+
     #[inline]
+    #[allow(clippy::too_many_arguments)]
     fn draw_wall_slice_rectangle_tinted(
         &mut self,
+        viewport: Viewport,
         x_param: f32,
         y_param: f32,
-        _width: f32,
         height: f32,
-        x_offset_param: f32,
+        u: f32,
+        // the rows of the texture the slice covers, from 0 to 1
+        v_range: (f32, f32),
         brightness_level: f32,
         texture_id: u32,
+        face: Option<WallFace>,
+        // rows above this one are not drawn
+        clip_top: f32,
     ) {
         // wait until the texture loads
-        if !self.assets.textures.contains_key(&texture_id) {
-            return;
-        }
-        let f_wall_texture_buffer;
-        match self.assets.textures.get(&texture_id) {
-            None => panic!("Wall not loaded. Dunno what to do"),
-            Some(wall_texture) => f_wall_texture_buffer = wall_texture,
-        }
-        let _dy = height;
+        let f_wall_texture_buffer = match self.assets.textures.get(&texture_id) {
+            None => return,
+            Some(wall_texture) => wall_texture,
+        };
+        // x and y are relative to the viewport
         let x = x_param.floor();
         let y = y_param.floor();
-        let x_offset = x_offset_param.floor();
-        let bytes_per_pixel: u32 = 4;
-        let default_increment = 4;
-
-        let mut source_index = bytes_per_pixel * x_offset as u32;
-
-        let last_source_index =
-            f_wall_texture_buffer.width * f_wall_texture_buffer.height * bytes_per_pixel
-                - bytes_per_pixel;
-
-        //let targetCanvasPixels=self.canvasContext.createImageData(0, 0, width, height);
-        let mut target_index: i32 =
-            ((self.width * default_increment) as f32 * y + (default_increment as f32 * x)) as i32;
-        let canvas_len: usize = self.canvas.len();
-        let mut height_to_draw: f32 = height;
-        // clip bottom
-        if y + height_to_draw > self.height as f32 {
-            height_to_draw = self.height as f32 - y;
-        }
-
-        let mut y_error: f32 = 0.0;
 
         // we need to check this, otherwise, program might crash when trying
         // to fetch the shade if this condition is true (possible if height is 0)
-        if height_to_draw < 0.0 || height_to_draw.is_nan() {
+        if height < 1.0 || height.is_nan() || x < 0.0 || x >= viewport.width as f32 {
             return;
         }
 
-        // we're going to draw the first row, then move down and draw the next row
-        // and so on we can use the original x destination to find out
-        // the x position of the next row
-        // Remeber that the source bitmap is rotated, so the width is actually the
-        // height
-        loop {
-            // if error < actualHeight, this will cause row to be skipped until
-            // this addition sums to scaledHeight
-            // if error > actualHeight, this ill cause row to be drawn repeatedly until
-            // this addition becomes smaller than actualHeight
-            // 1) Think the image height as 100, if percent is >= 100, we'll need to
-            // copy the same pixel over and over while decrementing the percentage.
-            // 2) Similarly, if percent is <100, we skip a pixel while incrementing
-            // and do 1) when the percentage we're adding has reached >=100
-            y_error += height;
-
-            // dereference for faster access (especially useful when the same bit
-            // will be copied more than once)
+        // u goes from 0 to 1 across the texture, and the slice maps the
+        // rows in v_range, so any texture size works here
+        let v_step = (v_range.1 - v_range.0) / height;
+        let level = if self.mipmapping {
+            // one pixel of the slice covers this many texels
+            f_wall_texture_buffer.mip_level_for(
+                f_wall_texture_buffer
+                    .width
+                    .max(f_wall_texture_buffer.height) as f32
+                    * v_step,
+            )
+        } else {
+            0
+        };
 
-            // Cheap shading trick by using brightnessLevel (which doesn't really have to correspond to "brightness")
-            // to alter colors.  You can use logarithmic falloff or linear falloff to produce some interesting effect
-            let f_wall_texture_pixels = &f_wall_texture_buffer.data;
-
-            let red = f_wall_texture_pixels[source_index as usize] as f32 * brightness_level; //.floor();
-            let green = f_wall_texture_pixels[source_index as usize + 1] as f32 * brightness_level; //.floor();
-            let blue = f_wall_texture_pixels[source_index as usize + 2] as f32 * brightness_level; //.floor();
-            let alpha = f_wall_texture_pixels[source_index as usize + 3]; //.floor();
-
-            // while there's a row to draw & not end of drawing area
-            while y_error >= f_wall_texture_buffer.width as f32 && !y_error.is_nan() {
-                y_error -= f_wall_texture_buffer.width as f32;
-                if alpha != 0 && target_index > 0 && (target_index as usize) < canvas_len {
-                    argb_to_buffer!(
-                        alpha,
-                        red.floor() as u8,
-                        green.floor() as u8,
-                        blue.floor() as u8,
-                        self.canvas,
-                        target_index as usize
-                    );
-                }
-                target_index += (default_increment * self.width) as i32;
+        // clip top and bottom, so we only walk the rows that end up on screen
+        let first_row = y.max(clip_top.floor()).max(0.0) as i32;
+        let last_row = (y + height).min(viewport.height as f32) as i32;
+
+        // decals on this column, with their texture, mip level and u
+        let textures = &self.assets.textures;
+        let mipmapping = self.mipmapping;
+        let column_decals: Vec<_> = match face {
+            None => Vec::new(),
+            Some(face) => self
+                .decals
+                .on_column(face, u)
+                .filter_map(|decal| {
+                    let texture = textures.get(&decal.texture_id)?;
+                    let level = if mipmapping {
+                        texture.mip_level_for(
+                            texture.width.max(texture.height) as f32 / (height * decal.size),
+                        )
+                    } else {
+                        0
+                    };
+                    Some((decal, texture, level, decal.decal_u(u)?))
+                })
+                .collect(),
+        };
 
-                // clip bottom (just return if we reach bottom)
-                height_to_draw -= 1.0;
-                if height_to_draw < 1.0 || height_to_draw.is_nan() {
-                    return;
+        let mut target_index: usize =
+            ((viewport.y + first_row as u32) * self.width + viewport.x + x as u32) as usize;
+        for row in first_row..last_row {
+            // sample in the middle of the pixel
+            let v = v_range.0 + (row as f32 - y + 0.5) * v_step;
+            let [mut red, mut green, mut blue, alpha] =
+                f_wall_texture_buffer.sample(level, u, v, self.texture_filter);
+            for (decal, texture, decal_level, decal_u) in column_decals.iter() {
+                if let Some(decal_v) = decal.decal_v(v) {
+                    let [r, g, b, a] =
+                        texture.sample(*decal_level, *decal_u, decal_v, self.texture_filter);
+                    let mix = |over: u8, under: u8| {
+                        ((over as u32 * a as u32 + under as u32 * (255 - a as u32)) / 255) as u8
+                    };
+                    red = mix(r, red);
+                    green = mix(g, green);
+                    blue = mix(b, blue);
                 }
             }
 
-            source_index += bytes_per_pixel * f_wall_texture_buffer.width;
-            if source_index > last_source_index {
-                source_index = last_source_index;
-            }
-        }
-    }
-
-    //*******************************************************************//
-    //* Mostly used to draw in the overhead map. Doesn't have other uses now.
-    //*******************************************************************//
-    fn draw_fill_rectangle(
-        &mut self,
-        x: u32,
-        y: u32,
-        width: u32,
-        height: u32,
-        red: u8,
-        green: u8,
-        blue: u8,
-        alpha: u8,
-    ) {
-        let canvas_len: usize = self.canvas.len();
-        let default_increment = 4;
-        //let targetCanvasPixels=self.canvasContext.createImageData(0, 0, width, height);
-        let mut target_index: i32 =
-            (default_increment * self.width * y + default_increment * x) as i32;
-        for _h in 0..height {
-            for _w in 0..width {
-                if (target_index as usize) < canvas_len {
-                    argb_to_buffer!(alpha, red, green, blue, self.canvas, target_index as usize);
-                }
-                target_index += default_increment as i32;
+            // Cheap shading trick by using brightnessLevel (which doesn't really have to correspond to "brightness")
+            // to alter colors.  You can use logarithmic falloff or linear falloff to produce some interesting effect
+            if alpha != 0 {
+                argb_to_buffer!(
+                    alpha,
+                    (red as f32 * brightness_level).floor() as u8,
+                    (green as f32 * brightness_level).floor() as u8,
+                    (blue as f32 * brightness_level).floor() as u8,
+                    self.canvas,
+                    target_index,
+                    self.pixel_format
+                );
             }
-            target_index += (default_increment * (self.width - width)) as i32;
+            target_index += self.width as usize;
         }
     }
 
     pub fn init(&mut self) {
-        let mut radian;
         self.f_sin_table = vec![0.0; self.arc_angle360 as usize + 1];
-        self.f_isin_table = vec![0.0; self.arc_angle360 as usize + 1];
         self.f_cos_table = vec![0.0; self.arc_angle360 as usize + 1];
-        self.f_icos_table = vec![0.0; self.arc_angle360 as usize + 1];
-        self.f_tan_table = vec![0.0; self.arc_angle360 as usize + 1];
-        self.f_itan_table = vec![0.0; self.arc_angle360 as usize + 1];
-        self.f_fish_table = vec![0.0; self.arc_angle360 as usize + 1];
-        self.f_xstep_table = vec![0.0; self.arc_angle360 as usize + 1];
-        self.f_ystep_table = vec![0.0; self.arc_angle360 as usize + 1];
 
         for i in 0..=self.arc_angle360 as usize {
             // Populate tables with their radian values.
-            // (The addition of 0.0001 is a kludge to avoid divisions by 0. Removing it will produce unwanted holes in the wall when a ray is at 0, 90, 180, or 270 degree angles)
-            radian = arc_to_rad(i as i32, self.projectionplanewidth) + 0.0001;
+            let radian = arc_to_rad(i as i32, self.projectionplanewidth);
             self.f_sin_table[i] = radian.sin();
-            self.f_isin_table[i] = self.f_sin_table[i].recip();
             self.f_cos_table[i] = radian.cos();
-            self.f_icos_table[i] = self.f_cos_table[i].recip();
-            self.f_tan_table[i] = radian.tan();
-            self.f_itan_table[i] = self.f_tan_table[i].recip();
-
-            // Next we crate a table to speed up wall lookups.
-            //
-            // These tables let you find the X intersection on a tile,
-            // then using the step we can find the next X intersection on the next tile
-            // by taking the current x and adding the step value.
-            //
-            //  You can see that the distance between walls are the same
-            //  if we know the angle
-            //  _____|_/next xi______________
-            //       |
-            //  ____/|next xi_________   slope = tan = height / dist between xi's
-            //     / |
-            //  __/__|_________  dist between xi = height/tan where height=tile size
-            // old xi|
-            //                  distance between xi = x_step[view_angle];
-
-            // Facing LEFT
-            if i >= (self.arc_angle90 as usize) && i < (self.arc_angle270 as usize) {
-                self.f_xstep_table[i] = self.tile_size / self.f_tan_table[i];
-                if self.f_xstep_table[i] > 0.0 {
-                    self.f_xstep_table[i] = -self.f_xstep_table[i];
-                }
-            }
-            // facing RIGHT
-            else {
-                self.f_xstep_table[i] = self.tile_size / self.f_tan_table[i];
-                if self.f_xstep_table[i] < 0.0 {
-                    self.f_xstep_table[i] = -self.f_xstep_table[i];
-                }
-            }
-
-            // FACING DOWN
-            if i >= (self.arc_angle0 as usize) && i < self.arc_angle180 as usize {
-                self.f_ystep_table[i] = self.tile_size as f32 * self.f_tan_table[i];
-                if self.f_ystep_table[i] < 0.0 {
-                    self.f_ystep_table[i] = -self.f_ystep_table[i];
-                }
-            }
-            // FACING UP
-            else {
-                self.f_ystep_table[i] = self.tile_size as f32 * self.f_tan_table[i];
-                if self.f_ystep_table[i] > 0.0 {
-                    self.f_ystep_table[i] = -self.f_ystep_table[i];
-                }
-            }
-        }
-
-        // Create table for fixing FISHBOWL distortion
-        for i in -(self.arc_angle30 as i32)..=self.arc_angle30 as i32 {
-            radian = arc_to_rad(i, self.projectionplanewidth);
-            // we don't have negative angle, so make it start at 0
-            // this will give range from column 0 to 319 (PROJECTONPLANEWIDTH) since we only will need to use those range
-            self.f_fish_table[(i + self.arc_angle30 as i32) as usize] = radian.cos().recip();
         }
 
         // CREATE A SIMPLE MAP.
 
         /*
          * POC map definition:
-         * ---unused 16bits --- generic index 8 bits --- tile type 8 bits---
+         * ---unused 8bits --- lock 8 bits --- generic index 8 bits --- tile type 8 bits---
          * where the generic index can be the door index for doors,
          * and I don't know what else I could use it for in other cases
          * lets say types:
          * 0 - nothing
          * 1 - wall
-         * 2 - door
+         * 2 - door, the lock is the key it needs (see KeyColor::lock_of)
+         * 3 - pushwall, the index is how many cells it moves
+         *     (0 until it runs into something)
          *
          * Emilio, remember to access it f_map[y][x]
          */
@@ -805,13 +1121,15 @@ impl GameWindow {
             [1, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 1, 0, 1, 0, 0, 0, 0, 1],
             [1, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 1, 0, 1, 0, 0, 0, 0, 1],
             [
-                1, 0, 0, 0, 0x0002, 0, 0, 0, 0, 0, 0, 0, 1, 0, 1, 0, 0, 0, 0, 1,
+                1, 0, 0, 0, 0x010002, 0, 0, 0, 0, 0, 0, 0, 1, 0, 1, 0, 0, 0, 0, 1,
             ],
             [1, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 1, 0, 1, 0, 0, 0, 0, 1],
             [
                 1, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 0x0102, 0, 1, 0, 0, 0, 0, 1,
             ],
-            [1, 0, 0, 0, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 0, 0, 0, 0, 1],
+            [
+                1, 0, 0, 0, 1, 1, 1, 1, 1, 1, 1, 1, 1, 0x0203, 1, 0, 0, 0, 0, 1,
+            ],
             [1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1],
             [1, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 1],
             [1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1],
@@ -822,7 +1140,123 @@ impl GameWindow {
         ];
         self.map_width = 20.0;
         self.map_height = 20.0;
+
+        // the level events: the switches of the first room open the second
+        // door and turn the lights down, and the secret behind the pushwall
+        // says it was found
+        let room_wall = |cell_x| WallFace {
+            cell_x,
+            cell_y: 12,
+            side: Side::North,
+        };
+        self.set_level_events(LevelEvents {
+            switches: vec![
+                Switch {
+                    on_event: Some("door_switch_on".to_string()),
+                    off_event: Some("door_switch_off".to_string()),
+                    ..Switch::new(room_wall(6), 188, 189)
+                },
+                Switch {
+                    on_event: Some("lights_off".to_string()),
+                    off_event: Some("lights_on".to_string()),
+                    ..Switch::new(room_wall(9), 188, 189)
+                },
+            ],
+            triggers: vec![Trigger::new(Area::Cell(13, 13), "secret").once()],
+            actions: vec![
+                ("door_switch_on".to_string(), Action::OpenDoor(1)),
+                ("door_switch_off".to_string(), Action::CloseDoor(1)),
+                ("lights_off".to_string(), Action::SetLight(90)),
+                ("lights_on".to_string(), Action::SetLight(180)),
+                (
+                    "secret".to_string(),
+                    Action::Message("YOU FOUND A SECRET".to_string()),
+                ),
+            ],
+        });
+        self.level_ended = false;
+
+        self.minimap
+            .reset(self.map_width as usize, self.map_height as usize);
+        self.decals.clear();
+        self.doors.reset();
+        self.pushwalls.clear();
+        self.inventory.clear();
+
+        // the things in the level: two barrels, a weapon on the floor and
+        // the key of the first door
+        self.entities.clear();
+        self.touching.clear();
+        self.entities.spawn(Entity {
+            shape: Some(BodyShape::Circle(16.0)),
+            blocking: true,
+            ..Entity::decoration(620.0, 620.0, 25.0, 32, 50, 163)
+        });
+        self.entities.spawn(Entity {
+            shape: Some(BodyShape::Circle(24.0)),
+            blocking: true,
+            ..Entity::decoration(600.0, 690.0, 25.0, 60, 32, 163)
+        });
+        self.entities.spawn(Entity {
+            shape: Some(BodyShape::Circle(16.0)),
+            ..Entity::decoration(300.0, 1120.0, 25.0, 60, 32, 42)
+        });
+        self.entities
+            .spawn(Entity::key(1120.0, 224.0, KeyColor::Red));
+        // and the enemies, in the south and in the east corridor
+        let north = -std::f32::consts::FRAC_PI_2;
+        self.entities
+            .spawn(Entity::enemy(544.0, 1056.0, EnemyKind::Guard, north));
+        self.entities
+            .spawn(Entity::enemy(672.0, 1184.0, EnemyKind::Golem, north));
+        self.entities.spawn(Entity::enemy(
+            1120.0,
+            608.0,
+            EnemyKind::Skeleton,
+            std::f32::consts::PI,
+        ));
+        self.update_object_hash();
+        self.rng = Rng::new(self.seed);
+        self.player_health = 100;
+        self.hud.health = Some(self.player_health);
+        self.weapons = Weapons::default();
+        self.hud.ammo = self.weapons.loaded().map(|rounds| rounds as i32);
+
+        // a teleporter in the south east corner takes the player to the
+        // south west one, and the two columns at the south look at each
+        // other through a portal, making a corridor that never ends
+        self.teleporters = vec![Teleporter {
+            angle: Some(0.0),
+            ..Teleporter::new(17, 17, (1, 17))
+        }];
+        self.portals.clear();
+        self.portals.link(
+            WallFace {
+                cell_x: 3,
+                cell_y: 14,
+                side: Side::East,
+            },
+            WallFace {
+                cell_x: 15,
+                cell_y: 14,
+                side: Side::West,
+            },
+        );
+        // the first door opens from the middle, the second one goes up
+        let doors = [
+            (0, DoorKind::Split, SPLIT_DOOR_TEXTURE_ID),
+            (1, DoorKind::Lift, LIFT_DOOR_TEXTURE_ID),
+        ];
+        for (index, kind, texture_id) in doors {
+            if let Some(door) = self.doors.get_mut(index) {
+                door.kind = kind;
+                door.texture_id = Some(texture_id);
+                door.jamb_texture_id = Some(DOOR_JAMB_TEXTURE_ID);
+            }
+        }
+        self.add_map_icons();
         self.map_background_img = 110;
+        self.convert_background();
         // stores walls and doors textures
         self.map_wall_img = [
             [
@@ -950,6 +1384,11 @@ impl GameWindow {
                 162, 162, 162, 162,
             ],
         ];
+        // the teleporters have a floor of their own
+        for teleporter in self.teleporters.iter() {
+            self.map_floor_img[teleporter.cell_y as usize][teleporter.cell_x as usize] =
+                TELEPORTER_TEXTURE_ID;
+        }
         self.map_ceiling_img = [
             [
                 101, 101, 101, 101, 101, 101, 101, 101, 101, 101, 101, 101, 101, 101, 101, 101,
@@ -1034,527 +1473,626 @@ impl GameWindow {
         ];
     }
 
-    //*******************************************************************//
-    //* Draw map on top. Draw a black squares.
-    //*******************************************************************//
-    fn draw_overhead_map(&mut self) {
-        for r in 0..self.map_height as u32 {
-            for c in 0..self.map_width as u32 {
-                if self.f_map[r as usize][c as usize] & 0xf != 0 {
-                    if self.f_map[r as usize][c as usize] & 0x2 == 0x2 {
-                        //this is a door
-                        self.draw_fill_rectangle(
-                            c * self.f_minimap_width as u32, //self.projectionplanewidth + (c * self.f_minimap_width),
-                            r * self.f_minimap_width as u32,
-                            self.f_minimap_width as u32,
-                            self.f_minimap_width as u32,
-                            200,
-                            50,
-                            50,
-                            255,
-                        );
-                    } else {
-                        // a regular wall
-                        self.draw_fill_rectangle(
-                            c * self.f_minimap_width as u32, //self.projectionplanewidth + (c * self.f_minimap_width),
-                            r * self.f_minimap_width as u32,
-                            self.f_minimap_width as u32,
-                            self.f_minimap_width as u32,
-                            0,
-                            0,
-                            0,
-                            255,
-                        );
-                    }
-                }
-            }
-        }
-        // Draw player position on the overhead map
-        self.f_player_map_x =
-            (self.f_player_x / self.tile_size as f32) * self.f_minimap_width as f32; //self.projectionplanewidth as f32 + ((self.f_player_x / self.tile_size as f32) * self.f_minimap_width as f32);
-        self.f_player_map_y =
-            (self.f_player_y / self.tile_size as f32) * self.f_minimap_width as f32;
-    }
-
     //*******************************************************************//
     //* Draw background image
     //*******************************************************************//
 
-    // This is a workaround to fix colors on desktop so we can copy them faster later
-    // Its only for the background because we don't multiply each value to simulate
-    // darker things at the distance, we just dump the image as-is into the background
-    #[cfg(not(feature = "web"))]
-    pub fn correct_background_colors(&mut self) {
-        let mut texture = self
-            .assets
-            .textures
-            .remove(&self.map_background_img)
-            .unwrap();
-        texture.data.chunks_mut(4).for_each(|chunk| {
-            let r = chunk[0];
-            let g = chunk[1];
-            let b = chunk[2];
-            let a = chunk[3];
-
-            chunk[0] = b;
-            chunk[1] = g;
-            chunk[2] = r;
-            chunk[3] = a;
-        });
-        self.assets
-            .textures
-            .insert(self.map_background_img, texture);
-    }
-
-    fn draw_background(&mut self) {
-        let proj_plane_width: usize = self.projectionplanewidth as usize;
-        let bytes_per_pixel = 4;
-        let pp_width_in_bytes = proj_plane_width * bytes_per_pixel;
-        let src_width_in_bytes =
-            self.assets.textures[&self.map_background_img].width as usize * bytes_per_pixel;
-
-        let start_column = self.f_player_arc as usize;
-        let mut src_start = start_column * bytes_per_pixel;
-        let mut src_end = src_start + pp_width_in_bytes; //we only need to copy the row until the end of the proj plane
-        let extra_columns;
-        if src_end > src_width_in_bytes {
-            extra_columns = src_end - src_width_in_bytes;
-            src_end = src_width_in_bytes;
-        } else {
-            extra_columns = 0;
-        }
-        let columns_to_copy = src_end - src_start;
-        let texture = &self.assets.textures[&self.map_background_img].data;
-        let mut dest_start = 0;
-        let mut dest_end = columns_to_copy;
-        for y_position in 0..self.projectionplaneheight as usize {
-            self.canvas[dest_start..dest_end].copy_from_slice(&texture[src_start..src_end]);
-            dest_start = dest_end;
-
-            if extra_columns != 0 {
-                let extra_start = src_width_in_bytes * y_position;
-                let extra_end = extra_start + extra_columns;
-
-                dest_end = dest_start + extra_columns;
-                self.canvas[dest_start..dest_end].copy_from_slice(&texture[extra_start..extra_end]);
-                dest_start = dest_end;
+    /**
+     * Loads the textures listed in the resources, and converts the
+     * background to the pixel format of the canvas.
+     */
+    pub fn load_textures(&mut self) {
+        self.assets.load();
+        self.convert_background();
+    }
+
+    /**
+     * Packs the background into the pixel format of the canvas. The other
+     * textures are sampled and shaded, so they are packed while drawing.
+     * Call it after putting the textures in the assets by hand.
+     */
+    pub fn convert_background(&mut self) {
+        self.background_pixels = match self.assets.textures.get(&self.map_background_img) {
+            Some(texture) => self.pixel_format.pack_rgba_bytes(&texture.data),
+            None => Vec::new(),
+        };
+    }
+
+    // The background is not shaded, the image is copied as-is into the viewport.
+    // It goes around the whole circle, so every column shows the part of
+    // the image in the direction of its ray, and rows are stretched to
+    // the height of the viewport.
+    fn draw_background(&mut self, camera: &Camera, viewport: Viewport) {
+        let (src_width, src_height) = match self.assets.textures.get(&self.map_background_img) {
+            Some(texture) => (texture.width as usize, texture.height as usize),
+            // not loaded yet
+            None => return,
+        };
+        // frontends that put the textures in the assets by themselves may
+        // not have converted it, it's done once here then
+        if self.background_pixels.len() != src_width * src_height {
+            self.convert_background();
+        }
+        // the image column of every viewport column
+        let columns: Vec<usize> = (0..viewport.width)
+            .map(|column| {
+                let angle = camera.angle + camera.column_angle(column as f32, viewport.width);
+                ((angle / self.angle360).rem_euclid(1.0) * src_width as f32) as usize % src_width
+            })
+            .collect();
+        // the texture was converted to the pixel format of the canvas
+        // when it was loaded, so pixels are copied as they are
+        let texture = &self.background_pixels;
+        for row in 0..viewport.height as usize {
+            let src_start = row * src_height / viewport.height as usize * src_width;
+            let src_row = &texture[src_start..src_start + src_width];
+            let dest_start =
+                (viewport.y as usize + row) * self.width as usize + viewport.x as usize;
+            let dest = &mut self.canvas[dest_start..dest_start + viewport.width as usize];
+            for (pixel, &column) in dest.iter_mut().zip(columns.iter()) {
+                *pixel = src_row[column];
             }
-            dest_end = dest_start + columns_to_copy;
-            src_start += src_width_in_bytes;
-            src_end += src_width_in_bytes;
         }
     }
 
     //*******************************************************************//
-    //* Draw ray on the overhead map (for illustartion purpose)
-    //* This is not part of the ray-casting process
+    //* Ray casting
     //*******************************************************************//
 
-    fn draw_ray_on_overhead_map(
-        &mut self,
+    /**
+     * Follows a ray from x, y (world units) in the direction of `angle`
+     * (radians) until it hits a wall, the closed part of a door or a
+     * moving pushwall. Returns None if it leaves the map first.
+     * Rays go through portals, the hit is where the ray ended after them
+     * and the distance is measured along the whole ray.
+     */
+    pub fn cast_ray(&self, x: f32, y: f32, angle: f32) -> Option<RayHit> {
+        self.cast_ray_path(x, y, angle, &mut Vec::new())
+    }
+
+    /**
+     * True if no wall, closed door or pushwall is between the two points.
+     * It casts the rays the renderer casts, so whatever is seen on the
+     * screen blocks it. Portals don't let it through.
+     */
+    pub fn line_of_sight(&self, from_x: f32, from_y: f32, to_x: f32, to_y: f32) -> bool {
+        let distance = (to_x - from_x).hypot(to_y - from_y);
+        let angle = (to_y - from_y).atan2(to_x - from_x);
+        self.cast_straight(from_x, from_y, angle)
+            .is_none_or(|hit| hit.distance >= distance)
+    }
+
+    /**
+     * The same as cast_ray, adding to `hops` the part of the ray after
+     * every portal it went through. At most Portals::max_hops, the portal
+     * after those is hit like a wall.
+     */
+    pub fn cast_ray_path(
+        &self,
         x: f32,
         y: f32,
-        red: u8,
-        green: u8,
-        blue: u8,
-        alpha: u8,
-    ) {
-        // draw line from the player position to the position where the ray
-        // intersect with wall
-        self.draw_line(
-            self.f_player_map_x.floor() as i32,
-            self.f_player_map_y.floor() as i32,
-            //(self.projectionplanewidth as f32 + (x * self.f_minimap_width as f32) / self.tile_size as f32).floor() as i32,
-            ((x * self.f_minimap_width as f32) / self.tile_size as f32).floor() as i32,
-            ((y * self.f_minimap_width as f32) / self.tile_size as f32).floor() as i32,
-            red,
-            green,
-            blue,
-            alpha,
-        );
+        angle: f32,
+        hops: &mut Vec<RaySegment>,
+    ) -> Option<RayHit> {
+        let mut segment = RaySegment::new(0.0, x, y, angle, None);
+        for hop in 0..=self.portals.max_hops {
+            let hit = self.cast_straight(segment.x, segment.y, segment.angle)?;
+            let hit = RayHit {
+                distance: segment.start + hit.distance,
+                ..hit
+            };
+            let exit = match hit.door {
+                Some(_) => None,
+                None if hop < self.portals.max_hops => self.portals.exit(hit.face),
+                None => None,
+            };
+            let exit = match exit {
+                Some(exit) => exit,
+                None => return Some(hit),
+            };
+            let (x, y, angle) =
+                Portals::through(hit.face, exit, self.tile_size, hit.x, hit.y, segment.angle);
+            segment = RaySegment::new(hit.distance, x, y, angle, Some(hit.face));
+            hops.push(segment);
+            // it starts a bit out of the wall, so it doesn't hit the exit
+            // face, or the walls next to it
+            segment.x += segment.cos * PORTAL_GAP;
+            segment.y += segment.sin * PORTAL_GAP;
+            segment.start += PORTAL_GAP;
+        }
+        None
     }
 
-    //*******************************************************************//
-    //* Draw player POV on the overhead map (for illustartion purpose)
-    //* This is not part of the ray-casting process
-    //*******************************************************************//
-    fn draw_player_pov_on_overhead_map(&mut self, _x: u32, _y: u32) {
-        // draw a red line indication the player's direction
-        self.draw_line(
-            self.f_player_map_x.floor() as i32,
-            self.f_player_map_y.floor() as i32,
-            (self.f_player_map_x as f32 + self.f_cos_table[self.f_player_arc as usize] * 10.0)
-                .floor() as i32,
-            (self.f_player_map_y as f32 + self.f_sin_table[self.f_player_arc as usize] * 10.0)
-                .floor() as i32,
-            255,
-            0,
-            0,
-            255,
-        );
+    // cast_ray without portals
+    fn cast_straight(&self, x: f32, y: f32, angle: f32) -> Option<RayHit> {
+        let (sin, cos) = angle.sin_cos();
+        // A ray going straight left or right never crosses a horizontal
+        // grid line, and one going straight up or down never crosses a
+        // vertical one. Checking it here avoids divisions by 0.
+        let horizontal = if sin.abs() > f32::EPSILON {
+            self.cast_to_horizontal_grid(x, y, sin, cos)
+        } else {
+            None
+        };
+        let vertical = if cos.abs() > f32::EPSILON {
+            self.cast_to_vertical_grid(x, y, sin, cos)
+        } else {
+            None
+        };
+        let wall = match (horizontal, vertical) {
+            (Some(horizontal), Some(vertical)) => {
+                if horizontal.distance < vertical.distance {
+                    Some(horizontal)
+                } else {
+                    Some(vertical)
+                }
+            }
+            (horizontal, vertical) => horizontal.or(vertical),
+        };
+        match (wall, self.cast_to_pushwalls(x, y, sin, cos)) {
+            (Some(wall), Some(pushwall)) if pushwall.distance < wall.distance => Some(pushwall),
+            (wall, pushwall) => wall.or(pushwall),
+        }
     }
 
-    //*******************************************************************//
-    //* Renderer
-    //*******************************************************************//
-    fn raycast(&mut self) {
-        // NOTE: (0,0) is top left. Comments about orientation are based on that.
-        // So notice that when it says down, it means 0 < angle < 180 because
-        // when we look at the drawing the ray is facing down. It's just
-        // because the drawing looks like that. If 0,0 would at the be bottom
-        // then it would say itś looking up, but it'd still refer to the first
-        // and second quadrants (0 to 180)
-
-        // This horizontal grid is the Y coordinate of the ray intersection
-        // with the wall in a point A.
-        // So, it's the wall above or below the player (the horizontal walls).
-        // if it's facing down it will be bigger than the player_y position,
-        // if it's facing up it will be smaller.
-        // theoritically, this will be multiple of TILE_SIZE, but some trick done
-        // here might cause the values off by 1
-        let mut horizontal_grid: f32;
-        // contrary to the horizontal grid variable, the vertical
-        // grid value will hold the X value of the intersection which is left or right
-        // (hence, the vertical name)
-        // TODO: I think this naming is confusing and could be changed to something better
-        let mut vertical_grid: f32;
-        let mut dist_to_next_vertical_grid: f32; // how far to the next bound (this is multiple of
-        let mut dist_to_next_horizontal_grid: f32; // tile size)
-        let mut x_intersection: f32; // x and y intersections
-        let mut y_intersection: f32;
-        let mut dist_to_next_xintersection: f32;
-        let mut dist_to_next_yintersection: f32;
-
-        let mut x_grid_index: i32; // the current cell that the ray is in
-        let mut y_grid_index: i32;
-
-        let mut dist_to_vertical_grid_being_hit: f32; // the distance of the x and y ray intersections from
-        let mut dist_to_horizontal_grid_being_hit: f32; // the viewpoint
-
-        let mut cast_arc: i32;
-
-        let default_increment = 4;
-        //let debug = false;
-
-        // field of view is 60 degree with the point of view (player's direction in the middle)
-        // 30  30
-        //    ^
-        //  \ | /
-        //   \|/
-        //    v
-        // we will trace the rays starting from the leftmost ray
-        let mut cast_angle = self.f_player_angle - self.angle30;
-        cast_arc = rad_to_arc(cast_angle, self.projectionplanewidth);
-
-        // wrap around if necessary
-        if cast_angle < self.angle0 {
-            cast_angle += self.angle360;
-            cast_arc = rad_to_arc(cast_angle, self.projectionplanewidth);
-        }
-        for cast_column in 0..self.projectionplanewidth as u32 {
-            dist_to_next_xintersection = self.f_xstep_table[cast_arc as usize];
-            dist_to_next_yintersection = self.f_ystep_table[cast_arc as usize];
-
-            // SEARCH FOR THE FIRST INTERSECTION OF THE CAST COLUMN AND A POSSIBLE WALL
-            // We only need to search for the first tile borders. We will look for walls later.
-            // We check which side the ray is pointing first
-            // Ray is facing down
-            if cast_angle > self.angle0 && cast_angle < self.angle180 {
-                // truncuate then add to get the coordinate of the FIRST grid (horizontal
-                // wall) that is in front of the player (this is in pixel unit)
-                // ROUNDED DOWN
-                horizontal_grid = (self.f_player_y / self.tile_size).floor()
-                    * self.tile_size as f32
-                    + self.tile_size as f32;
-                // compute distance to the next horizontal wall
-                dist_to_next_horizontal_grid = self.tile_size;
-
-                // now we get the distances (offsets) from the player to the horizontal wall.
-                // if the intersection of the ray with the wall is at point A then:
-                // (remember A.y == horizontal_grid)
-                // y_offset = A.y - self.player_y
-                // If we draw this whole scenario on paper we can see:
-                // tan(cast_arc)=y_offset/x_offset
-                // And with that formular we can play like this:
-                // itan(cast_arc)=1/tan=x_offset/y_offset
-                // x_offset = itan * y_offset
-
-                // This x_offset plus the x point where the player stands
-                // gives use the A.x coordinate of intersection.
-                let xtemp =
-                    self.f_itan_table[cast_arc as usize] * (horizontal_grid - self.f_player_y);
-                x_intersection = xtemp + self.f_player_x;
-            }
-            // Else, the ray is facing up
-            else {
-                horizontal_grid =
-                    (self.f_player_y / self.tile_size as f32).floor() * self.tile_size;
-                dist_to_next_horizontal_grid = -(self.tile_size);
+    /**
+     * The nearest moving pushwall in front of the ray. A moving block is
+     * not in the map, it's somewhere between two cells, so the ray is
+     * tested against the block itself instead of following the grid.
+     * The face is the cell the block is leaving.
+     */
+    fn cast_to_pushwalls(&self, x: f32, y: f32, sin: f32, cos: f32) -> Option<RayHit> {
+        self.pushwalls
+            .moving()
+            .iter()
+            .filter_map(|wall| {
+                let (distance, side, u) = wall.intersect(self.tile_size, x, y, sin, cos)?;
+                Some(RayHit {
+                    distance,
+                    x: x + cos * distance,
+                    y: y + sin * distance,
+                    face: WallFace {
+                        cell_x: wall.cell_x,
+                        cell_y: wall.cell_y,
+                        side,
+                    },
+                    vertical: matches!(side, Side::West | Side::East),
+                    u,
+                    door: None,
+                })
+            })
+            .min_by(|a, b| a.distance.total_cmp(&b.distance))
+    }
+
+    // the value of a map cell, None outside of the map
+    #[inline]
+    fn map_cell(&self, cell_x: i32, cell_y: i32) -> Option<u32> {
+        if cell_x < 0
+            || cell_y < 0
+            || cell_x >= self.map_width as i32
+            || cell_y >= self.map_height as i32
+        {
+            return None;
+        }
+        Some(self.f_map[cell_y as usize][cell_x as usize])
+    }
 
-                let xtemp =
-                    self.f_itan_table[cast_arc as usize] * (horizontal_grid - self.f_player_y);
-                x_intersection = xtemp + self.f_player_x;
+    // the texture u of a door at `offset` along it (0 to 1), None where it's open
+    #[inline]
+    fn door_panel_u(&self, cell: u32, offset: f32) -> Option<f32> {
+        match self.doors.get(door_index(cell)) {
+            Some(door) => door.panel_u(offset),
+            // a door without a door, it can't be opened
+            None => Some(offset),
+        }
+    }
 
-                horizontal_grid -= 1.0;
+    /**
+     * What blocks movement in a cell. Outside of the map is solid.
+     * Doors are thin walls along the middle of the cell, across the
+     * corridor they close, and only the parts that are closed block.
+     */
+    pub fn cell_shape(&self, cell_x: i32, cell_y: i32) -> CellShape {
+        let cell = match self.map_cell(cell_x, cell_y) {
+            Some(cell) => cell,
+            None => return CellShape::Solid,
+        };
+        match cell & 0xf {
+            0 => CellShape::Empty,
+            2 => {
+                let parts = match self.doors.get(door_index(cell)) {
+                    Some(door) => door.closed_parts(),
+                    None => [Some((0.0, 1.0)), None],
+                };
+                match parts {
+                    [Some(first), Some(second)] => CellShape::Segments(
+                        self.door_segment(cell_x, cell_y, first),
+                        self.door_segment(cell_x, cell_y, second),
+                    ),
+                    [Some(part), None] | [None, Some(part)] => {
+                        let (x0, y0, x1, y1) = self.door_segment(cell_x, cell_y, part);
+                        CellShape::Segment(x0, y0, x1, y1)
+                    }
+                    [None, None] => CellShape::Empty,
+                }
             }
-            // NOW WE START LOOKING FOR WALLS
-            // We have the coordinates of the FIRST GRID intersection with the ray
-            // so we can start looking for walls
+            _ => CellShape::Solid,
+        }
+    }
 
-            // LOOK FOR HORIZONTAL WALL (walls in the X axis)
+    // part of the door line of a cell, from `from` to `to` along it (0 to 1)
+    fn door_segment(
+        &self,
+        cell_x: i32,
+        cell_y: i32,
+        (from, to): (f32, f32),
+    ) -> (f32, f32, f32, f32) {
+        let left = cell_x as f32 * self.tile_size;
+        let top = cell_y as f32 * self.tile_size;
+        let middle = self.tile_size / 2.0;
+        let is_wall = |x, y| self.map_cell(x, y).is_none_or(|cell| cell & 0xf != 0);
+        if is_wall(cell_x, cell_y - 1) && is_wall(cell_x, cell_y + 1) {
+            // a corridor going east to west, the door goes north to south
+            (
+                left + middle,
+                top + from * self.tile_size,
+                left + middle,
+                top + to * self.tile_size,
+            )
+        } else {
+            (
+                left + from * self.tile_size,
+                top + middle,
+                left + to * self.tile_size,
+                top + middle,
+            )
+        }
+    }
 
-            // If ray is directly facing right or left, then ignore it
-            if cast_arc == self.arc_angle0 || cast_arc == self.arc_angle180 {
-                dist_to_horizontal_grid_being_hit = f32::MAX;
-            }
-            // else, move the ray until it hits a horizontal wall
-            else {
-                // The step to the next x intersection is always the same for a given angle
-                // so this is optimized so we only calculate it at the beginning.
-                // The same happens with y intersections a few lines below
-                loop {
-                    x_grid_index = (x_intersection / self.tile_size).floor() as i32;
-                    y_grid_index = (horizontal_grid as f32 / self.tile_size as f32).floor() as i32;
-                    // If we've looked as far as outside the map range, then bail out
-                    if x_grid_index >= self.map_width as i32
-                        || y_grid_index >= self.map_height as i32
-                        || x_grid_index < 0
-                        || y_grid_index < 0
-                    {
-                        dist_to_horizontal_grid_being_hit = f32::MAX;
-                        break;
-                    }
+    /**
+     * The texture of what a ray hit: the door texture for doors, the jamb
+     * texture of a door for the faces around it, the texture the pushwall
+     * had for moving ones, the on or off texture of switches, the wall
+     * texture otherwise
+     */
+    fn wall_texture(&self, hit: &RayHit) -> u32 {
+        let face = hit.face;
+        if let Some(pushwall) = self.pushwalls.at(face.cell_x, face.cell_y) {
+            return pushwall.texture_id;
+        }
+        if let Some(switch) = self.switches.iter().find(|switch| switch.face == face) {
+            return switch.texture_id();
+        }
+        let wall = self.map_wall_img[face.cell_y as usize][face.cell_x as usize];
+        if let Some(index) = hit.door {
+            return self
+                .doors
+                .get(index)
+                .and_then(|door| door.texture_id)
+                .unwrap_or(wall);
+        }
+        // the cell the face looks at
+        let (dx, dy) = match face.side {
+            Side::North => (0, -1),
+            Side::South => (0, 1),
+            Side::West => (-1, 0),
+            Side::East => (1, 0),
+        };
+        match self.map_cell(face.cell_x + dx, face.cell_y + dy) {
+            Some(cell) if cell & 0xf == 2 => self
+                .doors
+                .get(door_index(cell))
+                .and_then(|door| door.jamb_texture_id)
+                .unwrap_or(wall),
+            _ => wall,
+        }
+    }
 
-                    // If the grid is not an Opening, then stop
-                    if self.f_map[y_grid_index as usize][x_grid_index as usize] & 0xf != 0 {
-                        if self.f_map[y_grid_index as usize][x_grid_index as usize] & 0x2 == 0x2 {
-                            //its a door
-                            let door_index =
-                                ((self.f_map[y_grid_index as usize][x_grid_index as usize] >> 8)
-                                    & 0xff) as usize;
-                            // check if open, if the ray goes through and act accordingly
-                            let hit_x_on_tile = x_intersection % self.tile_size;
-                            if hit_x_on_tile + dist_to_next_xintersection / 2.0
-                                >= self.door_positions[door_index] as f32
-                            {
-                                // we hit a door and the ray must not continue
-                                let door_x_intersection =
-                                    x_intersection + dist_to_next_xintersection / 2.0; // intercept x = ax+xstep/2
-                                                                                       //let door_y_intersection = horizontal_grid + self.tile_size/2.0;// intercepty = ay+tile_size/2
-                                dist_to_horizontal_grid_being_hit = (door_x_intersection
-                                    - self.f_player_x)
-                                    * self.f_icos_table[cast_arc as usize];
-                                break;
-                            }
-                        } else {
-                            // its a wall
-                            dist_to_horizontal_grid_being_hit = (x_intersection - self.f_player_x)
-                                * self.f_icos_table[cast_arc as usize];
-                            break;
-                        }
+    // NOTE: (0,0) is top left. Comments about orientation are based on that.
+    // So notice that when it says down, it means 0 < angle < 180 because
+    // when we look at the drawing the ray is facing down. It's just
+    // because the drawing looks like that. If 0,0 would at the be bottom
+    // then it would say itś looking up, but it'd still refer to the first
+    // and second quadrants (0 to 180)
+
+    // Looks for walls on the horizontal grid lines, the walls above or below
+    // the ray (north and south faces)
+    fn cast_to_horizontal_grid(&self, x: f32, y: f32, sin: f32, cos: f32) -> Option<RayHit> {
+        let facing_down = sin > 0.0;
+        // The Y coordinate of the FIRST horizontal grid line in front of the ray.
+        // If it's facing down it will be bigger than y, if it's facing up it
+        // will be smaller.
+        let (mut grid_y, step_y) = if facing_down {
+            (
+                (y / self.tile_size).floor() * self.tile_size + self.tile_size,
+                self.tile_size,
+            )
+        } else {
+            (
+                (y / self.tile_size).floor() * self.tile_size,
+                -self.tile_size,
+            )
+        };
+        // The distance between the x intersections is the same for every
+        // line, so we only compute it once and keep adding it.
+        //  _____|_/next xi______________
+        //       |
+        //  ____/|next xi_________   slope = tan = height / dist between xi's
+        //     / |
+        //  __/__|_________  dist between xi = height/tan where height=tile size
+        // old xi|
+        let step_x = step_y * cos / sin;
+        let mut x_intersection = x + (grid_y - y) * cos / sin;
+        loop {
+            let cell_x = (x_intersection / self.tile_size).floor() as i32;
+            // the cell after the line. Going up, that's the one above it
+            let cell_y = (grid_y / self.tile_size).floor() as i32 - if facing_down { 0 } else { 1 };
+            // if we've looked as far as outside the map range, then bail out
+            let cell = self.map_cell(cell_x, cell_y)?;
+            let hit = match cell & 0xf {
+                0 => None,
+                2 => {
+                    // Doors stand in the middle of their cell.
+                    // The ray goes through the part that is already open,
+                    // and through the cell if it leaves it before the middle
+                    let door_x = x_intersection + step_x / 2.0;
+                    let offset = door_x - cell_x as f32 * self.tile_size;
+                    if (0.0..self.tile_size).contains(&offset) {
+                        self.door_panel_u(cell, offset / self.tile_size)
+                            .map(|u| (door_x, grid_y + step_y / 2.0, u))
+                    } else {
+                        None
                     }
-                    // Else, keep looking.  At this point, the ray is not blocked, extend the ray to the next grid
-                    x_intersection += dist_to_next_xintersection;
-                    horizontal_grid += dist_to_next_horizontal_grid;
                 }
+                _ => Some((
+                    x_intersection,
+                    grid_y,
+                    x_intersection.rem_euclid(self.tile_size) / self.tile_size,
+                )),
+            };
+            if let Some((hit_x, hit_y, u)) = hit {
+                return Some(RayHit {
+                    distance: (hit_x - x).hypot(hit_y - y),
+                    x: hit_x,
+                    y: hit_y,
+                    face: WallFace {
+                        cell_x,
+                        cell_y,
+                        side: if facing_down {
+                            Side::North
+                        } else {
+                            Side::South
+                        },
+                    },
+                    vertical: false,
+                    u,
+                    door: (cell & 0xf == 2).then(|| door_index(cell)),
+                });
             }
-            // FOLLOW X RAY
-            // Ray facing right
-            if cast_angle < self.angle90 || cast_angle > self.angle270 {
-                // the vertical grid will be left or right of the player
-                // vertical_grid will be the X value of the intersection
-                vertical_grid =
-                    self.tile_size + (self.f_player_x / self.tile_size).floor() * self.tile_size;
-                dist_to_next_vertical_grid = self.tile_size;
-
-                let ytemp = self.f_tan_table[cast_arc as usize] * (vertical_grid - self.f_player_x);
-                y_intersection = ytemp + self.f_player_y;
-                // now we have the x and y intersection with a vertical grid
-            }
-            // ray facing left
-            else {
-                vertical_grid = (self.f_player_x / self.tile_size).floor() * self.tile_size as f32;
-                dist_to_next_vertical_grid = -(self.tile_size);
-                let ytemp;
-                ytemp = self.f_tan_table[cast_arc as usize] * (vertical_grid - self.f_player_x);
-                y_intersection = ytemp + self.f_player_y;
-
-                vertical_grid -= 1.0;
-            }
-
-            // LOOK FOR VERTICAL WALL (Y axis)
-            if cast_arc == self.arc_angle90 || cast_arc == self.arc_angle270 {
-                dist_to_vertical_grid_being_hit = f32::MAX;
-            } else {
-                loop {
-                    // compute current map position to inspect
-                    x_grid_index = (vertical_grid as f32 / self.tile_size as f32).floor() as i32;
-                    y_grid_index = (y_intersection as f32 / self.tile_size as f32).floor() as i32;
-
-                    if x_grid_index >= self.map_width as i32
-                        || y_grid_index >= self.map_height as i32
-                        || x_grid_index < 0
-                        || y_grid_index < 0
-                    {
-                        dist_to_vertical_grid_being_hit = f32::MAX;
-                        break;
-                    }
+            // Else, keep looking. At this point, the ray is not blocked, extend the ray to the next grid
+            x_intersection += step_x;
+            grid_y += step_y;
+        }
+    }
 
-                    if self.f_map[y_grid_index as usize][x_grid_index as usize] & 0xf != 0 {
-                        if self.f_map[y_grid_index as usize][x_grid_index as usize] & 0x2 == 0x2 {
-                            //its a door
-                            let door_index =
-                                ((self.f_map[y_grid_index as usize][x_grid_index as usize] >> 8)
-                                    & 0xff) as usize;
-                            // check if open, if the ray goes through and act accordingly
-                            //
-                            let hit_y_on_tile = y_intersection % self.tile_size;
-                            if hit_y_on_tile + dist_to_next_yintersection / 2.0
-                                >= self.door_positions[door_index] as f32
-                            {
-                                // we hit a door and the ray must not continue
-                                let door_y_intersection =
-                                    y_intersection + dist_to_next_yintersection / 2.0; // intercept y = ay+xstep/2
-                                                                                       //let door_x_intersection = vertical_grid + self.tile_size/2.0;// interceptx = ax+tile_size/2
-                                dist_to_vertical_grid_being_hit = (door_y_intersection
-                                    - self.f_player_y)
-                                    * self.f_isin_table[cast_arc as usize];
-                                break;
-                            }
-                        } else {
-                            dist_to_vertical_grid_being_hit = (y_intersection as f32
-                                - self.f_player_y as f32)
-                                * self.f_isin_table[cast_arc as usize];
-                            break;
-                        }
+    // The same as cast_to_horizontal_grid for the vertical grid lines, the
+    // walls left or right of the ray (east and west faces)
+    fn cast_to_vertical_grid(&self, x: f32, y: f32, sin: f32, cos: f32) -> Option<RayHit> {
+        let facing_right = cos > 0.0;
+        let (mut grid_x, step_x) = if facing_right {
+            (
+                (x / self.tile_size).floor() * self.tile_size + self.tile_size,
+                self.tile_size,
+            )
+        } else {
+            (
+                (x / self.tile_size).floor() * self.tile_size,
+                -self.tile_size,
+            )
+        };
+        let step_y = step_x * sin / cos;
+        let mut y_intersection = y + (grid_x - x) * sin / cos;
+        loop {
+            let cell_x =
+                (grid_x / self.tile_size).floor() as i32 - if facing_right { 0 } else { 1 };
+            let cell_y = (y_intersection / self.tile_size).floor() as i32;
+            let cell = self.map_cell(cell_x, cell_y)?;
+            let hit = match cell & 0xf {
+                0 => None,
+                2 => {
+                    let door_y = y_intersection + step_y / 2.0;
+                    let offset = door_y - cell_y as f32 * self.tile_size;
+                    if (0.0..self.tile_size).contains(&offset) {
+                        self.door_panel_u(cell, offset / self.tile_size)
+                            .map(|u| (grid_x + step_x / 2.0, door_y, u))
+                    } else {
+                        None
                     }
-                    y_intersection += dist_to_next_yintersection;
-                    vertical_grid += dist_to_next_vertical_grid;
                 }
+                _ => Some((
+                    grid_x,
+                    y_intersection,
+                    y_intersection.rem_euclid(self.tile_size) / self.tile_size,
+                )),
+            };
+            if let Some((hit_x, hit_y, u)) = hit {
+                return Some(RayHit {
+                    distance: (hit_x - x).hypot(hit_y - y),
+                    x: hit_x,
+                    y: hit_y,
+                    face: WallFace {
+                        cell_x,
+                        cell_y,
+                        side: if facing_right { Side::West } else { Side::East },
+                    },
+                    vertical: true,
+                    u,
+                    door: (cell & 0xf == 2).then(|| door_index(cell)),
+                });
             }
+            y_intersection += step_y;
+            grid_x += step_x;
+        }
+    }
+
+    /**
+     * The top and bottom rows of a wall at `distance` (measured perpendicular
+     * to the projection plane), relative to the viewport
+     */
+    fn project_wall(&self, camera: &Camera, viewport: Viewport, distance: f32) -> (f32, f32) {
+        let projection_distance = camera.projection_distance(viewport.width);
+        let ratio = projection_distance / distance;
+        let bottom_of_wall = ratio * camera.height + camera.horizon(viewport.height);
+        //
+        // Projected Slice Height=(Actual Slice Height/Distance to the Slice) * Distance to Projection Plane
+        //
+        let real_height = projection_distance * self.wall_height / distance;
+        (bottom_of_wall - real_height, bottom_of_wall)
+    }
+
+    //*******************************************************************//
+    //* Renderer
+    //*******************************************************************//
+
+    /**
+     * Draws what a camera sees into a viewport of the back buffer: the
+     * background, walls, floor, ceiling and sprites. Nothing outside of the
+     * viewport is touched and it gets its own z-buffer, so any amount of
+     * cameras can be drawn in the same frame.
+     */
+    pub fn render_view(&mut self, camera: &Camera, viewport: Viewport) {
+        let viewport = viewport.clip(self.width, self.height);
+        if viewport.is_empty() {
+            return;
+        }
+        self.draw_background(camera, viewport);
+        self.raycast(camera, viewport, false);
+        self.draw_objects(camera, viewport);
+    }
 
-            // DRAW THE WALL SLICE
-            //let mut scale_factor: f32;
-            let mut dist: f32;
-            let x_offset;
-            let top_of_wall: f32; // used to compute the top and bottom of the sliver that
-            let bottom_of_wall: f32; // will be the staring point of floor and ceiling
-                                     // determine which ray strikes a closer wall.
-                                     // if yray distance to the wall is closer, the yDistance will be shorter than
-                                     // the xDistance
-            let mut is_vertical_hit = false;
-
-            if dist_to_horizontal_grid_being_hit < dist_to_vertical_grid_being_hit {
-                // the next function call (drawRayOnMap()) is not a part of raycating rendering part,
-                // it just draws the ray on the overhead map to illustrate the raycasting process
-                self.draw_ray_on_overhead_map(x_intersection, horizontal_grid, 0, 255, 0, 255);
-                self.f_player_to_wall_dist[cast_column as usize] =
-                    dist_to_horizontal_grid_being_hit;
-                dist = dist_to_horizontal_grid_being_hit / self.f_fish_table[cast_column as usize];
-                let ratio = self.f_player_distance_to_the_projection_plane as f32 / dist;
-                bottom_of_wall =
-                    ratio * self.f_player_height as f32 + self.f_projection_plane_ycenter as f32;
-
-                //
-                // Projected Slice Height=(Actual Slice Height/Distance to the Slice) * Distance to Projection Plane
-                //
-                let real_height: f32 = self.f_player_distance_to_the_projection_plane as f32 //277
-                    * self.wall_height as f32  //64
-                    / dist;
-                top_of_wall = bottom_of_wall - real_height;
-                x_offset = x_intersection % self.tile_size as f32;
-                // update current map position to get the textures later
-                x_grid_index = (x_intersection as f32 / self.tile_size as f32).floor() as i32;
-                y_grid_index = (horizontal_grid as f32 / self.tile_size as f32).floor() as i32;
+    // flips a viewport of the back buffer horizontally
+    fn mirror_viewport(&mut self, viewport: Viewport) {
+        for row in viewport.y..viewport.y + viewport.height {
+            let start = (row * self.width + viewport.x) as usize;
+            self.canvas[start..start + viewport.width as usize].reverse();
+        }
+    }
+
+    // Draws walls, floor and ceiling and fills the z-buffer.
+    // Only the player's view reveals cells on the minimap.
+    fn raycast(&mut self, camera: &Camera, viewport: Viewport, reveal: bool) {
+        let projection_distance = camera.projection_distance(viewport.width);
+        let projection_plane_center_y = camera.horizon(viewport.height);
+        self.z_buffer.clear();
+        self.z_buffer.resize(viewport.width as usize, f32::MAX);
+
+        // we trace the rays starting from the leftmost column
+        for cast_column in 0..viewport.width {
+            let column_angle = camera.column_angle(cast_column as f32, viewport.width);
+            let cast_angle = camera.angle + column_angle;
+            // The rays are cast from the eye, but the slices are projected on a
+            // flat plane. Using the distance perpendicular to that plane fixes
+            // the FISHBOWL distortion
+            let fish = column_angle.cos();
+
+            // The ray from the camera, and the parts of it after the
+            // portals it went through. The floor and the ceiling behind a
+            // portal are the ones after it
+            let first_segment = RaySegment::new(0.0, camera.x, camera.y, cast_angle, None);
+            let mut hops = Vec::new();
+            let segment_at = |hops: &[RaySegment], distance: f32| {
+                *hops
+                    .iter()
+                    .rev()
+                    .find(|hop| hop.start <= distance)
+                    .unwrap_or(&first_segment)
+            };
+
+            // Lift doors that are partly up let the ray go under them. What
+            // is behind them is drawn first, and the doors over it later
+            let mut lifted = Vec::new();
+            let mut ray_hit = self.cast_ray_path(camera.x, camera.y, cast_angle, &mut hops);
+            while let Some(hit) = ray_hit {
+                match hit.door.and_then(|index| self.doors.get(index)) {
+                    Some(door) if door.kind == DoorKind::Lift && door.position > 0.0 => {
+                        lifted.push((hit, door.position))
+                    }
+                    _ => break,
+                }
+                // keep going from just behind the door
+                let skip = hit.distance + 0.01;
+                let segment = segment_at(&hops, skip);
+                let (x, y) = segment.point(skip);
+                let first_hop = hops.len();
+                ray_hit = self
+                    .cast_ray_path(x, y, segment.angle, &mut hops)
+                    .map(|far| RayHit {
+                        distance: far.distance + skip,
+                        ..far
+                    });
+                for hop in hops[first_hop..].iter_mut() {
+                    hop.start += skip;
+                }
             }
-            // else, we use xray instead (meaning the vertical wall is closer than
-            //   the horizontal wall)
-            else {
-                is_vertical_hit = true;
-                // the next function call (drawRayOnMap()) is not a part of raycating rendering part,
-                // it just draws the ray on the overhead map to illustrate the raycasting process
-                self.draw_ray_on_overhead_map(vertical_grid, y_intersection, 0, 0, 255, 255);
-                self.f_player_to_wall_dist[cast_column as usize] = dist_to_vertical_grid_being_hit;
-                dist = dist_to_vertical_grid_being_hit / self.f_fish_table[cast_column as usize];
-
-                x_offset = y_intersection % self.tile_size as f32;
-
-                let ratio = self.f_player_distance_to_the_projection_plane as f32 / dist;
-                bottom_of_wall =
-                    ratio * self.f_player_height as f32 + self.f_projection_plane_ycenter as f32;
-                let real_height: f32 = self.f_player_distance_to_the_projection_plane as f32
-                    * self.wall_height as f32
-                    / dist;
-                top_of_wall = bottom_of_wall - real_height;
-                // update current map position to get the textures later
-                x_grid_index = (vertical_grid as f32 / self.tile_size as f32).floor() as i32;
-                y_grid_index = (y_intersection as f32 / self.tile_size as f32).floor() as i32;
+            // sprites are hidden by the closest thing the ray hit
+            if let Some((nearest, _)) = lifted.first() {
+                self.z_buffer[cast_column as usize] = nearest.distance * fish;
+            } else if let Some(hit) = ray_hit {
+                self.z_buffer[cast_column as usize] = hit.distance * fish;
             }
 
-            // Add simple shading so that farther wall slices appear darker.
-            // use arbitrary value of the farthest distance.
-            dist = dist.floor();
-
-            // get the texture:
-            // x_grid_index y_grid_index
-            let wall_texture: u32 = self.map_wall_img[y_grid_index as usize][x_grid_index as usize];
-
-            // Trick to give different shades between vertical and horizontal (you could also use different textures for each if you wish to)
-            if is_vertical_hit {
-                self.draw_wall_slice_rectangle_tinted(
-                    cast_column as f32,
-                    top_of_wall,
-                    1.0,
-                    (bottom_of_wall - top_of_wall) + 1.0,
-                    x_offset,
-                    self.base_light_value as f32 / dist,
-                    wall_texture,
-                );
-            } else {
-                self.draw_wall_slice_rectangle_tinted(
-                    cast_column as f32,
-                    top_of_wall,
-                    1.0,
-                    (bottom_of_wall - top_of_wall) + 1.0,
-                    x_offset,
-                    (self.base_light_value as f32 - 50.0) / dist,
-                    wall_texture,
-                );
-            }
+            let (top_of_wall, bottom_of_wall) = match ray_hit {
+                Some(hit) => {
+                    // not part of the rendering: the minimap reveals what this
+                    // ray saw, up to the first portal
+                    if reveal {
+                        let (x, y, face, vertical) = match hops.first() {
+                            Some(hop) => {
+                                let (x, y) = first_segment.point(hop.start);
+                                let face = hop.portal.unwrap_or(hit.face);
+                                (x, y, face, matches!(face.side, Side::West | Side::East))
+                            }
+                            None => (hit.x, hit.y, hit.face, hit.vertical),
+                        };
+                        self.minimap.reveal_ray(
+                            camera.x,
+                            camera.y,
+                            x,
+                            y,
+                            (face.cell_x, face.cell_y),
+                            vertical,
+                            self.tile_size,
+                        );
+                    }
+                    self.draw_wall_hit(camera, viewport, cast_column, &hit, fish, 0.0)
+                }
+                // nothing to draw, the floor and ceiling go up to the horizon
+                None => (projection_plane_center_y, projection_plane_center_y),
+            };
 
-            let bytes_per_pixel = 4;
-            let projection_plane_center_y = self.f_projection_plane_ycenter;
-            let last_bottom_of_wall: f32 = bottom_of_wall.floor();
+            let last_bottom_of_wall: f32 = bottom_of_wall.floor().max(0.0);
             let last_top_of_wall: f32 = top_of_wall.floor();
+            let screen_column = (viewport.x + cast_column) as usize;
 
             // *************
             // FLOOR CASTING at the simplest!  Try to find ways to optimize this, you can do it!
             // *************
             // find the first bit so we can just add the width to get the
             // next row (of the same column)
-            let mut target_index: i32 = last_bottom_of_wall as i32
-                * (self.width * default_increment) as i32
-                + (default_increment * cast_column) as i32;
-            for row in last_bottom_of_wall as i32..self.projectionplaneheight as i32 {
-                let straight_distance = self.f_player_height as f32
-                    / (row as f32 - projection_plane_center_y as f32)
-                    * self.f_player_distance_to_the_projection_plane as f32;
-
-                let actual_distance: f32 =
-                    straight_distance * self.f_fish_table[cast_column as usize];
-
-                let mut y_end: i32 =
-                    (actual_distance * self.f_sin_table[cast_arc as usize]).floor() as i32;
-                let mut x_end: i32 =
-                    (actual_distance * self.f_cos_table[cast_arc as usize]).floor() as i32;
-
-                // Translate relative to viewer coordinates:
-                x_end = x_end.wrapping_add(self.f_player_x as i32);
-                y_end = y_end.wrapping_add(self.f_player_y as i32);
+            let mut target_index = (viewport.y as usize + last_bottom_of_wall as usize)
+                * self.width as usize
+                + screen_column;
+            for row in last_bottom_of_wall as i32..viewport.height as i32 {
+                let straight_distance =
+                    camera.height / (row as f32 - projection_plane_center_y) * projection_distance;
+
+                let actual_distance: f32 = straight_distance / fish;
+
+                let (x_end, y_end) = segment_at(&hops, actual_distance).point(actual_distance);
 
                 // Get the tile intersected by ray:
-                let cell_x: i32 = (x_end as f32 / self.tile_size as f32).floor() as i32;
-                let cell_y: i32 = (y_end as f32 / self.tile_size as f32).floor() as i32;
+                let cell_x: i32 = (x_end / self.tile_size).floor() as i32;
+                let cell_y: i32 = (y_end / self.tile_size).floor() as i32;
                 //println!("cell_x="+cell_x+" cell_y="+cell_y);
 
                 //Make sure the tile is within our map
@@ -1563,42 +2101,56 @@ impl GameWindow {
                     && cell_x >= 0
                     && cell_y >= 0
                 {
-                    if target_index > 0 {
-                        // Find texture
-                        let floor_texture_idx: u32 =
-                            self.map_floor_img[cell_y as usize][cell_x as usize];
-                        let floor_texture = &self.assets.textures[&floor_texture_idx];
-                        // Find offset of tile and column in texture
-                        let tile_row = (y_end as f32 % self.tile_size as f32).floor() as i32;
-                        let tile_column = (x_end as f32 % self.tile_size as f32).floor() as i32;
-                        // Pixel to draw
-                        let source_index =
-                            (tile_row as u32 * floor_texture.width * bytes_per_pixel)
-                                + (bytes_per_pixel * tile_column as u32);
-
-                        let alpha = floor_texture.data[source_index as usize + 3];
-                        if alpha != 0 {
-                            // Cheap shading trick
-                            let brightness_level = 100.0 / actual_distance;
-                            let red = floor_texture.data[source_index as usize] as f32;
-                            let green = floor_texture.data[source_index as usize + 1] as f32;
-                            let blue = floor_texture.data[source_index as usize + 2] as f32;
-
-                            // Draw the pixel
-                            argb_to_buffer!(
-                                alpha,
-                                (red * brightness_level) as u8,
-                                (green * brightness_level) as u8,
-                                (blue * brightness_level) as u8,
-                                self.canvas,
-                                target_index as usize
-                            );
+                    // Find texture
+                    let floor_texture_idx: u32 =
+                        self.map_floor_img[cell_y as usize][cell_x as usize];
+                    // wait until the texture loads, the pixel is left as it is
+                    let floor_texture = match self.assets.textures.get(&floor_texture_idx) {
+                        None => {
+                            target_index += self.width as usize;
+                            continue;
                         }
-                    }
+                        Some(floor_texture) => floor_texture,
+                    };
+                    // Find offset of tile and column in texture. The texture
+                    // covers the whole tile, no matter how big it is
+                    let tile_row = y_end.rem_euclid(self.tile_size) / self.tile_size;
+                    let tile_column = x_end.rem_euclid(self.tile_size) / self.tile_size;
+                    // One pixel covers distance/projection_plane_distance units across,
+                    // but a lot more in depth (the next row is much farther away).
+                    // We use the biggest of both to pick the mip level.
+                    let level = if self.mipmapping {
+                        let texels_per_unit =
+                            floor_texture.width.max(floor_texture.height) as f32 / self.tile_size;
+                        floor_texture.mip_level_for(
+                            (actual_distance / projection_distance)
+                                .max(actual_distance / (row as f32 - projection_plane_center_y))
+                                * texels_per_unit,
+                        )
+                    } else {
+                        0
+                    };
+                    // Pixel to draw
+                    let [red, green, blue, alpha] =
+                        floor_texture.sample(level, tile_column, tile_row, self.texture_filter);
+                    if alpha != 0 {
+                        // Cheap shading trick
+                        let brightness_level = 100.0 / actual_distance;
 
-                    // Go to the next pixel (directly under the current pixel)
-                    target_index += (default_increment * self.width) as i32;
+                        // Draw the pixel
+                        argb_to_buffer!(
+                            alpha,
+                            (red as f32 * brightness_level) as u8,
+                            (green as f32 * brightness_level) as u8,
+                            (blue as f32 * brightness_level) as u8,
+                            self.canvas,
+                            target_index,
+                            self.pixel_format
+                        );
+                    }
                 }
+                // Go to the next pixel (directly under the current pixel)
+                target_index += self.width as usize;
             }
             // *************
             // CEILING CASTING at the simplest!  Try to find ways to optimize this, you can do it!
@@ -1606,30 +2158,19 @@ impl GameWindow {
             // find the first bit so we can just add the width to get the
             // next row (of the same column)
 
-            let mut target_index: i32 = last_top_of_wall as i32
-                * (self.width * default_increment) as i32
-                + (default_increment * cast_column) as i32;
-            for row in (0..=last_top_of_wall as i32).rev() {
-                let ratio: f32 = (self.wall_height - self.f_player_height)
-                    / (projection_plane_center_y - row as f32);
-
-                let diagonal_distance = (self.f_player_distance_to_the_projection_plane
-                    * ratio
-                    * self.f_fish_table[cast_column as usize])
-                    .floor();
+            // rows below the viewport (looking up a lot) are skipped
+            let first_ceiling_row = last_top_of_wall.min(viewport.height as f32 - 1.0) as i32;
+            for row in (0..=first_ceiling_row).rev() {
+                let ratio: f32 =
+                    (self.wall_height - camera.height) / (projection_plane_center_y - row as f32);
 
-                let mut y_end: i32 =
-                    (diagonal_distance * self.f_sin_table[cast_arc as usize]).floor() as i32;
-                let mut x_end: i32 =
-                    (diagonal_distance * self.f_cos_table[cast_arc as usize]).floor() as i32;
+                let diagonal_distance = (projection_distance * ratio / fish).floor();
 
-                // Translate relative to viewer coordinates:
-                x_end = x_end.wrapping_add(self.f_player_x as i32);
-                y_end = y_end.wrapping_add(self.f_player_y as i32);
+                let (x_end, y_end) = segment_at(&hops, diagonal_distance).point(diagonal_distance);
 
                 // Get the tile intersected by ray:
-                let cell_x: i32 = (x_end as f32 / self.tile_size as f32).floor() as i32;
-                let cell_y: i32 = (y_end as f32 / self.tile_size as f32).floor() as i32;
+                let cell_x: i32 = (x_end / self.tile_size).floor() as i32;
+                let cell_y: i32 = (y_end / self.tile_size).floor() as i32;
                 //println!("cell_x="+cell_x+" cell_y="+cell_y);
 
                 //Make sure the tile is within our map
@@ -1641,46 +2182,98 @@ impl GameWindow {
                     // Find the texture
                     let ceiling_texture_idx: u32 =
                         self.map_ceiling_img[cell_y as usize][cell_x as usize];
-                    let ceiling_texture = &self.assets.textures[&ceiling_texture_idx];
+                    // wait until the texture loads
+                    let ceiling_texture = match self.assets.textures.get(&ceiling_texture_idx) {
+                        None => continue,
+                        Some(ceiling_texture) => ceiling_texture,
+                    };
                     // Find offset of tile and column in texture
-                    let tile_row: i32 = (y_end as f32 % self.tile_size as f32).floor() as i32;
-                    let tile_column: i32 = (x_end as f32 % self.tile_size as f32).floor() as i32;
+                    let tile_row = y_end.rem_euclid(self.tile_size) / self.tile_size;
+                    let tile_column = x_end.rem_euclid(self.tile_size) / self.tile_size;
+                    // same footprint estimation as the floor
+                    let level = if self.mipmapping {
+                        let texels_per_unit = ceiling_texture.width.max(ceiling_texture.height)
+                            as f32
+                            / self.tile_size;
+                        ceiling_texture.mip_level_for(
+                            (diagonal_distance / projection_distance)
+                                .max(diagonal_distance / (projection_plane_center_y - row as f32))
+                                * texels_per_unit,
+                        )
+                    } else {
+                        0
+                    };
                     // Pixel to draw
-                    let source_index = (tile_row as u32 * ceiling_texture.width * bytes_per_pixel)
-                        + (bytes_per_pixel * tile_column as u32);
-
-                    let alpha = ceiling_texture.data[source_index as usize + 3];
+                    let [red, green, blue, alpha] =
+                        ceiling_texture.sample(level, tile_column, tile_row, self.texture_filter);
                     if alpha != 0 {
                         // Cheap shading trick
                         let brightness_level = 100.0 / diagonal_distance;
-                        let red = ceiling_texture.data[source_index as usize] as f32;
-                        let green = ceiling_texture.data[source_index as usize + 1] as f32;
-                        let blue = ceiling_texture.data[source_index as usize + 2] as f32;
 
                         // Draw the pixel
                         argb_to_buffer!(
                             alpha,
-                            (red * brightness_level) as u8,
-                            (green * brightness_level) as u8,
-                            (blue * brightness_level) as u8,
+                            (red as f32 * brightness_level) as u8,
+                            (green as f32 * brightness_level) as u8,
+                            (blue as f32 * brightness_level) as u8,
                             self.canvas,
-                            target_index as usize
+                            (viewport.y as usize + row as usize) * self.width as usize
+                                + screen_column,
+                            self.pixel_format
                         );
                     }
-
-                    // Go to the next pixel (directly above the current pixel)
-                    target_index -= (default_increment * self.width) as i32;
                 }
             }
 
-            // TRACE THE NEXT RAY
-            cast_arc += 1;
-            if cast_arc >= self.arc_angle360 {
-                cast_arc -= self.arc_angle360;
+            // the lift doors the ray went under, from the farthest
+            for (hit, lift) in lifted.iter().rev() {
+                self.draw_wall_hit(camera, viewport, cast_column, hit, fish, *lift);
             }
-            cast_angle = arc_to_rad(cast_arc, self.projectionplanewidth);
         }
     }
+
+    /**
+     * Draws the wall slice of a ray hit in a column and returns its top
+     * and bottom rows. `lift` moves the slice up by that part of its
+     * height, it's cut at the ceiling (for lift doors).
+     */
+    fn draw_wall_hit(
+        &mut self,
+        camera: &Camera,
+        viewport: Viewport,
+        column: u32,
+        hit: &RayHit,
+        fish: f32,
+        lift: f32,
+    ) -> (f32, f32) {
+        let mut dist = hit.distance * fish;
+        let (top_of_wall, bottom_of_wall) = self.project_wall(camera, viewport, dist);
+
+        // Add simple shading so that farther wall slices appear darker.
+        // use arbitrary value of the farthest distance.
+        dist = dist.floor();
+        // Trick to give different shades between vertical and horizontal (you could also use different textures for each if you wish to)
+        let brightness_level = if hit.vertical {
+            self.base_light_value as f32 / dist
+        } else {
+            (self.base_light_value as f32 - 50.0) / dist
+        };
+        let height = bottom_of_wall - top_of_wall;
+        self.draw_wall_slice_rectangle_tinted(
+            viewport,
+            column as f32,
+            top_of_wall - lift * height,
+            height + 1.0,
+            hit.u,
+            (0.0, 1.0),
+            brightness_level,
+            self.wall_texture(hit),
+            Some(hit.face),
+            top_of_wall,
+        );
+        (top_of_wall, bottom_of_wall)
+    }
+
     /*
         fn sprite_is_visible(self, sprite_x, sprite_y, radius) {
             //https://bheisler.github.io/post/writing-raytracer-in-rust-part-1/
@@ -1693,110 +2286,86 @@ impl GameWindow {
         }
     */
 
-    fn draw_objects(&mut self) {
+    // Draws the sprites seen by the camera, from the farthest to the closest,
+    // hidden behind the walls of the z-buffer
+    fn draw_objects(&mut self, camera: &Camera, viewport: Viewport) {
         // First: recalculate objects distances and reorder the array
-        for obj in self.drawable_objects.iter_mut() {
-            obj.real_distance = (self.f_player_x - obj.x).hypot(self.f_player_y - obj.y);
-            obj.angle = ((obj.y - self.f_player_y) as f32).atan2((obj.x - self.f_player_x) as f32);
-            // for sorting the drawables,
-            // we only care about the x component for the distance
-            // so we draw from the player to its front
-            obj.x_distance = obj.angle.sin().abs() * obj.real_distance;
-            obj.angle = obj.angle;
-            if obj.angle > self.angle360 {
-                obj.angle -= self.angle360;
-            } else if obj.angle < self.angle0 {
-                obj.angle += self.angle360;
-            }
+        let mut drawables: Vec<Drawable> = self
+            .entities
+            .iter()
+            .filter_map(|(_, entity)| Drawable::of(entity))
+            .collect();
+        for obj in drawables.iter_mut() {
+            obj.real_distance = (camera.x - obj.x).hypot(camera.y - obj.y);
+            obj.angle = (obj.y - camera.y).atan2(obj.x - camera.x);
+            // For sorting the drawables and checking the z-buffer we only
+            // care about the distance along the camera direction
+            obj.x_distance = (obj.angle - camera.angle).cos() * obj.real_distance;
         }
-        //self.drawable_objects.sort_by(|a, b| b.distance.partial_cmp(&a.distance).unwrap_or(core::cmp::Ordering::Equal));
-
-        // print
-        let column_unit: f32 = (self.projectionplanewidth as f32) / self.angle60; // the degrees
-        let half_screen_column = column_unit * self.angle30;
 
-        let min_visible_angle;
-        let max_visible_angle;
-
-        /* for the sake of simplification lets asume all objects in front of us as drawable (180 of view) */
-        if self.f_player_angle < self.angle90 {
-            min_visible_angle = self.f_player_angle - self.angle90 + self.angle360;
-        } else {
-            min_visible_angle = self.f_player_angle - self.angle90;
-        }
-        if self.f_player_angle > self.angle270 {
-            max_visible_angle = self.f_player_angle + self.angle90 - self.angle360;
-        } else {
-            max_visible_angle = self.f_player_angle + self.angle90;
-        }
+        let projection_distance = camera.projection_distance(viewport.width);
+        let projection_plane_center_y = camera.horizon(viewport.height);
 
         let mut tmp_objects_buffer: BTreeSet<Drawable> = BTreeSet::new(); // temporary array to sort all visible objects
-        for obj in self.drawable_objects.iter() {
-            if obj.real_distance > 1.0 &&  //object distance must be at least 1 pixel, because real_height uses that and x/0 is undefined
-            ((obj.angle >= min_visible_angle && obj.angle <= max_visible_angle)
-                || (max_visible_angle < min_visible_angle
-                    && (obj.angle <= min_visible_angle || obj.angle >= max_visible_angle)))
-            {
-                tmp_objects_buffer.insert(obj.clone());
+        for obj in drawables.into_iter() {
+            // only the objects in front of the camera. They must be at least
+            // 1 pixel away, because real_height uses that and x/0 is undefined
+            if obj.x_distance > 1.0 {
+                tmp_objects_buffer.insert(obj);
             }
         }
 
         for obj in tmp_objects_buffer.iter().rev() {
-            let ratio = self.f_player_distance_to_the_projection_plane / obj.real_distance;
-            let bottom_of_wall = ratio * (self.f_player_height - obj.z + obj.height as f32 / 2.0)
-                + self.f_projection_plane_ycenter;
-            let real_height: f32 = self.f_player_distance_to_the_projection_plane
-                * obj.height as f32
-                / obj.real_distance;
+            let ratio = projection_distance / obj.x_distance;
+            let bottom_of_wall = ratio * (camera.height - obj.z + obj.height as f32 / 2.0)
+                + projection_plane_center_y;
+            let real_height: f32 = projection_distance * obj.height as f32 / obj.x_distance;
 
             let top_of_wall = bottom_of_wall - real_height;
 
-            let player_angle = self.f_player_angle;
-            let delta_angle;
-            if player_angle > self.angle270 && obj.angle < self.angle90 {
-                delta_angle = -(obj.angle + self.angle360 - player_angle);
-            } else if obj.angle > self.angle270 && player_angle < self.angle90 {
-                delta_angle = player_angle + self.angle360 - obj.angle;
-            } else {
-                delta_angle = player_angle - obj.angle;
-            }
-
-            // this is the middle column of the object (if it were in screen)
+            // this is the middle column of the object (if it were in the viewport)
             // it can be negative, because the center of the object may be outside
             // but that doesn't mean all of it is outside.
-            let obj_cast_column = half_screen_column - delta_angle * column_unit;
+            let delta_angle = obj.angle - camera.angle;
+            let obj_cast_column =
+                viewport.width as f32 / 2.0 + delta_angle.tan() * projection_distance;
 
             let total_image_columns = obj.width as f32 * ratio;
             if total_image_columns > 1.0 &&
-                obj_cast_column < self.projectionplanewidth as f32 + total_image_columns/2.0 && // is visible on the right side
-                    (obj_cast_column > 0.0 || obj_cast_column > -total_image_columns/2.0)
+                obj_cast_column < viewport.width as f32 + total_image_columns/2.0 && // is visible on the right side
+                    obj_cast_column > -total_image_columns/2.0
             {
                 // is visible on the left side
                 //calculate the field of view so we don´t try to draw something that is
                 //hidden
                 let min_cast_column = (obj_cast_column - total_image_columns / 2.0).max(0.0);
-                let max_cast_column = (obj_cast_column + total_image_columns / 2.0)
-                    .min(self.projectionplanewidth as f32);
-                let increment = obj.texture_width as f32 / total_image_columns;
+                let max_cast_column =
+                    (obj_cast_column + total_image_columns / 2.0).min(viewport.width as f32);
+                // the whole frame is stretched over the object width,
+                // whatever the texture size is
+                let (left, top, right, bottom) = obj.frame;
+                let increment = 1.0 / total_image_columns;
                 let mut x_image_column;
                 if (obj_cast_column - total_image_columns / 2.0) <= 0.0 {
                     let delta = obj_cast_column - total_image_columns / 2.0;
-                    x_image_column = (total_image_columns - delta) * increment;
+                    x_image_column = -delta * increment;
                 } else {
                     x_image_column = 0.0;
                 }
                 for cast_column in min_cast_column.floor() as i32..max_cast_column.floor() as i32 {
-                    // FIXME this check fails because distance is now only x value!
-                    if self.f_player_to_wall_dist[cast_column as usize] > obj.real_distance {
+                    if self.z_buffer[cast_column as usize] > obj.x_distance {
                         // print the column
                         self.draw_wall_slice_rectangle_tinted(
+                            viewport,
                             cast_column as f32,
                             top_of_wall,
-                            1.0,
                             (bottom_of_wall - top_of_wall) + 1.0,
-                            x_image_column,
+                            left + x_image_column * (right - left),
+                            (top, bottom),
                             self.base_light_value as f32 / obj.real_distance,
                             obj.texture_id,
+                            None,
+                            0.0,
                         );
                     }
                     // now lets draw the next column
@@ -1806,180 +2375,870 @@ impl GameWindow {
         }
     }
 
-    pub fn move_doors_demo(&mut self) {
-        if self.door_opening {
-            self.door_positions[0] += 1;
-        } else {
-            self.door_positions[0] -= 1;
-        }
-        if self.door_positions[0] == self.tile_size as u8 {
-            self.door_opening = false;
-        } else if self.door_positions[0] == 0x0 {
-            self.door_opening = true;
-        }
+    /**
+     * Draws the weapon in the player's hands and the crosshair over the
+     * 3D view. The weapon rests at the bottom, a bit to the right of the
+     * center, at the frame of what it's doing, and moves with
+     * Weapons::offset.
+     */
+    fn draw_weapon(&mut self, viewport: Viewport) {
+        let sprite = self.weapons.sprite();
+        let (offset_x, offset_y) = self.weapons.offset();
+        let height = viewport.height as f32 / 3.0;
+        let width = height * sprite.width as f32 / sprite.height.max(1) as f32;
+        let x = viewport.x as f32 + viewport.width as f32 / 2.0 + width * (0.1 + offset_x);
+        // the lower part of it is below the view, like the arm holding it
+        let y = (viewport.y + viewport.height) as f32 - height * (0.85 - offset_y);
+        self.draw_screen_image(viewport, sprite, (x, y, width, height), None);
+
+        let (center_x, center_y) = self.crosshair();
+        let size = (viewport.height / 16).max(8) as f32;
+        let color = self.pixel_format.pack(255, 220, 255, 220);
+        let crosshair = Sprite::new(self.weapons.current().crosshair_id, 1, 1);
+        let (x, y) = (center_x as f32 - size / 2.0, center_y as f32 - size / 2.0);
+        self.draw_screen_image(viewport, crosshair, (x, y, size, size), Some(color));
     }
 
-    // This function is called every certain interval (see self.frameRate) to handle input and render the screen
-    fn update(&mut self) {
-        self.draw_background();
-        self.raycast();
-        self.draw_objects();
-        self.draw_overhead_map();
-        self.draw_player_pov_on_overhead_map(0, 0);
-        //self.blitOffscreenCanvas(); //we are writting directly to the buffer, then we copy. no need for this
-
-        if self.f_key_left {
-            self.f_player_arc -= self.arc_angle5;
-            if self.f_player_arc < self.arc_angle0 {
-                self.f_player_arc += self.arc_angle360;
-            }
-            self.f_player_angle = arc_to_rad(self.f_player_arc, self.projectionplanewidth)
-        }
-        // rotate right
-        else if self.f_key_right {
-            self.f_player_arc += self.arc_angle5;
-            if self.f_player_arc >= self.arc_angle360 {
-                self.f_player_arc -= self.arc_angle360;
+    /**
+     * Draws the frame of a sprite stretched over a rectangle of the
+     * screen, (x, y, width, height) from its top left corner, clipped to
+     * the viewport. Transparent texels are skipped, `tint` draws the
+     * others in a single color. Missing textures draw nothing.
+     */
+    fn draw_screen_image(
+        &mut self,
+        viewport: Viewport,
+        sprite: Sprite,
+        (x, y, width, height): (f32, f32, f32, f32),
+        tint: Option<u32>,
+    ) {
+        let texture = match self.assets.textures.get(&sprite.texture_id) {
+            Some(texture) => texture,
+            None => return,
+        };
+        let (frame_left, frame_top, frame_right, frame_bottom) = sprite.frame_rect();
+        let left = x.max(viewport.x as f32) as u32;
+        let top = y.max(viewport.y as f32) as u32;
+        let right = (x + width)
+            .min((viewport.x + viewport.width) as f32)
+            .max(0.0) as u32;
+        let bottom = (y + height)
+            .min((viewport.y + viewport.height) as f32)
+            .max(0.0) as u32;
+        for screen_y in top..bottom {
+            let v = (screen_y as f32 + 0.5 - y) / height;
+            let v = frame_top + v * (frame_bottom - frame_top);
+            for screen_x in left..right {
+                let u = (screen_x as f32 + 0.5 - x) / width;
+                let u = frame_left + u * (frame_right - frame_left);
+                let [r, g, b, a] = texture.sample(0, u, v, TextureFilter::Nearest);
+                if a < 128 {
+                    continue;
+                }
+                let index = screen_y as usize * self.width as usize + screen_x as usize;
+                match tint {
+                    Some(color) => self.canvas[index] = color,
+                    None => {
+                        argb_to_buffer!(255, r, g, b, self.canvas, index, self.pixel_format);
+                    }
+                }
             }
-            self.f_player_angle = arc_to_rad(self.f_player_arc, self.projectionplanewidth)
         }
+    }
 
-        //  _____     _
-        // |\ arc     |
-        // |  \       y
-        // |    \     |
-        //            -
-        // |--x--|
-        //
-        //  sin(arc)=y/diagonal
-        //  cos(arc)=x/diagonal   where diagonal=speed
-        let player_xdir: f32 = self.f_cos_table[self.f_player_arc as usize];
-        let player_ydir: f32 = self.f_sin_table[self.f_player_arc as usize];
-
-        let mut dx: f32 = 0.0;
-        let mut dy: f32 = 0.0;
-        // move forward
-        if self.f_key_up {
-            dx = (player_xdir * self.f_player_speed).round();
-            dy = (player_ydir * self.f_player_speed).round();
-        }
-        // move backward
-        else if self.f_key_down {
-            dx = -(player_xdir * self.f_player_speed).round();
-            dy = -(player_ydir * self.f_player_speed).round();
-        }
-
-        let mut new_player_x = self.f_player_x + dx;
-        let mut new_player_y = self.f_player_y + dy;
-
-        let player_xcell = (self.f_player_x / self.tile_size).floor();
-        let player_ycell = (self.f_player_y / self.tile_size).floor();
-
-        let min_distance_to_wall = 8.0;
-
-        // compute position relative to cell (ie: how many pixel from edge of cell)
-        let new_player_xcell_offset = new_player_x % self.tile_size;
-        let new_player_ycell_offset = new_player_y % self.tile_size;
-        // make sure the player don't bump into walls
-        //we check if the next position is too close to the border
-        //from the current or the next cell and back the player to the previous position
-        if dx > 0.5 {
-            // moving right
-            if self.f_map[player_ycell as usize][(player_xcell as i32 + 1) as usize] & 0xf != 0
-                && (new_player_xcell_offset < (min_distance_to_wall)
-                    || new_player_xcell_offset > (self.tile_size - min_distance_to_wall))
-            {
-                // back player up
-                new_player_x = self.f_player_x;
+    /**
+     * Opens or closes the first door in front of the player, or pushes
+     * the pushwall there, if it's close. Locked doors need their key in
+     * the inventory. Pushwalls go away from the side the player is on.
+     * Switches on the wall in front are turned on or off.
+     */
+    fn use_in_front(&mut self) {
+        let (dir_x, dir_y) = (self.f_player_angle.cos(), self.f_player_angle.sin());
+        let mut previous = (
+            (self.f_player_x / self.tile_size).floor() as i32,
+            (self.f_player_y / self.tile_size).floor() as i32,
+        );
+        let mut distance = 0.0;
+        while distance <= self.doors.reach {
+            let x = self.f_player_x + dir_x * distance;
+            let y = self.f_player_y + dir_y * distance;
+            let cell_x = (x / self.tile_size).floor() as i32;
+            let cell_y = (y / self.tile_size).floor() as i32;
+            let mut direction = (cell_x - previous.0, cell_y - previous.1);
+            // it went in through a corner, the side it's looking at more
+            // directly wins
+            if direction.0 != 0 && direction.1 != 0 {
+                if dir_x.abs() > dir_y.abs() {
+                    direction.1 = 0;
+                } else {
+                    direction.0 = 0;
+                }
             }
-        } else if dx < 0.5 {
-            // moving left
-            if self.f_map[player_ycell as usize][(player_xcell as i32 - 1) as usize] & 0xf != 0
-                && (new_player_xcell_offset < (min_distance_to_wall)
-                    || new_player_xcell_offset > (self.tile_size - min_distance_to_wall))
-            {
-                // back player up
-                new_player_x = self.f_player_x;
+            match self.map_cell(cell_x, cell_y) {
+                Some(cell) if cell & 0xf == 0 => {}
+                Some(cell) if cell & 0xf == 2 => {
+                    match KeyColor::lock_of(cell) {
+                        Some(key) if !self.inventory.has_key(key) => {
+                            self.hud
+                                .show_message(&format!("YOU NEED THE {} KEY", key.name()), 2.0);
+                            self.sound_events.push(SoundEvent::DoorLocked);
+                        }
+                        _ => {
+                            if let Some(door) = self.doors.get_mut(door_index(cell)) {
+                                door.activate();
+                            }
+                        }
+                    }
+                    return;
+                }
+                Some(cell) if cell & 0xf == 3 => {
+                    // a block that can't move stays a pushwall, so it can
+                    // still be pushed from another side
+                    let (next_x, next_y) = (cell_x + direction.0, cell_y + direction.1);
+                    let can_move = self
+                        .map_cell(next_x, next_y)
+                        .is_some_and(|next| next & 0xf == 0)
+                        && !self.pushwalls.is_taken(next_x, next_y);
+                    if can_move {
+                        let texture_id = self.map_wall_img[cell_y as usize][cell_x as usize];
+                        self.f_map[cell_y as usize][cell_x as usize] = 0;
+                        self.pushwalls.push(
+                            cell_x,
+                            cell_y,
+                            direction,
+                            push_distance(cell),
+                            texture_id,
+                        );
+                    }
+                    return;
+                }
+                Some(_) => {
+                    // the face of the wall the player is looking at
+                    let side = match direction {
+                        (1, 0) => Side::West,
+                        (-1, 0) => Side::East,
+                        (0, 1) => Side::North,
+                        _ => Side::South,
+                    };
+                    let face = WallFace {
+                        cell_x,
+                        cell_y,
+                        side,
+                    };
+                    if let Some(switch) =
+                        self.switches.iter_mut().find(|switch| switch.face == face)
+                    {
+                        switch.toggle(&mut self.events);
+                    }
+                    return;
+                }
+                None => return,
             }
+            previous = (cell_x, cell_y);
+            distance += 4.0;
         }
+    }
 
-        if dy < -0.5 {
-            // moving up
-            if self.f_map[(player_ycell as i32 - 1) as usize][player_xcell as i32 as usize] & 0xf
-                != 0
-                && (new_player_ycell_offset > (self.tile_size as f32 - min_distance_to_wall)
-                    || new_player_ycell_offset < (min_distance_to_wall))
-            {
-                // back player up
-                new_player_y = self.f_player_y;
-            }
-        } else if dy > 0.5 {
-            // moving down
-            if self.f_map[(player_ycell as i32 + 1) as usize][player_xcell as usize] & 0xf != 0
-                && (new_player_ycell_offset > (self.tile_size - min_distance_to_wall)
-                    || new_player_ycell_offset < (min_distance_to_wall))
-            {
-                // back player up
-                new_player_y = self.f_player_y;
+    // does what the events fired since the last tick say
+    fn run_events(&mut self) {
+        for action in self.events.dispatch() {
+            match action {
+                Action::OpenDoor(index) => {
+                    if let Some(door) = self.doors.get_mut(index) {
+                        door.open();
+                    }
+                }
+                Action::CloseDoor(index) => {
+                    if let Some(door) = self.doors.get_mut(index) {
+                        door.close();
+                    }
+                }
+                Action::SetLight(value) => self.base_light_value = value,
+                Action::Spawn(entity) => {
+                    self.entities.spawn(entity);
+                }
+                Action::SetWallTexture {
+                    cell_x,
+                    cell_y,
+                    texture_id,
+                } => {
+                    if self.map_cell(cell_x, cell_y).is_some() {
+                        self.map_wall_img[cell_y as usize][cell_x as usize] = texture_id;
+                    }
+                }
+                Action::Teleport { x, y, angle } => self.set_camera(x, y, angle),
+                Action::EndLevel => {
+                    self.level_ended = true;
+                    self.hud.show_message("LEVEL COMPLETE", 5.0);
+                }
+                Action::Message(text) => self.hud.show_message(&text, 3.0),
+                // the bus follows them
+                Action::Fire(_) => {}
             }
         }
-        //finally... back up any invalid movement that was not saved on the previous computation
-        let new_player_xcell = (new_player_x / self.tile_size).floor();
-        let new_player_ycell = (new_player_y / self.tile_size).floor();
+    }
 
-        if self.f_map[new_player_ycell as usize][new_player_xcell as usize] & 0xf != 0 {
-            //the new cell is not allowed
-            if new_player_xcell != player_xcell && (dx >= 0.5 || dx <= -0.5) {
-                //moving left or right caused us to move to an invalid cell
-                new_player_x = self.f_player_x; // undo the movement in this direction
+    /**
+     * Runs what every entity does by itself for one tick, in the order
+     * they were spawned, and takes the dead ones out of the world.
+     */
+    fn think_entities(&mut self) {
+        for id in self.entities.ids() {
+            // taken out while it runs, to look at the rest of the game
+            let mut behaviour = match self.entities.get_mut(id) {
+                Some(entity) => {
+                    if let Some(animation) = entity.animation.as_mut() {
+                        animation.tick();
+                    }
+                    std::mem::replace(&mut entity.behaviour, Behaviour::Still)
+                }
+                None => continue,
+            };
+            match &mut behaviour {
+                Behaviour::Still => {}
+                &mut Behaviour::Projectile {
+                    dx,
+                    dy,
+                    damage,
+                    owner,
+                } => self.move_projectile(id, dx, dy, damage, owner),
+                Behaviour::Enemy(enemy) => self.think_enemy(id, enemy),
+                Behaviour::Effect { ticks } => {
+                    if *ticks == 0 {
+                        self.entities.despawn(id);
+                    }
+                    *ticks = ticks.saturating_sub(1);
+                }
             }
-
-            if new_player_ycell != player_ycell && (dy < -0.5 || dy > 0.5) {
-                new_player_y = self.f_player_y; //undo the movement
+            if let Some(entity) = self.entities.get_mut(id) {
+                entity.behaviour = behaviour;
             }
         }
-
-        self.f_player_x = new_player_x;
-        self.f_player_y = new_player_y;
-
-        if self.f_key_look_up {
-            self.f_projection_plane_ycenter += 15.0;
-        } else if self.f_key_look_down {
-            self.f_projection_plane_ycenter -= 15.0;
+        // dead enemies go away by themselves
+        let dead: Vec<EntityId> = self
+            .entities
+            .iter()
+            .filter(|(_, entity)| {
+                entity.is_dead() && !matches!(entity.behaviour, Behaviour::Enemy(_))
+            })
+            .map(|(id, _)| id)
+            .collect();
+        for id in dead {
+            self.entities.despawn(id);
         }
+        self.update_object_hash();
+    }
 
-        if self.f_projection_plane_ycenter < -(self.projectionplaneheight) {
-            self.f_projection_plane_ycenter = -(self.projectionplaneheight);
-        } else if self.f_projection_plane_ycenter >= (self.projectionplaneheight as f32 * 1.5) {
-            self.f_projection_plane_ycenter = self.projectionplaneheight as f32 * 1.5 - 1.0;
+    /**
+     * Runs the AI of an enemy for one tick: it looks for the player, walks
+     * after it opening the doors in its way, and attacks it. Dead enemies
+     * sink into the floor and are taken out of the world.
+     */
+    fn think_enemy(&mut self, id: EntityId, enemy: &mut Enemy) {
+        let (x, y, health) = match self.entities.get(id) {
+            Some(entity) => (entity.x, entity.y, entity.health.unwrap_or(0)),
+            None => return,
+        };
+        let (player_x, player_y) = (self.f_player_x, self.f_player_y);
+        let senses = Senses {
+            sees_player: self.line_of_sight(x, y, player_x, player_y),
+            player_distance: (player_x - x).hypot(player_y - y),
+            player_angle: (player_y - y).atan2(player_x - x),
+            health,
+        };
+        let state = enemy.state;
+        match enemy.think(senses, &mut self.rng) {
+            Intent::Stand => {}
+            Intent::Chase => self.chase(id, enemy, senses),
+            Intent::Attack(damage) => self.hurt_player(damage),
         }
-
-        if self.f_key_fly_up {
-            self.f_player_height += 1.0;
-        } else if self.f_key_fly_down {
-            self.f_player_height -= 1.0;
+        if enemy.state != state {
+            match enemy.state {
+                EnemyState::Alert => self.sound_events.push(SoundEvent::EnemyAlert),
+                EnemyState::Attack => self.sound_events.push(SoundEvent::EnemyAttack),
+                EnemyState::Dead => self.sound_events.push(SoundEvent::EnemyDeath),
+                _ => {}
+            }
         }
-
-        if self.f_player_height < -5.0 {
-            //originally -5
-            self.f_player_height = -5.0;
-        } else if self.f_player_height > self.wall_height - 5.0 {
-            self.f_player_height = self.wall_height - 5.0;
+        let frame = enemy.frame(senses.player_angle);
+        let entity = match self.entities.get_mut(id) {
+            Some(entity) => entity,
+            None => return,
+        };
+        if let Some(sprite) = entity.sprite.as_mut() {
+            sprite.frame = frame;
+        }
+        if enemy.state == EnemyState::Dead {
+            // the body can't be touched, it would be a pickup without blocking
+            entity.blocking = false;
+            entity.shape = None;
+            entity.z -= 1.0;
+            let height = entity.sprite.map_or(0.0, |sprite| sprite.height as f32);
+            if entity.z < -height / 2.0 {
+                self.entities.despawn(id);
+            }
         }
     }
 
-    fn handle_keys(&mut self, window: &Window) {
-        // UP keypad
+    /**
+     * Moves an enemy one tick towards the player: straight to it when it
+     * sees it, along a path over the map when it doesn't. A door in the
+     * way is opened and it waits for it.
+     */
+    fn chase(&mut self, id: EntityId, enemy: &mut Enemy, senses: Senses) {
+        let (x, y) = match self.entities.get(id) {
+            Some(entity) => entity.position(),
+            None => return,
+        };
+        let radius = enemy.kind.radius();
+        // it doesn't walk into the player
+        if senses.player_distance <= radius + self.f_player_radius + 4.0 {
+            return;
+        }
+        let (target_x, target_y) = if senses.sees_player {
+            enemy.path.clear();
+            // it looks for a path as soon as it loses sight of the player
+            enemy.repath = 0;
+            (self.f_player_x, self.f_player_y)
+        } else {
+            let cell = (
+                (x / self.tile_size).floor() as i32,
+                (y / self.tile_size).floor() as i32,
+            );
+            // a search that found nothing waits too, the player may be
+            // somewhere it can't get to and it would search every tick
+            if enemy.repath == 0 {
+                let goal = (
+                    (self.f_player_x / self.tile_size).floor() as i32,
+                    (self.f_player_y / self.tile_size).floor() as i32,
+                );
+                enemy.path = find_path(cell, goal, MAX_PATH_CELLS, |x, y| self.walk_cost(x, y))
+                    .unwrap_or_default();
+                enemy.repath = REPATH_TICKS;
+            } else {
+                enemy.repath -= 1;
+            }
+            let center = |(cell_x, cell_y): (i32, i32)| {
+                (
+                    (cell_x as f32 + 0.5) * self.tile_size,
+                    (cell_y as f32 + 0.5) * self.tile_size,
+                )
+            };
+            // the cells it got to are done
+            while let Some(&next) = enemy.path.first() {
+                let (center_x, center_y) = center(next);
+                if (center_x - x).hypot(center_y - y) > enemy.kind.speed() {
+                    break;
+                }
+                enemy.path.remove(0);
+                // at the end of the path, it looks where the player went
+                if enemy.path.is_empty() {
+                    enemy.repath = 0;
+                }
+            }
+            let next = match enemy.path.first() {
+                Some(&next) => next,
+                None => return,
+            };
+            if let Some(cell) = self.map_cell(next.0, next.1) {
+                if cell & 0xf == 2 {
+                    if let Some(door) = self.doors.get_mut(door_index(cell)) {
+                        door.open();
+                    }
+                }
+            }
+            center(next)
+        };
+        let angle = (target_y - y).atan2(target_x - x);
+        let step = enemy.kind.speed().min((target_x - x).hypot(target_y - y));
+        enemy.facing = angle;
+        let (old_x, old_y) = (x, y);
+        let (x, y) = self.move_body(
+            x,
+            y,
+            angle.cos() * step,
+            angle.sin() * step,
+            radius,
+            Some(id),
+        );
+        let cell = |x: f32, y: f32| {
+            (
+                (x / self.tile_size).floor() as i32,
+                (y / self.tile_size).floor() as i32,
+            )
+        };
+        let entered = cell(x, y) != cell(old_x, old_y);
+        // the teleporters for actors send it away when it walks into them
+        let (x, y) = match self.teleport_destination(x, y, true) {
+            Some((x, y, angle)) if entered => {
+                enemy.facing = angle.unwrap_or(enemy.facing);
+                // the path was from where it was
+                enemy.path.clear();
+                enemy.repath = 0;
+                (x, y)
+            }
+            _ => (x, y),
+        };
+        if let Some(entity) = self.entities.get_mut(id) {
+            entity.x = x;
+            entity.y = y;
+        }
+    }
+
+    // what walking into a cell costs the enemies, None for walls and locked doors
+    fn walk_cost(&self, cell_x: i32, cell_y: i32) -> Option<u32> {
+        let cell = self.map_cell(cell_x, cell_y)?;
+        match cell & 0xf {
+            0 => Some(1),
+            // it has to wait for the door to open
+            2 if KeyColor::lock_of(cell).is_none() => Some(3),
+            _ => None,
+        }
+    }
+
+    /**
+     * Moves the weapon one tick with what the player pressed, and applies
+     * its attacks. Hitscan weapons hit the first entity or wall straight
+     * ahead, melee ones only what is in reach in front of the player.
+     */
+    fn use_weapon(&mut self, moving: bool) {
+        let input = WeaponInput {
+            fire: std::mem::take(&mut self.f_key_fire),
+            reload: std::mem::take(&mut self.f_key_reload),
+            switch_to: self.weapon_switch.take(),
+        };
+        match self.weapons.tick(input, moving) {
+            Some(WeaponEvent::Attack(weapon)) => match weapon.kind {
+                AttackKind::Hitscan => {
+                    self.sound_events.push(SoundEvent::WeaponFire);
+                    if !self.hit_along(self.f_player_angle, f32::INFINITY, weapon.damage) {
+                        let (x, y) = self.crosshair();
+                        self.spawn_decal_at_screen(x, y, BULLET_HOLE_TEXTURE_ID, 0.12);
+                    }
+                }
+                AttackKind::Melee { range } => {
+                    self.sound_events.push(SoundEvent::WeaponSwing);
+                    // the swing covers a bit to the sides, it hits one thing
+                    for spread in [0.0, -0.25, 0.25] {
+                        let angle = self.f_player_angle + spread;
+                        if self.hit_along(angle, range, weapon.damage) {
+                            break;
+                        }
+                    }
+                }
+            },
+            Some(WeaponEvent::Empty) => self.sound_events.push(SoundEvent::WeaponEmpty),
+            Some(WeaponEvent::Reload) => self.sound_events.push(SoundEvent::WeaponReload),
+            Some(WeaponEvent::Switch) | None => {}
+        }
+        self.hud.ammo = self.weapons.loaded().map(|rounds| rounds as i32);
+    }
+
+    /**
+     * Hurts the first entity that can be hurt along a ray from the player,
+     * if it's closer than `range` and no wall is in the way, and leaves a
+     * puff of blood where it was hit. Returns true if it hit one.
+     */
+    fn hit_along(&mut self, angle: f32, range: f32, damage: i32) -> bool {
+        // portals don't let attacks through
+        let (x, y) = (self.f_player_x, self.f_player_y);
+        let reach = self
+            .cast_straight(x, y, angle)
+            .map_or(range, |hit| hit.distance.min(range));
+        let (sin, cos) = angle.sin_cos();
+        let target = self
+            .entities
+            .iter()
+            .filter(|(_, entity)| entity.blocking && entity.health.is_some() && !entity.is_dead())
+            .filter_map(|(id, entity)| {
+                let radius = entity.shape?.extent();
+                // how far along the ray its center is, and how far from it
+                let along = (entity.x - x) * cos + (entity.y - y) * sin;
+                let across = ((entity.x - x) * sin - (entity.y - y) * cos).abs();
+                if along <= 0.0 || across > radius {
+                    return None;
+                }
+                let distance = (along - (radius * radius - across * across).sqrt()).max(0.0);
+                (distance <= reach).then_some((id, distance))
+            })
+            .min_by(|a, b| a.1.total_cmp(&b.1));
+        let (id, distance) = match target {
+            Some(target) => target,
+            None => return false,
+        };
+        if let Some(entity) = self.entities.get_mut(id) {
+            entity.hurt(damage);
+        }
+        let puff = Entity {
+            behaviour: Behaviour::Effect { ticks: 10 },
+            ..Entity::decoration(
+                x + cos * distance,
+                y + sin * distance,
+                self.vertical.eye(),
+                16,
+                16,
+                BLOOD_TEXTURE_ID,
+            )
+        };
+        self.entities.spawn(puff);
+        true
+    }
+
+    /**
+     * Moves a projectile one tick. It's taken out of the world when it
+     * hits a wall, a door or a blocking entity, and hurts the entity.
+     */
+    fn move_projectile(
+        &mut self,
+        id: EntityId,
+        dx: f32,
+        dy: f32,
+        damage: i32,
+        owner: Option<EntityId>,
+    ) {
+        // projectiles are tiny circles, they don't have a shape to not
+        // block or be touched by anything
+        const RADIUS: f32 = 1.0;
+        let (x, y) = match self.entities.get(id) {
+            Some(entity) => entity.position(),
+            None => return,
+        };
+        let (end_x, end_y) = self.move_body(x, y, dx, dy, RADIUS, owner);
+        if let Some(entity) = self.entities.get_mut(id) {
+            entity.x = end_x;
+            entity.y = end_y;
+        }
+        if (end_x - (x + dx)).hypot(end_y - (y + dy)) < 0.001 {
+            return;
+        }
+        // it stopped, in front of whatever it hit
+        let target = self
+            .object_hash
+            .query(end_x, end_y, RADIUS * 2.0)
+            .into_iter()
+            .filter(|&other| other != id && Some(other) != owner)
+            .find(|&other| {
+                self.entities.get(other).is_some_and(|entity| {
+                    entity.blocking
+                        && entity
+                            .body()
+                            .is_some_and(|body| body.overlaps(end_x, end_y, RADIUS * 2.0))
+                })
+            });
+        if let Some(entity) = target.and_then(|target| self.entities.get_mut(target)) {
+            entity.hurt(damage);
+        }
+        self.entities.despawn(id);
+    }
+
+    /**
+     * Moves the pushwalls one tick. A block waits while the player or a
+     * blocking object is in its way, and goes back into the map as a
+     * wall when it stops.
+     */
+    fn move_pushwalls(&mut self) {
+        // the blocks are taken out while they look at the rest of the game
+        let mut pushwalls = std::mem::take(&mut self.pushwalls);
+        let is_free = |x, y| self.map_cell(x, y).is_some_and(|cell| cell & 0xf == 0);
+        let blocked = |body: &Body| {
+            body.overlaps(self.f_player_x, self.f_player_y, self.f_player_radius)
+                || self
+                    .object_hash
+                    .query(body.x, body.y, self.tile_size)
+                    .into_iter()
+                    .filter_map(|id| self.entities.get(id))
+                    .filter(|entity| entity.blocking)
+                    .any(|entity| {
+                        entity
+                            .shape
+                            .is_some_and(|shape| body.overlaps(entity.x, entity.y, shape.extent()))
+                    })
+        };
+        let stopped = pushwalls.tick(self.tile_size, is_free, blocked);
+        self.pushwalls = pushwalls;
+        for wall in stopped {
+            let (x, y) = (wall.cell_x as usize, wall.cell_y as usize);
+            self.f_map[y][x] = 1;
+            self.map_wall_img[y][x] = wall.texture_id;
+        }
+    }
+
+    /**
+     * Moves the doors one tick. A door that is not closed doesn't close
+     * while the player or a blocking object is in the doorway.
+     */
+    fn move_doors(&mut self) {
+        let mut blocked = [false; MAX_DOORS];
+        for cell_y in 0..self.map_height as i32 {
+            for cell_x in 0..self.map_width as i32 {
+                let cell = self.f_map[cell_y as usize][cell_x as usize];
+                let index = door_index(cell);
+                if cell & 0xf != 2 || index >= MAX_DOORS || self.doors.position(index) <= 0.0 {
+                    continue;
+                }
+                // where the door is when it's closed
+                let (x0, y0, x1, y1) = self.door_segment(cell_x, cell_y, (0.0, 1.0));
+                let doorway = CellShape::Segment(x0, y0, x1, y1);
+                let in_doorway = |x, y, radius| {
+                    collision::overlaps(doorway, cell_x, cell_y, self.tile_size, x, y, radius)
+                };
+                let center = (cell_x as f32 + 0.5) * self.tile_size;
+                let middle_y = (cell_y as f32 + 0.5) * self.tile_size;
+                blocked[index] = in_doorway(self.f_player_x, self.f_player_y, self.f_player_radius)
+                    || self
+                        .object_hash
+                        .query(center, middle_y, self.tile_size / 2.0)
+                        .into_iter()
+                        .filter_map(|id| self.entities.get(id))
+                        .filter(|entity| entity.blocking)
+                        .any(|entity| {
+                            entity
+                                .shape
+                                .is_some_and(|shape| in_doorway(entity.x, entity.y, shape.extent()))
+                        });
+            }
+        }
+        self.doors.tick(|index| blocked[index]);
+    }
+
+    /**
+     * Draws the whole frame into the back buffer. It doesn't move anything.
+     * The post-processing effects are only for the 3D view, the automap is
+     * drawn without them so a flash or a shake doesn't get in the way of
+     * reading it.
+     */
+    fn render(&mut self) {
+        if self.automap.open {
+            let f_map = &self.f_map;
+            self.automap.update(self.f_player_x, self.f_player_y);
+            self.automap.draw(
+                &mut self.canvas,
+                self.width as usize,
+                self.height as usize,
+                self.pixel_format,
+                |x, y| f_map[y as usize][x as usize],
+                &self.minimap,
+                &self.doors,
+                (self.f_player_x, self.f_player_y, self.f_player_angle),
+                self.tile_size,
+            );
+        } else {
+            let camera = self.interpolated_camera();
+            let viewport = self.player_viewport.clip(self.width, self.height);
+            self.minimap.begin_frame();
+            if !viewport.is_empty() {
+                self.draw_background(&camera, viewport);
+                self.raycast(&camera, viewport, true);
+                self.draw_objects(&camera, viewport);
+                self.draw_weapon(viewport);
+            }
+            for index in 0..self.views.len() {
+                if let Some(angle) = self.views[index].follow_player {
+                    let view_camera = &mut self.views[index].camera;
+                    view_camera.x = camera.x;
+                    view_camera.y = camera.y;
+                    view_camera.height = camera.height;
+                    view_camera.angle = camera.angle + angle;
+                }
+                let view = self.views[index];
+                self.render_view(&view.camera, view.viewport);
+                if view.mirrored {
+                    self.mirror_viewport(view.viewport.clip(self.width, self.height));
+                }
+            }
+            self.post_process.apply(
+                &mut self.canvas,
+                self.width as usize,
+                self.height as usize,
+                self.pixel_format,
+            );
+            let f_map = &self.f_map;
+            self.minimap.draw(
+                &mut self.canvas,
+                self.width as usize,
+                self.height as usize,
+                self.pixel_format,
+                |x, y| f_map[y as usize][x as usize],
+                self.f_player_x,
+                self.f_player_y,
+                self.f_player_angle,
+                self.tile_size,
+            );
+        }
+        self.hud.draw(
+            &mut self.canvas,
+            self.width as usize,
+            self.height as usize,
+            self.pixel_format,
+            &self.assets.textures,
+        );
+    }
+
+    // turns the player, any amount of radians
+    fn turn(&mut self, angle: f32) {
+        self.f_player_angle = (self.f_player_angle + angle).rem_euclid(self.angle360);
+    }
+
+    // moves the horizon up (positive) or down, looking up or down
+    fn move_horizon(&mut self, pixels: f32) {
+        self.f_projection_plane_ycenter = (self.f_projection_plane_ycenter + pixels).clamp(
+            -(self.projectionplaneheight),
+            self.projectionplaneheight * 1.5 - 1.0,
+        );
+    }
+
+    /**
+     * Turns and looks up or down by a mouse movement, in pixels.
+     * Uses the sensitivity and inversion of the look controls.
+     */
+    pub fn look(&mut self, dx: f32, dy: f32) {
+        let (yaw, pitch) = self.controls.look_delta(dx, dy);
+        self.turn(yaw);
+        // the mouse turns right away, it doesn't wait for the next tick
+        self.previous_pose.2 += yaw;
+        self.move_horizon(pitch);
+    }
+
+    // turn speed, mouse look, sensitivity and inversion
+    pub fn controls(&mut self) -> &mut LookControls {
+        &mut self.controls
+    }
+
+    /**
+     * Moves the world forward by one tick: the player, the doors and
+     * the pushwalls, and runs the level events.
+     * Nothing happens while the game is paused, and the presses made
+     * meanwhile are dropped so they don't all happen when it's resumed.
+     */
+    pub fn tick(&mut self) {
+        self.previous_pose = (self.f_player_x, self.f_player_y, self.f_player_angle);
+        if self.is_paused() {
+            self.f_key_use = false;
+            self.f_key_fire = false;
+            self.f_key_reload = false;
+            self.weapon_switch = None;
+            return;
+        }
+        self.update_object_hash();
+        if std::mem::take(&mut self.f_key_use) {
+            self.use_in_front();
+        }
+        self.move_doors();
+        self.move_pushwalls();
+        self.think_entities();
+        self.post_process.tick();
+
+        let input = VerticalInput {
+            jump: self.f_key_jump,
+            crouch: self.f_key_crouch,
+            fly_up: self.f_key_fly_up,
+            fly_down: self.f_key_fly_down,
+        };
+        let floor = self.floor_height(self.f_player_x, self.f_player_y);
+        self.vertical.tick(input, floor, self.wall_height);
+
+        // rotate left
+        if self.f_key_left {
+            self.turn(-self.controls.turn_speed);
+        }
+        // rotate right
+        else if self.f_key_right {
+            self.turn(self.controls.turn_speed);
+        }
+
+        //  _____     _
+        // |\ arc     |
+        // |  \       y
+        // |    \     |
+        //            -
+        // |--x--|
+        //
+        //  sin(arc)=y/diagonal
+        //  cos(arc)=x/diagonal   where diagonal=speed
+        let player_xdir: f32 = table_lookup(
+            &self.f_cos_table,
+            self.f_player_angle,
+            self.projectionplanewidth,
+        );
+        let player_ydir: f32 = table_lookup(
+            &self.f_sin_table,
+            self.f_player_angle,
+            self.projectionplanewidth,
+        );
+
+        // 1 moves forward (or right), -1 backward (or left)
+        let forward = self.f_key_up as i32 - self.f_key_down as i32;
+        let sideways = self.f_key_strafe_right as i32 - self.f_key_strafe_left as i32;
+        // moving diagonally is not faster
+        let speed = if forward != 0 && sideways != 0 {
+            self.f_player_speed * std::f32::consts::FRAC_1_SQRT_2
+        } else {
+            self.f_player_speed
+        } * self.vertical.speed_factor();
+        // strafing goes 90 degrees to the right of the view direction
+        let dx: f32 = (player_xdir * forward as f32 - player_ydir * sideways as f32) * speed;
+        let dy: f32 = (player_ydir * forward as f32 + player_xdir * sideways as f32) * speed;
+
+        // slide along walls, doors and solid objects instead of going through them
+        let (new_player_x, new_player_y) = self.move_body(
+            self.f_player_x,
+            self.f_player_y,
+            dx,
+            dy,
+            self.f_player_radius,
+            None,
+        );
+        let moving = new_player_x != self.f_player_x || new_player_y != self.f_player_y;
+        self.f_player_x = new_player_x;
+        self.f_player_y = new_player_y;
+        self.use_weapon(moving);
+        self.touch_objects();
+        self.use_teleporters();
+        for trigger in self.triggers.iter_mut() {
+            trigger.update(
+                self.f_player_x,
+                self.f_player_y,
+                self.tile_size,
+                &mut self.events,
+            );
+        }
+        self.run_events();
+
+        if self.f_key_look_up {
+            self.move_horizon(15.0);
+        } else if self.f_key_look_down {
+            self.move_horizon(-15.0);
+        }
+    }
+
+    fn handle_keys(&mut self, window: &Window) {
+        // UP keypad
         self.f_key_up = window.is_key_down(Key::W);
 
         // DOWN keypad
         self.f_key_down = window.is_key_down(Key::S);
 
-        // LEFT keypad
-        self.f_key_left = window.is_key_down(Key::A);
+        // With mouse look the mouse turns, so A and D strafe.
+        // The arrows turn, comma and period always strafe.
+        let mouse_look = self.controls.mouse_look;
+        // the arrows pan the automap while it's open
+        let arrows = !self.automap.open;
+
+        // TURN LEFT
+        self.f_key_left = (arrows && window.is_key_down(Key::Left))
+            || (!mouse_look && window.is_key_down(Key::A));
 
-        // RIGHT keypad
-        self.f_key_right = window.is_key_down(Key::D);
+        // TURN RIGHT
+        self.f_key_right = (arrows && window.is_key_down(Key::Right))
+            || (!mouse_look && window.is_key_down(Key::D));
+
+        // STRAFE LEFT
+        self.f_key_strafe_left =
+            window.is_key_down(Key::Comma) || (mouse_look && window.is_key_down(Key::A));
+
+        // STRAFE RIGHT
+        self.f_key_strafe_right =
+            window.is_key_down(Key::Period) || (mouse_look && window.is_key_down(Key::D));
 
         // LOOK UP
         self.f_key_look_up = window.is_key_down(Key::Q);
@@ -1987,48 +3246,975 @@ impl GameWindow {
         // LOOK DOWN
         self.f_key_look_down = window.is_key_down(Key::Z);
 
-        // FLY UP
-        self.f_key_fly_up = window.is_key_down(Key::E);
+        // JUMP
+        self.f_key_jump = window.is_key_down(Key::Space);
 
-        // FLY DOWN
-        self.f_key_fly_down = window.is_key_down(Key::C);
-    }
+        // CROUCH, or fly down when flying
+        self.f_key_crouch = window.is_key_down(Key::C);
+        self.f_key_fly_down = self.f_key_crouch;
 
-    /*    fn flip_buffer_in_use(&mut self) {
-        if self.buffer_n == 0 {
-            self.buffer_n = 1;
-        } else {
-            self.buffer_n = 0;
+        // FLY UP, only when flying. It's the jump key
+        self.f_key_fly_up = self.f_key_jump;
+
+        // USE, opens and closes doors. It waits for the next tick
+        if window.is_key_pressed(Key::E, KeyRepeat::No) {
+            self.f_key_use = true;
+        }
+
+        // FLY MODE, no gravity (debug)
+        if window.is_key_pressed(Key::F6, KeyRepeat::No) {
+            self.vertical.fly = !self.vertical.fly;
+        }
+
+        // MOUSE LOOK
+        if window.is_key_pressed(Key::F5, KeyRepeat::No) {
+            self.controls.mouse_look = !self.controls.mouse_look;
+        }
+        let (mouse_dx, mouse_dy) = self
+            .controls
+            .mouse_delta(window.get_mouse_pos(MouseMode::Pass));
+        if self.controls.mouse_look && !self.is_paused() {
+            self.look(mouse_dx, mouse_dy);
+        }
+
+        // MINIMAP
+        if window.is_key_pressed(Key::Tab, KeyRepeat::No) {
+            self.minimap.visible = !self.minimap.visible;
+        }
+        if window.is_key_pressed(Key::F3, KeyRepeat::No) {
+            self.minimap.orientation = match self.minimap.orientation {
+                MinimapOrientation::NorthUp => MinimapOrientation::PlayerUp,
+                MinimapOrientation::PlayerUp => MinimapOrientation::NorthUp,
+            };
+        }
+        if window.is_key_pressed(Key::F4, KeyRepeat::No) {
+            self.minimap.show_rays = !self.minimap.show_rays;
+        }
+        if !self.automap.open {
+            if window.is_key_pressed(Key::Equal, KeyRepeat::Yes) {
+                self.minimap.zoom = (self.minimap.zoom + 1.0).min(16.0);
+            }
+            if window.is_key_pressed(Key::Minus, KeyRepeat::Yes) {
+                self.minimap.zoom = (self.minimap.zoom - 1.0).max(1.0);
+            }
+        }
+
+        // FIRE, RELOAD and the weapons by number. They wait for the next tick
+        if !self.automap.open {
+            if window.is_key_pressed(Key::LeftCtrl, KeyRepeat::No) {
+                self.f_key_fire = true;
+            }
+            if window.is_key_pressed(Key::R, KeyRepeat::No) {
+                self.f_key_reload = true;
+            }
+            let numbers = [Key::Key1, Key::Key2, Key::Key3, Key::Key4];
+            for (index, &key) in numbers.iter().enumerate() {
+                if window.is_key_pressed(key, KeyRepeat::No) {
+                    self.weapon_switch = Some(index);
+                }
+            }
+        }
+
+        // AUTOMAP
+        if window.is_key_pressed(Key::M, KeyRepeat::No) {
+            self.automap.toggle();
+        }
+        if self.automap.open {
+            let pan_speed = 4.0;
+            if window.is_key_down(Key::Left) {
+                self.automap.pan(-pan_speed, 0.0, self.tile_size);
+            }
+            if window.is_key_down(Key::Right) {
+                self.automap.pan(pan_speed, 0.0, self.tile_size);
+            }
+            if window.is_key_down(Key::Up) {
+                self.automap.pan(0.0, -pan_speed, self.tile_size);
+            }
+            if window.is_key_down(Key::Down) {
+                self.automap.pan(0.0, pan_speed, self.tile_size);
+            }
+            if window.is_key_down(Key::Equal) {
+                self.automap.zoom_by(1.05);
+            }
+            if window.is_key_down(Key::Minus) {
+                self.automap.zoom_by(1.0 / 1.05);
+            }
+            if window.is_key_pressed(Key::F, KeyRepeat::No) {
+                self.automap.follow_player = true;
+            }
+            if window.is_key_pressed(Key::Enter, KeyRepeat::No) {
+                self.automap.add_marker();
+            }
+            if window.is_key_pressed(Key::Backspace, KeyRepeat::No) {
+                self.automap.remove_last_marker();
+            }
         }
-    }*/
+
+        // TEXTURE QUALITY
+        if window.is_key_pressed(Key::F1, KeyRepeat::No) {
+            self.set_mipmapping(!self.mipmapping);
+        }
+        #[cfg(not(feature = "web"))]
+        if window.is_key_pressed(Key::F2, KeyRepeat::No) {
+            self.set_texture_filter(match self.texture_filter {
+                TextureFilter::Nearest => TextureFilter::Bilinear,
+                TextureFilter::Bilinear => TextureFilter::Nearest,
+            });
+        }
+    }
+
+    /**
+     * Use smaller versions of the textures for distant surfaces.
+     * Disabling it is faster, but distant floors shimmer
+     */
+    pub fn set_mipmapping(&mut self, enabled: bool) {
+        self.mipmapping = enabled;
+    }
+
+    /**
+     * Bilinear filtering is only available on native builds
+     */
+    pub fn set_texture_filter(&mut self, filter: TextureFilter) {
+        self.texture_filter = filter;
+    }
+
+    /**
+     * Swaps the back buffer we just finished drawing with the front buffer,
+     * so the frame becomes visible through get_buffer_to_print.
+     * We don't need to clear the new back buffer, the background covers
+     * the entire screen anyway.
+     */
+    pub fn present(&mut self) {
+        std::mem::swap(&mut self.canvas, &mut self.front_buffer);
+    }
+
+    /**
+     * return the last complete frame. The game never draws into it,
+     * so it can't show a half drawn frame (no tearing artifacts)
+     */
+    pub fn get_buffer_to_print(&self) -> &[u32] {
+        &self.front_buffer
+    }
+
+    /**
+     * Renders and presents one frame without input or a window, and
+     * returns its pixels. Nothing moves, so the same camera always gives
+     * the same image. Used for screenshots and tooling.
+     */
+    pub fn render_frame(&mut self) -> &[u32] {
+        self.render();
+        self.present();
+        self.get_buffer_to_print()
+    }
+
+    /**
+     * Places the camera (the player) at x, y in world units, looking at
+     * `angle` radians.
+     */
+    pub fn set_camera(&mut self, x: f32, y: f32, angle: f32) {
+        self.f_player_x = x;
+        self.f_player_y = y;
+        self.f_player_angle = angle.rem_euclid(self.angle360);
+        // it's a jump, there is nothing to interpolate
+        self.previous_pose = (self.f_player_x, self.f_player_y, self.f_player_angle);
+        // and it doesn't walk into a teleporter
+        self.player_cell = (
+            (x / self.tile_size).floor() as i32,
+            (y / self.tile_size).floor() as i32,
+        );
+    }
+
+    // (x, y, angle) of the camera
+    pub fn camera(&self) -> (f32, f32, f32) {
+        (self.f_player_x, self.f_player_y, self.f_player_angle)
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn pixel_format(&self) -> PixelFormat {
+        self.pixel_format
+    }
+
+    // writes the last presented frame as a farbfeld image
+    pub fn write_screenshot<W: Write>(&self, writer: W) -> farfarbfeld::Result<()> {
+        screenshot::encode_farbfeld(
+            writer,
+            self.get_buffer_to_print(),
+            self.width,
+            self.height,
+            self.pixel_format,
+        )
+    }
+
+    #[cfg(not(feature = "web"))]
+    pub fn save_screenshot(&self, path: &str) -> farfarbfeld::Result<()> {
+        let file = std::fs::File::create(path)?;
+        self.write_screenshot(std::io::BufWriter::new(file))
+    }
 
     /**
-     * return a slice in the buffer that has just been updated
+     * The real time is used by default. Tests and tools can use a
+     * ManualClock to decide how much time passes between frames.
      */
-    pub fn get_buffer_to_print(&mut self) -> &[u32] {
-        let default_increment = 4;
-        let start_offset = self.buffer_n * self.area_size as usize * default_increment;
-        unsafe {
-            &self.canvas[start_offset..(start_offset + self.area_size * default_increment) as usize]
-                .align_to::<u32>()
-                .1
+    pub fn set_clock(&mut self, clock: Box<dyn Clock>) {
+        self.clock = clock;
+        self.last_frame = None;
+        let max_ticks_per_frame = self.timestep.max_ticks_per_frame;
+        self.timestep = FixedTimestep::new(1.0 / self.timestep.tick);
+        self.timestep.max_ticks_per_frame = max_ticks_per_frame;
+    }
+
+    // ticks per second and the most ticks run in a single frame
+    pub fn timestep(&mut self) -> &mut FixedTimestep {
+        &mut self.timestep
+    }
+
+    /**
+     * Draws the player in between the last two ticks, depending on when
+     * the frame is drawn. Movement looks smooth when there are more frames
+     * than ticks, but the view is up to one tick behind.
+     */
+    pub fn set_interpolation(&mut self, enabled: bool) {
+        self.interpolation = enabled;
+    }
+
+    /**
+     * Runs the ticks that fit in the time since the previous frame and
+     * draws a new frame, without reading any input.
+     */
+    pub fn run_frame(&mut self) {
+        let now = self.clock.now();
+        if let Some(last_frame) = self.last_frame {
+            self.hud.frame((now - last_frame) as f32);
+        }
+        self.last_frame = Some(now);
+        for _ in 0..self.timestep.advance(now) {
+            self.tick();
         }
+        self.render();
+        self.present();
     }
 
     pub fn game_step(&mut self, window: &Window) {
-        /*
-        self.flip_buffer_in_use(); // we are not using the two buffers in the other part of the code
-        let offset = self.buffer_n * self.area_size as usize;
-
-        // clear the buffer
-        // nah. we are drawing the entire screen anyway
-        self.canvas
-            .iter_mut()
-            //.skip(offset)
-            .take(self.area_size as usize)
-            .for_each(|value| *value = 0xFF01A101); // clear in blue, so we can see if we are drawing something
-        */
-        self.handle_keys(&window);
-        self.update();
+        self.handle_keys(window);
+        self.run_frame();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+    use crate::doors::DoorState;
+    use crate::loader::{FileLoader, ResourceIndex, Texture};
+    use std::collections::HashMap;
+
+    struct NoFiles {}
+
+    impl FileLoader for NoFiles {
+        fn load_textures(&mut self, _: &ResourceIndex, _: &mut HashMap<u32, Texture>) {}
+        fn load_index_file(&mut self) -> Option<ResourceIndex> {
+            None
+        }
+    }
+
+    fn solid(width: u32, height: u32, rgb: [u8; 3]) -> Texture {
+        let data = (0..width * height)
+            .flat_map(|_| [rgb[0], rgb[1], rgb[2], 255])
+            .collect();
+        Texture::new(width, height, data)
+    }
+
+    fn game_with_textures(textures: HashMap<u32, Texture>) -> GameWindow {
+        let assets = Assets {
+            root: "./".to_string(),
+            resources: None,
+            textures,
+            loader: Box::new(NoFiles {}),
+        };
+        let mut game = GameWindow::new(320, 200, assets);
+        game.init();
+        game.convert_background();
+        game
+    }
+
+    // the test level with every texture it uses, all of them plain grey
+    fn test_game() -> GameWindow {
+        test_game_with(Vec::new())
+    }
+
+    // the test level, with some of its textures replaced or added
+    fn test_game_with(overrides: Vec<(u32, Texture)>) -> GameWindow {
+        let mut textures = HashMap::new();
+        for id in [110, 83, 74, 162, 14, 181, 101, 188, 189, 190] {
+            textures.insert(id, solid(16, 16, [100, 100, 100]));
+        }
+        textures.extend(overrides);
+        game_with_textures(textures)
+    }
+
+    // reads back a pixel of the last presented frame as [red, green, blue]
+    fn pixel(game: &GameWindow, x: u32, y: u32) -> [u8; 3] {
+        let color = game.get_buffer_to_print()[(y * game.width + x) as usize];
+        let (_, red, green, blue) = game.pixel_format.unpack(color);
+        [red, green, blue]
+    }
+
+    #[test]
+    fn renders_textures_of_mixed_sizes() {
+        let mut textures = HashMap::new();
+        textures.insert(110, solid(1920, 200, [0, 0, 0]));
+        // walls
+        textures.insert(83, solid(32, 32, [200, 0, 0]));
+        textures.insert(74, solid(128, 16, [200, 0, 0]));
+        // floors
+        textures.insert(162, solid(128, 128, [0, 200, 0]));
+        textures.insert(14, solid(16, 64, [0, 200, 0]));
+        textures.insert(181, solid(3, 7, [0, 200, 0]));
+        // ceiling
+        textures.insert(101, solid(16, 16, [0, 0, 200]));
+        // sprites
+        textures.insert(163, solid(20, 50, [200, 0, 0]));
+        textures.insert(42, solid(128, 64, [200, 0, 0]));
+
+        for mipmapping in [false, true] {
+            let mut game = game_with_textures(textures.clone());
+            game.set_mipmapping(mipmapping);
+            let camera = game.player_camera();
+            game.render_view(&camera, game.player_viewport());
+            game.present();
+
+            let center = pixel(&game, 160, 100);
+            assert!(center[0] > 0 && center[1] == 0 && center[2] == 0);
+            let bottom = pixel(&game, 160, 199);
+            assert!(bottom[0] == 0 && bottom[1] > 0 && bottom[2] == 0);
+            let top = pixel(&game, 160, 0);
+            assert!(top[0] == 0 && top[1] == 0 && top[2] > 0);
+        }
+    }
+
+    #[test]
+    fn decals_are_drawn_where_they_were_spawned() {
+        let mut game = test_game_with(vec![
+            (83, solid(32, 32, [200, 0, 0])),
+            (74, solid(32, 32, [200, 0, 0])),
+            (BULLET_HOLE_TEXTURE_ID, solid(8, 8, [0, 0, 200])),
+        ]);
+        assert!(game.spawn_decal_at_screen(160, 100, BULLET_HOLE_TEXTURE_ID, 0.1));
+        let camera = game.player_camera();
+        game.render_view(&camera, game.player_viewport());
+        game.present();
+        // shaded like the wall, but with the decal colors
+        let center = pixel(&game, 160, 100);
+        assert!(center[0] == 0 && center[2] > 0);
+        let beside = pixel(&game, 100, 100);
+        assert!(beside[0] > 0 && beside[2] == 0);
+    }
+
+    #[test]
+    fn headless_frames_follow_the_camera() {
+        let mut game = test_game();
+        game.set_camera(160.0, 160.0, 0.0);
+        let first = game.render_frame().to_vec();
+        assert_eq!(game.render_frame(), &first[..]);
+        // nothing moved
+        assert_eq!(game.camera(), (160.0, 160.0, 0.0));
+        game.set_camera(160.0, 160.0, std::f32::consts::PI);
+        assert_ne!(game.render_frame(), &first[..]);
+
+        let mut file = Vec::new();
+        game.write_screenshot(&mut file).unwrap();
+        assert_eq!(file.len(), 16 + 320 * 200 * 8);
+    }
+
+    #[test]
+    fn missing_floor_and_ceiling_textures_are_skipped() {
+        let mut textures = HashMap::new();
+        for id in [110, 83, 74, 162, 14, 181] {
+            textures.insert(id, solid(16, 16, [100, 100, 100]));
+        }
+        let mut game = game_with_textures(textures);
+        // looking at the teleporter floor, without its texture or a ceiling
+        game.set_camera(960.0, 1120.0, 0.0);
+        game.render_frame();
+    }
+
+    #[test]
+    fn lift_doors_show_what_is_behind_them() {
+        let mut game = test_game_with(vec![
+            (83, solid(32, 32, [200, 0, 0])),
+            (74, solid(32, 32, [200, 0, 0])),
+            (500, solid(32, 32, [0, 0, 200])),
+        ]);
+        game.minimap().visible = false;
+        let door = game.doors().get_mut(1).unwrap();
+        assert_eq!(door.kind, DoorKind::Lift);
+        door.texture_id = Some(500);
+        // in front of the door, it covers the whole screen
+        let is_door = |[red, green, blue]: [u8; 3]| red == 0 && green == 0 && blue > 0;
+        game.set_camera(736.0, 736.0, 0.0);
+        game.render_frame();
+        assert!(is_door(pixel(&game, 160, 90)));
+        assert!(is_door(pixel(&game, 160, 110)));
+
+        // half way up it ends at the eyes, the wall behind it shows under it
+        game.doors().get_mut(1).unwrap().position = 0.5;
+        game.render_frame();
+        assert!(is_door(pixel(&game, 160, 90)));
+        let behind = pixel(&game, 160, 110);
+        assert!(behind[0] > 0 && behind[2] == 0);
+    }
+
+    #[test]
+    fn split_screen_views_share_the_world() {
+        let mut game = test_game();
+        game.set_camera(300.0, 200.0, 0.3);
+        // it would cover part of the left half
+        game.minimap().visible = false;
+        game.set_player_viewport(Viewport::new(0, 0, 160, 200));
+        let camera = game.player_camera();
+        game.views()
+            .push(View::new(camera, Viewport::new(160, 0, 160, 200)));
+        let frame = game.render_frame().to_vec();
+        let row = |frame: &[u32], y: usize| frame[y * 320..(y + 1) * 320].to_vec();
+        // the same camera in both halves
+        for y in [0, 60, 100, 150, 199] {
+            let row = row(&frame, y);
+            assert_eq!(row[..160], row[160..]);
+        }
+
+        // the right half becomes a mirror of the left one
+        game.views()[0].mirrored = true;
+        let frame = game.render_frame().to_vec();
+        for y in [0, 60, 100, 150, 199] {
+            let mut right = row(&frame, y)[160..].to_vec();
+            right.reverse();
+            assert_eq!(row(&frame, y)[..160], right[..]);
+        }
+    }
+
+    #[test]
+    fn strafing_and_turning_are_continuous() {
+        let mut game = test_game();
+        game.set_camera(320.0, 320.0, 0.0);
+        // looking right (east), so the right side is down
+        game.f_key_strafe_right = true;
+        game.tick();
+        assert_eq!(game.camera(), (320.0, 336.0, 0.0));
+
+        game.f_key_strafe_right = false;
+        game.f_key_right = true;
+        game.controls().turn_speed = 0.1;
+        game.tick();
+        game.tick();
+        assert!((game.camera().2 - 0.2).abs() < 1e-6);
+
+        // the mouse turns by the sensitivity and moves the horizon
+        game.controls().mouse_sensitivity_x = 0.001;
+        game.look(-300.0, -10.0);
+        assert!((game.camera().2 - (std::f32::consts::PI * 2.0 - 0.1)).abs() < 1e-5);
+        assert_eq!(game.player_camera().pitch, 10.0 / 200.0);
+    }
+
+    #[test]
+    fn closed_doors_stop_the_player() {
+        let mut game = test_game();
+        // in front of the second door, walking east into it
+        game.set_camera(736.0, 736.0, 0.0);
+        game.doors().get_mut(1).unwrap().set_open(false);
+        game.f_key_up = true;
+        for _ in 0..10 {
+            game.tick();
+        }
+        assert_eq!(game.camera(), (800.0 - 8.0, 736.0, 0.0));
+
+        // going in diagonally slides along the door
+        game.set_camera(736.0, 736.0, std::f32::consts::FRAC_PI_4);
+        game.tick();
+        game.tick();
+        let (x, y, _) = game.camera();
+        assert!(x <= 792.0 && y > 736.0 + 16.0);
+
+        game.doors().get_mut(1).unwrap().set_open(true);
+        game.set_camera(736.0, 736.0, 0.0);
+        for _ in 0..10 {
+            game.tick();
+        }
+        assert!(game.camera().0 > 800.0);
+    }
+
+    #[test]
+    fn doors_are_used_and_wait_for_the_player() {
+        let mut game = test_game();
+        let door = game.doors().get_mut(1).unwrap();
+        door.speed = 0.25;
+        door.auto_close = Some(5);
+        // too far away to use it
+        game.set_camera(640.0, 736.0, 0.0);
+        game.f_key_use = true;
+        game.tick();
+        assert_eq!(game.doors().get(1).unwrap().state, DoorState::Closed);
+
+        // in front of the door, but the game is paused in the automap
+        game.set_camera(760.0, 736.0, 0.0);
+        game.automap().toggle();
+        game.f_key_use = true;
+        game.tick();
+        game.automap().toggle();
+        game.tick();
+        assert_eq!(game.doors().get(1).unwrap().state, DoorState::Closed);
+
+        game.f_key_use = true;
+        for _ in 0..4 {
+            game.tick();
+        }
+        assert_eq!(game.doors().get(1).unwrap().state, DoorState::Open);
+        // stand in the doorway
+        game.set_camera(800.0, 736.0, 0.0);
+        for _ in 0..20 {
+            game.tick();
+        }
+        assert_eq!(game.doors().get(1).unwrap().state, DoorState::Open);
+
+        // once the player leaves it closes
+        game.set_camera(900.0, 736.0, 0.0);
+        for _ in 0..20 {
+            game.tick();
+        }
+        assert_eq!(game.doors().get(1).unwrap().state, DoorState::Closed);
+    }
+
+    #[test]
+    fn pushwalls_slide_away_and_open_a_passage() {
+        let mut game = test_game();
+        game.pushwalls.speed = 0.25;
+        // at the end of the corridor behind the second door, looking south
+        let south = std::f32::consts::FRAC_PI_2;
+        game.set_camera(864.0, 736.0, south);
+        assert_eq!(game.cast_ray(864.0, 736.0, south).unwrap().distance, 32.0);
+        game.f_key_use = true;
+        game.tick();
+        game.tick();
+        // half way into the next cell, drawn where it is
+        assert_eq!(game.f_map[12][13], 0);
+        let hit = game.cast_ray(864.0, 736.0, south).unwrap();
+        assert_eq!((hit.distance, hit.face.cell_y), (64.0, 12));
+        // and solid there
+        let (_, y) = game.move_body(864.0, 736.0, 0.0, 100.0, 8.0, None);
+        assert_eq!(y, 792.0);
+
+        for _ in 0..10 {
+            game.tick();
+        }
+        // it went 2 cells and it's a wall again, leaving a passage behind
+        assert!(game.pushwalls.moving().is_empty());
+        assert_eq!((game.f_map[13][13], game.f_map[14][13]), (0, 1));
+        let (_, y) = game.move_body(864.0, 736.0, 0.0, 200.0, 8.0, None);
+        assert_eq!(y, 888.0);
+    }
+
+    #[test]
+    fn pushwalls_against_a_wall_can_be_pushed_from_another_side() {
+        let mut game = test_game();
+        game.pushwalls.speed = 0.5;
+        // a block in the north west of the map, next to the column at 3, 4
+        game.f_map[4][4] = 0x0103;
+        game.map_wall_img[4][4] = 83;
+        // pushed west into the column, it doesn't move
+        game.set_camera(352.0, 288.0, std::f32::consts::PI);
+        game.f_key_use = true;
+        game.tick();
+        assert!(game.pushwalls.moving().is_empty());
+        assert_eq!(game.f_map[4][4], 0x0103);
+
+        // pushed north from the open side, it goes one cell
+        game.set_camera(288.0, 352.0, -std::f32::consts::FRAC_PI_2);
+        game.f_key_use = true;
+        for _ in 0..4 {
+            game.tick();
+        }
+        assert!(game.pushwalls.moving().is_empty());
+        assert_eq!((game.f_map[4][4], game.f_map[3][4]), (0, 1));
+    }
+
+    #[test]
+    fn locked_doors_need_their_key() {
+        let mut game = test_game();
+        // in front of the first door, it's locked
+        game.set_camera(220.0, 608.0, 0.0);
+        game.f_key_use = true;
+        game.tick();
+        assert_eq!(game.doors().get(0).unwrap().state, DoorState::Closed);
+        assert_eq!(game.take_sound_events(), vec![SoundEvent::DoorLocked]);
+        assert_eq!(
+            game.hud().messages().collect::<Vec<_>>(),
+            vec!["YOU NEED THE RED KEY"]
+        );
+
+        // walking into the key picks it up, and takes it off the automap
+        assert_eq!(game.automap().icons.len(), 1);
+        let entities = game.entities().len();
+        game.set_camera(1060.0, 224.0, 0.0);
+        game.f_key_up = true;
+        for _ in 0..3 {
+            game.tick();
+        }
+        game.f_key_up = false;
+        assert!(game.inventory().has_key(KeyColor::Red));
+        assert!(game.automap().icons.is_empty());
+        assert_eq!(game.entities().len(), entities - 1);
+        assert_eq!(game.take_sound_events(), vec![SoundEvent::KeyPickup]);
+        assert!(game.take_touch_events().is_empty());
+
+        game.set_camera(220.0, 608.0, 0.0);
+        game.f_key_use = true;
+        game.tick();
+        assert_eq!(game.doors().get(0).unwrap().state, DoorState::Opening);
+    }
+
+    #[test]
+    fn switches_and_triggers_run_level_events() {
+        let mut game = test_game();
+        // in the first room, in front of the switch of the second door
+        let south = std::f32::consts::FRAC_PI_2;
+        game.set_camera(416.0, 740.0, south);
+        let hit = game.cast_ray(416.0, 740.0, south).unwrap();
+        assert_eq!(game.wall_texture(&hit), 188);
+        game.f_key_use = true;
+        game.tick();
+        assert_eq!(game.doors().get(1).unwrap().state, DoorState::Opening);
+        assert_eq!(game.wall_texture(&hit), 189);
+
+        // events fired from outside run in the next tick. This one takes
+        // the player to the secret, which has a trigger
+        game.events().on(
+            "warp",
+            Action::Teleport {
+                x: 864.0,
+                y: 860.0,
+                angle: 0.0,
+            },
+        );
+        game.events().on("warp", Action::EndLevel);
+        game.events().fire("warp");
+        game.tick();
+        assert_eq!(game.camera(), (864.0, 860.0, 0.0));
+        assert!(game.level_ended());
+        game.tick();
+        assert!(game
+            .hud()
+            .messages()
+            .any(|message| message == "YOU FOUND A SECRET"));
+    }
+
+    #[test]
+    fn teleporters_move_the_player_and_portals_bend_rays() {
+        let mut game = test_game();
+        // walking east onto the pad in the south east corner
+        game.set_camera(1080.0, 1120.0, 0.0);
+        game.f_key_up = true;
+        for _ in 0..10 {
+            game.tick();
+            if game.take_sound_events().contains(&SoundEvent::Teleport) {
+                break;
+            }
+        }
+        let (x, y, angle) = game.camera();
+        assert_eq!((x, y), (96.0, 1120.0));
+        assert!(angle.abs() < 0.001);
+
+        // looking west at the column with the portal, the ray comes back
+        // from the other column again and again, and hits a wall after the
+        // last hop
+        let mut hops = Vec::new();
+        let hit = game
+            .cast_ray_path(400.0, 928.0, std::f32::consts::PI, &mut hops)
+            .unwrap();
+        assert_eq!(hops.len(), game.portals().max_hops);
+        assert!(
+            (hit.distance - (144.0 + 4.0 * 704.0)).abs() < 1.0,
+            "{}",
+            hit.distance
+        );
+        assert_eq!(hops[0].portal.map(|face| face.side), Some(Side::East));
+    }
+
+    #[test]
+    fn solid_objects_block_and_pickups_are_touched() {
+        let mut game = test_game();
+        game.entities().clear();
+        game.entities().spawn(Entity {
+            shape: Some(BodyShape::Square(16.0)),
+            blocking: true,
+            ..Entity::decoration(400.0, 96.0, 25.0, 32, 32, 163)
+        });
+        let pickup = game.entities().spawn(Entity {
+            shape: Some(BodyShape::Circle(8.0)),
+            ..Entity::decoration(320.0, 200.0, 25.0, 32, 32, 42)
+        });
+        // walking east stops in front of the crate
+        game.set_camera(320.0, 96.0, 0.0);
+        game.f_key_up = true;
+        for _ in 0..10 {
+            game.tick();
+        }
+        assert_eq!(game.camera().0, 400.0 - 16.0 - 8.0);
+        assert!(game.take_touch_events().is_empty());
+
+        // walking south goes over the pickup, and touches it once
+        game.set_camera(320.0, 96.0, std::f32::consts::FRAC_PI_2);
+        for _ in 0..7 {
+            game.tick();
+        }
+        assert!(game.camera().1 > 200.0);
+        assert_eq!(
+            game.take_touch_events(),
+            vec![TouchEvent { entity: pickup }]
+        );
+        game.tick();
+        assert!(game.take_touch_events().is_empty());
+    }
+
+    #[test]
+    fn projectiles_hurt_entities_and_are_gone_after_hitting() {
+        let mut game = test_game();
+        // a crate in the first room, with two shots of health
+        let target = game.entities().spawn(Entity {
+            shape: Some(BodyShape::Square(16.0)),
+            blocking: true,
+            health: Some(20),
+            ..Entity::decoration(400.0, 480.0, 25.0, 32, 32, 163)
+        });
+        let shot = |x, dx| Entity {
+            behaviour: Behaviour::Projectile {
+                dx,
+                dy: 0.0,
+                damage: 10,
+                owner: None,
+            },
+            ..Entity::decoration(x, 480.0, 25.0, 4, 4, 42)
+        };
+        let first = game.entities().spawn(shot(340.0, 8.0));
+        // levels spawn them with events too, this one goes into the wall
+        game.events().on("shoot", Action::Spawn(shot(700.0, 8.0)));
+        game.events().fire("shoot");
+        for _ in 0..10 {
+            game.tick();
+        }
+        assert!(game.entities().get(first).is_none());
+        assert_eq!(game.entities().get(target).unwrap().health, Some(10));
+        assert!(!game
+            .entities()
+            .iter()
+            .any(|(_, entity)| matches!(entity.behaviour, Behaviour::Projectile { .. })));
+
+        game.entities().spawn(shot(340.0, 8.0));
+        for _ in 0..10 {
+            game.tick();
+        }
+        assert!(game.entities().get(target).is_none());
+    }
+
+    #[test]
+    fn dead_enemies_sink_without_being_touched() {
+        let mut game = test_game();
+        game.set_camera(352.0, 600.0, 0.0);
+        let skeleton = game.entities().spawn(Entity {
+            health: Some(0),
+            ..Entity::enemy(400.0, 600.0, EnemyKind::Skeleton, 0.0)
+        });
+        game.tick();
+        assert!(game.entities().get(skeleton).unwrap().shape.is_none());
+        // the player walks over the body while it sinks
+        game.f_key_up = true;
+        for _ in 0..10 {
+            game.tick();
+        }
+        assert!(game.f_player_x > 400.0);
+        assert!(game.entities().get(skeleton).is_some());
+        assert!(game.take_touch_events().is_empty());
+    }
+
+    #[test]
+    fn weapons_shoot_far_and_swords_only_hit_close() {
+        let mut game = test_game();
+        game.set_camera(352.0, 600.0, 0.0);
+        let target = game.entities().spawn(Entity {
+            shape: Some(BodyShape::Circle(16.0)),
+            blocking: true,
+            health: Some(60),
+            ..Entity::decoration(560.0, 600.0, 25.0, 32, 32, 163)
+        });
+        let health = |game: &mut GameWindow| game.entities().get(target).and_then(|e| e.health);
+        let effects = |game: &mut GameWindow| {
+            game.entities()
+                .iter()
+                .filter(|(_, entity)| matches!(entity.behaviour, Behaviour::Effect { .. }))
+                .count()
+        };
+
+        game.f_key_fire = true;
+        game.tick();
+        assert_eq!(health(&mut game), Some(35));
+        assert_eq!(game.hud().ammo, Some(7));
+        assert_eq!(effects(&mut game), 1);
+        assert!(game.take_sound_events().contains(&SoundEvent::WeaponFire));
+        for _ in 0..30 {
+            game.tick();
+        }
+        assert_eq!(effects(&mut game), 0);
+
+        // the sword doesn't reach it from there
+        game.weapon_switch = Some(1);
+        for _ in 0..30 {
+            game.tick();
+        }
+        game.f_key_fire = true;
+        game.tick();
+        assert_eq!(health(&mut game), Some(35));
+        assert_eq!(game.hud().ammo, None);
+
+        game.entities().get_mut(target).unwrap().x = 400.0;
+        for _ in 0..30 {
+            game.tick();
+        }
+        game.f_key_fire = true;
+        game.tick();
+        game.tick();
+        assert!(game.entities().get(target).is_none());
+    }
+
+    #[test]
+    fn enemies_go_through_doors_to_attack_the_player() {
+        let run = |seed| {
+            let mut game = test_game();
+            game.set_seed(seed);
+            game.init();
+            game.entities().clear();
+            // a skeleton in the first room, and the player in the corridor
+            // behind the second door, where it can't be seen
+            let skeleton = game.entities().spawn(Entity::enemy(
+                672.0,
+                672.0,
+                EnemyKind::Skeleton,
+                std::f32::consts::PI,
+            ));
+            game.set_camera(864.0, 544.0, 0.0);
+            assert!(!game.line_of_sight(672.0, 672.0, 864.0, 544.0));
+            // hurting it wakes it up
+            game.entities().get_mut(skeleton).unwrap().hurt(1);
+            let mut door_opened = false;
+            for _ in 0..600 {
+                game.tick();
+                door_opened |= game.doors().get(1).unwrap().state != DoorState::Closed;
+            }
+            assert!(door_opened);
+            let (x, y) = game.entities().get(skeleton).unwrap().position();
+            assert!(x > 13.0 * 64.0 && y < 11.0 * 64.0, "{:?}", (x, y));
+            (x, y, game.player_health())
+        };
+        let (x, y, health) = run(1);
+        assert!(health < 100);
+        // the same seed plays the same way
+        assert_eq!(run(1), (x, y, health));
+    }
+
+    #[test]
+    fn enemies_only_use_the_teleporters_for_actors() {
+        let run = |actors| {
+            let mut game = test_game();
+            game.entities().clear();
+            // a teleporter in the middle of the first room, between a
+            // skeleton and the player
+            *game.teleporters() = vec![Teleporter {
+                actors,
+                ..Teleporter::new(7, 9, (1, 17))
+            }];
+            let skeleton =
+                game.entities()
+                    .spawn(Entity::enemy(416.0, 608.0, EnemyKind::Skeleton, 0.0));
+            game.entities().get_mut(skeleton).unwrap().hurt(1);
+            game.set_camera(736.0, 608.0, std::f32::consts::PI);
+            let mut teleported = false;
+            for _ in 0..120 {
+                game.tick();
+                let (x, y) = game.entities().get(skeleton).unwrap().position();
+                teleported |= x < 128.0 && y > 17.0 * 64.0;
+            }
+            teleported
+        };
+        assert!(run(true));
+        assert!(!run(false));
+    }
+
+    #[test]
+    fn the_simulation_does_not_depend_on_the_frame_rate() {
+        let mut positions = Vec::new();
+        for frames_per_second in [30.0f64, 144.0, 10.0] {
+            let mut game = test_game();
+            let clock = ManualClock::new();
+            game.set_clock(Box::new(clock.clone()));
+            game.set_camera(320.0, 320.0, 0.3);
+            game.f_key_up = true;
+            game.f_key_right = true;
+            game.doors().get_mut(0).unwrap().activate();
+            game.run_frame();
+            // half a second, plus half a tick to stay away from rounding errors
+            let end = 0.5 + 1.0 / 120.0;
+            while clock.now() < end {
+                clock.advance((1.0 / frames_per_second).min(end - clock.now()));
+                game.run_frame();
+            }
+            positions.push((game.camera(), game.doors().position(0)));
+        }
+        // half a second is 30 ticks, however many frames were drawn
+        assert_eq!(positions[0], positions[1]);
+        assert_eq!(positions[0], positions[2]);
+        assert_eq!(positions[0].1, 30.0 / 32.0);
+    }
+
+    #[test]
+    fn changing_the_clock_keeps_the_timestep_settings() {
+        let mut game = game_with_textures(HashMap::new());
+        game.timestep().max_ticks_per_frame = 2;
+        game.set_clock(Box::new(ManualClock::new()));
+        assert_eq!(game.timestep().max_ticks_per_frame, 2);
+    }
+
+    #[test]
+    fn the_automap_is_drawn_without_effects() {
+        let mut game = test_game();
+        game.post_process()
+            .get_mut::<DamageFlash>()
+            .unwrap()
+            .trigger(1.0);
+        game.automap().toggle();
+        game.render_frame();
+        // the corner is the black background, not the red of the flash
+        assert_eq!(pixel(&game, 0, 0), [0, 0, 0]);
+        // the 3D view is all red, away from the minimap
+        game.automap().toggle();
+        game.render_frame();
+        assert_eq!(pixel(&game, 250, 60), [255, 0, 0]);
+    }
+
+    #[test]
+    fn only_presented_frames_are_visible() {
+        let mut game = game_with_textures(HashMap::new());
+        game.canvas.fill(game.pixel_format.pack(255, 255, 0, 0));
+        // still drawing in the back buffer
+        assert_eq!(pixel(&game, 10, 10), [0, 0, 0]);
+        game.present();
+        assert_eq!(pixel(&game, 10, 10), [255, 0, 0]);
+        assert_eq!(game.get_buffer_to_print().len(), 320 * 200);
+        // the next frame starts in the other buffer
+        game.canvas.fill(game.pixel_format.pack(255, 0, 255, 0));
+        assert_eq!(pixel(&game, 10, 10), [255, 0, 0]);
     }
 }
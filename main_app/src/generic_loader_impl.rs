@@ -24,13 +24,9 @@ pub fn load_farbfeld(raw_bin: &[u8]) -> Result<Texture, Box<dyn Error>> {
         .read_image()
         .unwrap()
         .chunks_exact(2)
-        .into_iter()
         .map(|a| a[1]) //we could do .map(|a| u16::from_ne_bytes([a[0], a[1]])) here
         // but we only care about the first 8 bits
         .collect();
-    Ok(Texture {
-        width: w,
-        height: h,
-        data,
-    })
+    // the mip chain is generated here, once, so rendering never has to
+    Ok(Texture::new(w, h, data))
 }
@@ -0,0 +1,396 @@
+// The HUD is drawn on top of everything else, after the 3D view and the
+// post processing, so the effects don't shake or tint the text.
+// Text uses a bitmap font: a farbfeld sheet of fixed size glyphs
+// (see tools/gen_font.py), listed in resources.json like any other image.
+use crate::pixel_format::PixelFormat;
+use crate::texture::Texture;
+use std::collections::{HashMap, VecDeque};
+
+pub const FONT_TEXTURE_ID: u32 = 182;
+
+// how many frames are averaged to compute the FPS
+const FPS_SAMPLES: usize = 20;
+const MAX_MESSAGES: usize = 4;
+
+#[derive(Clone, Copy, Debug)]
+pub struct BitmapFont {
+    pub texture_id: u32,
+    pub glyph_width: u32,
+    pub glyph_height: u32,
+    // the character of the first glyph in the sheet
+    pub first_char: u8,
+}
+
+impl BitmapFont {
+    // the 5x7 font generated by tools/gen_font.py. 6x8 cells, from ' ' to '~'
+    pub fn default_font() -> Self {
+        BitmapFont {
+            texture_id: FONT_TEXTURE_ID,
+            glyph_width: 6,
+            glyph_height: 8,
+            first_char: b' ',
+        }
+    }
+
+    pub fn text_width(&self, text: &str) -> i32 {
+        (text.chars().count() as u32 * self.glyph_width) as i32
+    }
+
+    /**
+     * Draws a single line of text. x is the left side, the center or the
+     * right side of the text depending on the alignment, y is the top.
+     * Anything outside of the screen is clipped, characters missing from
+     * the sheet are drawn as blanks.
+     */
+    #[allow(clippy::too_many_arguments)]
+    pub fn draw_text(
+        &self,
+        canvas: &mut [u32],
+        width: usize,
+        height: usize,
+        texture: &Texture,
+        x: i32,
+        y: i32,
+        text: &str,
+        color: u32,
+        align: Align,
+    ) {
+        let columns = texture.width / self.glyph_width;
+        if columns == 0 {
+            return;
+        }
+        let mut pen_x = match align {
+            Align::Left => x,
+            Align::Center => x - self.text_width(text) / 2,
+            Align::Right => x - self.text_width(text),
+        };
+        for character in text.chars() {
+            let code = character as u32;
+            if code >= self.first_char as u32 {
+                let glyph = code - self.first_char as u32;
+                let sheet_x = (glyph % columns) * self.glyph_width;
+                let sheet_y = (glyph / columns) * self.glyph_height;
+                if sheet_y + self.glyph_height <= texture.height {
+                    self.draw_glyph(
+                        canvas, width, height, texture, sheet_x, sheet_y, pen_x, y, color,
+                    );
+                }
+            }
+            pen_x += self.glyph_width as i32;
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn draw_glyph(
+        &self,
+        canvas: &mut [u32],
+        width: usize,
+        height: usize,
+        texture: &Texture,
+        sheet_x: u32,
+        sheet_y: u32,
+        x: i32,
+        y: i32,
+        color: u32,
+    ) {
+        for row in 0..self.glyph_height {
+            let screen_y = y + row as i32;
+            if screen_y < 0 || screen_y >= height as i32 {
+                continue;
+            }
+            for column in 0..self.glyph_width {
+                let screen_x = x + column as i32;
+                if screen_x < 0 || screen_x >= width as i32 {
+                    continue;
+                }
+                let index = ((sheet_y + row) * texture.width + sheet_x + column) as usize * 4;
+                // only the alpha matters, the color is chosen when drawing
+                if texture.data[index + 3] != 0 {
+                    canvas[screen_y as usize * width + screen_x as usize] = color;
+                }
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Align {
+    Left,
+    Center,
+    Right,
+}
+
+// The corner or side of the screen a widget is attached to
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Anchor {
+    TopLeft,
+    Top,
+    TopRight,
+    Center,
+    BottomLeft,
+    Bottom,
+    BottomRight,
+}
+
+impl Anchor {
+    // screen position and text alignment for a block of text of this size
+    fn place(&self, width: i32, height: i32, text_height: i32) -> (i32, i32, Align) {
+        match self {
+            Anchor::TopLeft => (0, 0, Align::Left),
+            Anchor::Top => (width / 2, 0, Align::Center),
+            Anchor::TopRight => (width, 0, Align::Right),
+            Anchor::Center => (width / 2, (height - text_height) / 2, Align::Center),
+            Anchor::BottomLeft => (0, height - text_height, Align::Left),
+            Anchor::Bottom => (width / 2, height - text_height, Align::Center),
+            Anchor::BottomRight => (width, height - text_height, Align::Right),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum WidgetKind {
+    Fps,
+    Health,
+    Ammo,
+    Messages,
+    Label(String),
+}
+
+#[derive(Clone, Debug)]
+pub struct Widget {
+    pub kind: WidgetKind,
+    pub anchor: Anchor,
+    // moves the widget away from the anchor. Positive values go to the
+    // right and down, so the right and bottom anchors need negative values
+    pub offset_x: i32,
+    pub offset_y: i32,
+    // ARGB
+    pub color: (u8, u8, u8, u8),
+}
+
+impl Widget {
+    pub fn new(kind: WidgetKind, anchor: Anchor, offset_x: i32, offset_y: i32) -> Self {
+        Widget {
+            kind,
+            anchor,
+            offset_x,
+            offset_y,
+            color: (255, 255, 255, 255),
+        }
+    }
+
+    pub fn with_color(mut self, red: u8, green: u8, blue: u8) -> Self {
+        self.color = (255, red, green, blue);
+        self
+    }
+}
+
+struct Message {
+    text: String,
+    // seconds left on screen
+    time_left: f32,
+}
+
+pub struct Hud {
+    pub font: BitmapFont,
+    pub visible: bool,
+    // a dark copy of the text one pixel down and right, to read it over bright walls
+    pub shadow: bool,
+    pub widgets: Vec<Widget>,
+    pub health: Option<i32>,
+    pub ammo: Option<i32>,
+    frame_times: VecDeque<f32>,
+    messages: VecDeque<Message>,
+}
+
+impl Default for Hud {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Hud {
+    pub fn new() -> Self {
+        Hud {
+            font: BitmapFont::default_font(),
+            visible: true,
+            shadow: true,
+            widgets: vec![
+                Widget::new(WidgetKind::Fps, Anchor::TopRight, -2, 2).with_color(255, 255, 0),
+                Widget::new(WidgetKind::Messages, Anchor::Top, 0, 12),
+                Widget::new(WidgetKind::Health, Anchor::BottomLeft, 2, -2).with_color(255, 64, 64),
+                Widget::new(WidgetKind::Ammo, Anchor::BottomRight, -2, -2).with_color(255, 200, 64),
+            ],
+            health: None,
+            ammo: None,
+            frame_times: VecDeque::with_capacity(FPS_SAMPLES),
+            messages: VecDeque::new(),
+        }
+    }
+
+    /**
+     * Shows a message for `seconds`. The oldest message goes away
+     * if there are too many of them on screen.
+     */
+    pub fn show_message(&mut self, text: &str, seconds: f32) {
+        if self.messages.len() == MAX_MESSAGES {
+            self.messages.pop_front();
+        }
+        self.messages.push_back(Message {
+            text: text.to_string(),
+            time_left: seconds,
+        });
+    }
+
+    pub fn messages(&self) -> impl Iterator<Item = &str> {
+        self.messages.iter().map(|message| message.text.as_str())
+    }
+
+    /**
+     * Called once per frame with the time it took, in seconds.
+     * Updates the FPS average and expires old messages.
+     */
+    pub fn frame(&mut self, delta: f32) {
+        if self.frame_times.len() == FPS_SAMPLES {
+            self.frame_times.pop_front();
+        }
+        self.frame_times.push_back(delta);
+        for message in self.messages.iter_mut() {
+            message.time_left -= delta;
+        }
+        self.messages.retain(|message| message.time_left > 0.0);
+    }
+
+    pub fn fps(&self) -> f32 {
+        let total: f32 = self.frame_times.iter().sum();
+        if total <= 0.0 {
+            return 0.0;
+        }
+        self.frame_times.len() as f32 / total
+    }
+
+    // the lines of text a widget shows right now. Empty when there is nothing to show
+    fn lines(&self, kind: &WidgetKind) -> Vec<String> {
+        match kind {
+            WidgetKind::Fps => {
+                let fps = self.fps();
+                if fps <= 0.0 {
+                    return Vec::new();
+                }
+                vec![format!("FPS {:.0} ({:.1} ms)", fps, 1000.0 / fps)]
+            }
+            WidgetKind::Health => self
+                .health
+                .iter()
+                .map(|h| format!("HEALTH {}", h))
+                .collect(),
+            WidgetKind::Ammo => self.ammo.iter().map(|a| format!("AMMO {}", a)).collect(),
+            WidgetKind::Messages => self.messages().map(|m| m.to_string()).collect(),
+            WidgetKind::Label(text) => vec![text.clone()],
+        }
+    }
+
+    /**
+     * Draws every widget into the canvas. Nothing is drawn until the font
+     * texture has been loaded.
+     */
+    pub fn draw(
+        &self,
+        canvas: &mut [u32],
+        width: usize,
+        height: usize,
+        format: PixelFormat,
+        textures: &HashMap<u32, Texture>,
+    ) {
+        if !self.visible {
+            return;
+        }
+        let texture = match textures.get(&self.font.texture_id) {
+            Some(texture) => texture,
+            None => return,
+        };
+        let line_height = self.font.glyph_height as i32 + 1;
+        let shadow_color = format.pack(255, 0, 0, 0);
+        for widget in self.widgets.iter() {
+            let lines = self.lines(&widget.kind);
+            if lines.is_empty() {
+                continue;
+            }
+            let text_height = lines.len() as i32 * line_height - 1;
+            let (x, y, align) = widget
+                .anchor
+                .place(width as i32, height as i32, text_height);
+            let (a, r, g, b) = widget.color;
+            let color = format.pack(a, r, g, b);
+            for (number, line) in lines.iter().enumerate() {
+                let line_x = x + widget.offset_x;
+                let line_y = y + widget.offset_y + number as i32 * line_height;
+                if self.shadow {
+                    self.font.draw_text(
+                        canvas,
+                        width,
+                        height,
+                        texture,
+                        line_x + 1,
+                        line_y + 1,
+                        line,
+                        shadow_color,
+                        align,
+                    );
+                }
+                self.font.draw_text(
+                    canvas, width, height, texture, line_x, line_y, line, color, align,
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a two glyph sheet: ' ' is empty and '!' is a solid block
+    fn tiny_font() -> (BitmapFont, HashMap<u32, Texture>) {
+        let font = BitmapFont {
+            texture_id: 1,
+            glyph_width: 2,
+            glyph_height: 2,
+            first_char: b' ',
+        };
+        let mut data = Vec::new();
+        for _ in 0..2 {
+            data.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0, 0]);
+            data.extend_from_slice(&[255, 255, 255, 255, 255, 255, 255, 255]);
+        }
+        let mut textures = HashMap::new();
+        textures.insert(1, Texture::new(4, 2, data));
+        (font, textures)
+    }
+
+    #[test]
+    fn text_is_aligned_and_clipped() {
+        let (font, textures) = tiny_font();
+        let texture = &textures[&1];
+        let mut canvas = vec![0u32; 8 * 2];
+        font.draw_text(&mut canvas, 8, 2, texture, 8, 0, "! !", 7, Align::Right);
+        assert_eq!(&canvas[0..8], &[0, 0, 7, 7, 0, 0, 7, 7]);
+        // half of it is outside the screen, the rest is still drawn
+        let mut canvas = vec![0u32; 8 * 2];
+        font.draw_text(&mut canvas, 8, 2, texture, 0, -1, "!!", 3, Align::Center);
+        assert_eq!(&canvas[0..8], &[3, 3, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(&canvas[8..16], &[0; 8]);
+    }
+
+    #[test]
+    fn fps_and_messages_follow_the_frame_time() {
+        let mut hud = Hud::new();
+        hud.show_message("door opened", 0.05);
+        hud.show_message("key found", 1.0);
+        for _ in 0..4 {
+            hud.frame(0.02);
+        }
+        assert!((hud.fps() - 50.0).abs() < 0.01);
+        assert_eq!(hud.messages().collect::<Vec<_>>(), vec!["key found"]);
+    }
+}
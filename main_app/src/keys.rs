@@ -0,0 +1,110 @@
+// Keys and locked doors. A locked door has the key it needs in the bits 16
+// to 23 of its map cell (0 isn't locked). The keys lie around the level as
+// pickups, and the ones the player picked up are kept in the inventory.
+// Keys are never used up, a key opens every door of its color.
+
+use std::collections::BTreeSet;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum KeyColor {
+    Red,
+    Blue,
+    Yellow,
+}
+
+impl KeyColor {
+    // the key a door cell of the map needs, None if it isn't locked
+    pub fn lock_of(cell: u32) -> Option<KeyColor> {
+        match (cell >> 16) & 0xff {
+            1 => Some(KeyColor::Red),
+            2 => Some(KeyColor::Blue),
+            3 => Some(KeyColor::Yellow),
+            _ => None,
+        }
+    }
+
+    // the bits of a door cell that lock it with this key
+    pub fn lock_bits(self) -> u32 {
+        let lock = match self {
+            KeyColor::Red => 1,
+            KeyColor::Blue => 2,
+            KeyColor::Yellow => 3,
+        };
+        lock << 16
+    }
+
+    // for the HUD messages
+    pub fn name(self) -> &'static str {
+        match self {
+            KeyColor::Red => "RED",
+            KeyColor::Blue => "BLUE",
+            KeyColor::Yellow => "YELLOW",
+        }
+    }
+
+    // ARGB, the color of the doors it opens in the minimap and the automap
+    pub fn color(self) -> (u8, u8, u8, u8) {
+        match self {
+            KeyColor::Red => (255, 255, 40, 40),
+            KeyColor::Blue => (255, 60, 100, 255),
+            KeyColor::Yellow => (255, 255, 220, 0),
+        }
+    }
+
+    // the sprite of the key, see tools/gen_keys.py
+    pub fn texture_id(self) -> u32 {
+        match self {
+            KeyColor::Red => 187,
+            KeyColor::Blue => 186,
+            KeyColor::Yellow => 185,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Inventory {
+    keys: BTreeSet<KeyColor>,
+}
+
+impl Inventory {
+    pub fn new() -> Self {
+        Inventory::default()
+    }
+
+    // returns false if the player already had it
+    pub fn add_key(&mut self, key: KeyColor) -> bool {
+        self.keys.insert(key)
+    }
+
+    pub fn has_key(&self, key: KeyColor) -> bool {
+        self.keys.contains(&key)
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = KeyColor> + '_ {
+        self.keys.iter().copied()
+    }
+
+    // empties it, for a new game
+    pub fn clear(&mut self) {
+        self.keys.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn locks_are_read_from_the_map_and_opened_by_keys() {
+        let cell = 0x0102 | KeyColor::Blue.lock_bits();
+        assert_eq!(KeyColor::lock_of(cell), Some(KeyColor::Blue));
+        assert_eq!(KeyColor::lock_of(0x0102), None);
+
+        let mut inventory = Inventory::new();
+        assert!(!inventory.has_key(KeyColor::Blue));
+        assert!(inventory.add_key(KeyColor::Blue));
+        assert!(!inventory.add_key(KeyColor::Blue));
+        assert!(inventory.has_key(KeyColor::Blue));
+        assert_eq!(inventory.keys().collect::<Vec<_>>(), vec![KeyColor::Blue]);
+    }
+}
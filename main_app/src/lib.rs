@@ -1,7 +1,29 @@
 extern crate farfarbfeld;
 
+pub mod ai;
+pub mod automap;
+pub mod camera;
+pub mod clock;
+pub mod collision;
+pub mod controls;
+pub mod decals;
+pub mod doors;
+pub mod entities;
+pub mod events;
 pub mod game;
+pub mod hud;
+pub mod keys;
 pub mod loader;
+pub mod minimap;
+pub mod physics;
+pub mod pixel_format;
+pub mod portals;
+pub mod postprocess;
+pub mod pushwalls;
+pub mod screenshot;
+pub mod sound;
+pub mod texture;
+pub mod weapons;
 
 mod generic_loader_impl;
 
@@ -9,12 +9,7 @@ use std::collections::HashMap;
 #[cfg(feature = "web")]
 use std::{cell::RefCell, rc::Rc};
 
-#[derive(Clone)]
-pub struct Texture {
-    pub width: u32,
-    pub height: u32,
-    pub data: Vec<u8>,
-}
+pub use crate::texture::Texture;
 
 pub struct Assets {
     pub root: String,
@@ -66,9 +61,9 @@ impl FileLoader for LocalFileLoader {
         }
     }
     fn load_index_file(&mut self) -> Option<ResourceIndex>{
-        let raw_bin = load_raw_bin(&("/resources.json".to_owned())).unwrap();
+        let raw_bin = load_raw_bin("/resources.json").unwrap();
         let resources_str = std::str::from_utf8(&raw_bin).unwrap();
-        Some(serde_json::from_str(&resources_str).unwrap())
+        Some(serde_json::from_str(resources_str).unwrap())
     }
 }
 
@@ -103,7 +98,7 @@ impl Assets {
 
     pub fn load(&mut self){
         if let Some(resources) = &self.resources {
-            self.loader.load_textures(&resources, &mut self.textures)
+            self.loader.load_textures(resources, &mut self.textures)
         } else {
             panic!("Resources file not loaded");
         }
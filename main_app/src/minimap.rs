@@ -0,0 +1,419 @@
+// A small map of the surroundings drawn over the 3D view, centered on the
+// player. It can keep north up or turn with the player, and with the fog of
+// war enabled it only shows the cells the player has actually seen.
+// The renderer tells the minimap what every ray hit (see reveal_ray), which
+// is also what the optional debug rays are drawn from.
+use crate::keys::KeyColor;
+use crate::pixel_format::PixelFormat;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MinimapOrientation {
+    // the map doesn't move, the player marker turns
+    NorthUp,
+    // the map turns so the player always looks up
+    PlayerUp,
+}
+
+// ARGB colors. Alpha below 255 blends with the 3D view
+#[derive(Clone, Copy, Debug)]
+pub struct MinimapColors {
+    pub background: (u8, u8, u8, u8),
+    pub floor: (u8, u8, u8, u8),
+    pub wall: (u8, u8, u8, u8),
+    pub door: (u8, u8, u8, u8),
+    pub player: (u8, u8, u8, u8),
+}
+
+impl Default for MinimapColors {
+    fn default() -> Self {
+        MinimapColors {
+            background: (128, 0, 0, 0),
+            floor: (128, 70, 70, 70),
+            wall: (255, 200, 200, 200),
+            door: (255, 200, 50, 50),
+            player: (255, 255, 0, 0),
+        }
+    }
+}
+
+// a ray hit, in world coordinates
+struct DebugRay {
+    x: f32,
+    y: f32,
+    vertical: bool,
+}
+
+pub struct Minimap {
+    pub visible: bool,
+    // top left corner on the screen and size (it's square), in pixels
+    pub x: i32,
+    pub y: i32,
+    pub size: u32,
+    // pixels per map cell
+    pub zoom: f32,
+    pub orientation: MinimapOrientation,
+    pub colors: MinimapColors,
+    pub fog_of_war: bool,
+    // draw every ray cast by the renderer (debug)
+    pub show_rays: bool,
+    map_width: usize,
+    map_height: usize,
+    explored: Vec<bool>,
+    rays: Vec<DebugRay>,
+}
+
+impl Minimap {
+    pub fn new(map_width: usize, map_height: usize) -> Self {
+        Minimap {
+            visible: true,
+            x: 0,
+            y: 0,
+            size: 100,
+            zoom: 5.0,
+            orientation: MinimapOrientation::NorthUp,
+            colors: MinimapColors::default(),
+            fog_of_war: true,
+            show_rays: false,
+            map_width,
+            map_height,
+            explored: vec![false; map_width * map_height],
+            rays: Vec::new(),
+        }
+    }
+
+    // forgets everything the player has seen, for a new map
+    pub fn reset(&mut self, map_width: usize, map_height: usize) {
+        self.map_width = map_width;
+        self.map_height = map_height;
+        self.explored = vec![false; map_width * map_height];
+        self.rays.clear();
+    }
+
+    pub fn reveal_all(&mut self) {
+        self.explored.iter_mut().for_each(|cell| *cell = true);
+    }
+
+    pub fn is_explored(&self, x: i32, y: i32) -> bool {
+        self.cell_index(x, y)
+            .map(|index| self.explored[index])
+            .unwrap_or(false)
+    }
+
+    fn reveal(&mut self, x: i32, y: i32) {
+        if let Some(index) = self.cell_index(x, y) {
+            self.explored[index] = true;
+        }
+    }
+
+    fn cell_index(&self, x: i32, y: i32) -> Option<usize> {
+        if x < 0 || y < 0 || x as usize >= self.map_width || y as usize >= self.map_height {
+            return None;
+        }
+        Some(y as usize * self.map_width + x as usize)
+    }
+
+    // called before casting the rays of a new frame
+    pub fn begin_frame(&mut self) {
+        self.rays.clear();
+    }
+
+    /**
+     * Reveals the cells crossed by a ray going from the player to the point
+     * it hit, plus the cell that stopped it (the hit point is on its border,
+     * so it could round to the cell in front of it).
+     */
+    #[allow(clippy::too_many_arguments)]
+    pub fn reveal_ray(
+        &mut self,
+        from_x: f32,
+        from_y: f32,
+        to_x: f32,
+        to_y: f32,
+        hit_cell: (i32, i32),
+        vertical: bool,
+        tile_size: f32,
+    ) {
+        let length = (to_x - from_x).hypot(to_y - from_y);
+        // a quarter of a tile is small enough to not skip cells in practice
+        let steps = (length / (tile_size / 4.0)).ceil() as i32;
+        for step in 0..=steps {
+            let t = if steps == 0 {
+                0.0
+            } else {
+                step as f32 / steps as f32
+            };
+            let x = from_x + (to_x - from_x) * t;
+            let y = from_y + (to_y - from_y) * t;
+            self.reveal(
+                (x / tile_size).floor() as i32,
+                (y / tile_size).floor() as i32,
+            );
+        }
+        self.reveal(hit_cell.0, hit_cell.1);
+        if self.show_rays {
+            self.rays.push(DebugRay {
+                x: to_x,
+                y: to_y,
+                vertical,
+            });
+        }
+    }
+
+    /**
+     * Draws the minimap. `cell(x, y)` returns the map value of a cell,
+     * the same encoding the renderer uses (0 empty, 1 wall, 2 door,
+     * with the lock of the door in the bits 16 to 23).
+     * The player is always at the center of the minimap.
+     */
+    #[allow(clippy::too_many_arguments)]
+    pub fn draw(
+        &self,
+        canvas: &mut [u32],
+        width: usize,
+        height: usize,
+        format: PixelFormat,
+        cell: impl Fn(i32, i32) -> u32,
+        player_x: f32,
+        player_y: f32,
+        player_angle: f32,
+        tile_size: f32,
+    ) {
+        if !self.visible || self.zoom <= 0.0 {
+            return;
+        }
+        let half = self.size as f32 / 2.0;
+        let (sin, cos) = match self.orientation {
+            MinimapOrientation::NorthUp => (0.0, 1.0),
+            // turn the map so the player direction points up
+            MinimapOrientation::PlayerUp => {
+                let angle = player_angle + std::f32::consts::FRAC_PI_2;
+                (angle.sin(), angle.cos())
+            }
+        };
+        let world_per_pixel = tile_size / self.zoom;
+        for row in 0..self.size as i32 {
+            let screen_y = self.y + row;
+            if screen_y < 0 || screen_y >= height as i32 {
+                continue;
+            }
+            for column in 0..self.size as i32 {
+                let screen_x = self.x + column;
+                if screen_x < 0 || screen_x >= width as i32 {
+                    continue;
+                }
+                let dx = column as f32 + 0.5 - half;
+                let dy = row as f32 + 0.5 - half;
+                // rotate the screen offset back into the world
+                let world_x = player_x + (dx * cos - dy * sin) * world_per_pixel;
+                let world_y = player_y + (dx * sin + dy * cos) * world_per_pixel;
+                let cell_x = (world_x / tile_size).floor() as i32;
+                let cell_y = (world_y / tile_size).floor() as i32;
+                let hidden = self.fog_of_war && !self.is_explored(cell_x, cell_y);
+                let color = if hidden || self.cell_index(cell_x, cell_y).is_none() {
+                    self.colors.background
+                } else {
+                    let value = cell(cell_x, cell_y);
+                    match value & 0xf {
+                        0 => self.colors.floor,
+                        // locked doors show the color of their key
+                        2 => KeyColor::lock_of(value).map_or(self.colors.door, KeyColor::color),
+                        _ => self.colors.wall,
+                    }
+                };
+                blend_pixel(
+                    canvas,
+                    screen_y as usize * width + screen_x as usize,
+                    color,
+                    format,
+                );
+            }
+        }
+
+        // world to minimap pixels, relative to the center
+        let to_screen = |x: f32, y: f32| {
+            let dx = (x - player_x) / world_per_pixel;
+            let dy = (y - player_y) / world_per_pixel;
+            (
+                self.x as f32 + half + dx * cos + dy * sin,
+                self.y as f32 + half - dx * sin + dy * cos,
+            )
+        };
+        let center = (self.x as f32 + half, self.y as f32 + half);
+        for ray in self.rays.iter() {
+            let color = if ray.vertical {
+                (255, 0, 0, 255)
+            } else {
+                (255, 0, 255, 0)
+            };
+            let end = to_screen(ray.x, ray.y);
+            draw_line(
+                canvas,
+                width,
+                height,
+                format,
+                self.clip(),
+                center,
+                end,
+                color,
+            );
+        }
+        // the player and where it's looking at
+        let look = to_screen(
+            player_x + player_angle.cos() * world_per_pixel * 8.0,
+            player_y + player_angle.sin() * world_per_pixel * 8.0,
+        );
+        draw_line(
+            canvas,
+            width,
+            height,
+            format,
+            self.clip(),
+            center,
+            look,
+            self.colors.player,
+        );
+        for (dx, dy) in [(-1.0, -1.0), (0.0, -1.0), (-1.0, 0.0), (0.0, 0.0)] {
+            let point = (center.0 + dx, center.1 + dy);
+            draw_line(
+                canvas,
+                width,
+                height,
+                format,
+                self.clip(),
+                point,
+                point,
+                self.colors.player,
+            );
+        }
+    }
+
+    // the area lines are clipped to: the minimap itself
+    fn clip(&self) -> (i32, i32, i32, i32) {
+        (
+            self.x,
+            self.y,
+            self.x + self.size as i32,
+            self.y + self.size as i32,
+        )
+    }
+}
+
+/**
+ * Draws a line between two points, only inside the clip rectangle
+ * (left, top, right, bottom, right and bottom excluded) and the screen.
+ * Shared with the automap.
+ */
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn draw_line(
+    canvas: &mut [u32],
+    width: usize,
+    height: usize,
+    format: PixelFormat,
+    clip: (i32, i32, i32, i32),
+    start: (f32, f32),
+    end: (f32, f32),
+    color: (u8, u8, u8, u8),
+) {
+    let (left, top, right, bottom) = clip;
+    let left = left.max(0);
+    let top = top.max(0);
+    let right = right.min(width as i32);
+    let bottom = bottom.min(height as i32);
+    let steps = (end.0 - start.0).abs().max((end.1 - start.1).abs()).ceil() as i32;
+    for step in 0..=steps {
+        let t = if steps == 0 {
+            0.0
+        } else {
+            step as f32 / steps as f32
+        };
+        let x = (start.0 + (end.0 - start.0) * t).floor() as i32;
+        let y = (start.1 + (end.1 - start.1) * t).floor() as i32;
+        if x >= left && y >= top && x < right && y < bottom {
+            blend_pixel(canvas, y as usize * width + x as usize, color, format);
+        }
+    }
+}
+
+// draws a pixel over the canvas, mixing them if the color is translucent
+pub(crate) fn blend_pixel(
+    canvas: &mut [u32],
+    index: usize,
+    color: (u8, u8, u8, u8),
+    format: PixelFormat,
+) {
+    let (alpha, red, green, blue) = color;
+    if alpha == 255 {
+        canvas[index] = format.pack(255, red, green, blue);
+        return;
+    }
+    let (_, dst_red, dst_green, dst_blue) = format.unpack(canvas[index]);
+    let mix = |src: u8, dst: u8| {
+        ((src as u32 * alpha as u32 + dst as u32 * (255 - alpha as u32)) / 255) as u8
+    };
+    canvas[index] = format.pack(
+        255,
+        mix(red, dst_red),
+        mix(green, dst_green),
+        mix(blue, dst_blue),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a corridor going east, walls everywhere else
+    fn corridor(x: i32, y: i32) -> u32 {
+        if y == 2 && (0..8).contains(&x) {
+            0
+        } else {
+            1
+        }
+    }
+
+    #[test]
+    fn rays_reveal_the_cells_they_cross() {
+        let mut minimap = Minimap::new(8, 8);
+        // from the middle of (1, 2) to the wall at x = 8 * 64
+        minimap.reveal_ray(96.0, 160.0, 511.9, 160.0, (7, 2), true, 64.0);
+        assert!((1..8).all(|x| minimap.is_explored(x, 2)));
+        assert!(!minimap.is_explored(0, 2));
+        assert!(!minimap.is_explored(1, 1));
+    }
+
+    #[test]
+    fn player_up_turns_the_map() {
+        let mut minimap = Minimap::new(8, 8);
+        minimap.reveal_all();
+        minimap.size = 20;
+        minimap.zoom = 4.0;
+        minimap.colors.floor = (255, 0, 0, 0);
+        minimap.colors.wall = (255, 255, 255, 255);
+        let white = PixelFormat::Bgra8.pack(255, 255, 255, 255);
+        let black = PixelFormat::Bgra8.pack(255, 0, 0, 0);
+        let draw = |minimap: &Minimap| {
+            let mut canvas = vec![0u32; 20 * 20];
+            minimap.draw(
+                &mut canvas,
+                20,
+                20,
+                PixelFormat::Bgra8,
+                corridor,
+                96.0,
+                160.0,
+                0.0,
+                64.0,
+            );
+            canvas
+        };
+        // north up: the corridor is horizontal
+        let canvas = draw(&minimap);
+        assert_eq!(canvas[8 * 20 + 18], black);
+        assert_eq!(canvas[4 * 20 + 10], white);
+        // looking east with the map turned: the corridor goes up
+        minimap.orientation = MinimapOrientation::PlayerUp;
+        let canvas = draw(&minimap);
+        assert_eq!(canvas[2 * 20 + 8], black);
+        assert_eq!(canvas[10 * 20 + 16], white);
+    }
+}
@@ -0,0 +1,218 @@
+// Vertical movement of the player: gravity, jumping, landing and crouching.
+// It runs once per tick of the fixed timestep, so jumps are the same height
+// at any frame rate. Heights are in world units above the floor at 0, the
+// walls go from 0 to the ceiling. The fly mode (a debug option) turns
+// gravity off and moves the player up and down freely.
+
+// what the player wants to do during a tick
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct VerticalInput {
+    pub jump: bool,
+    pub crouch: bool,
+    // only used when flying
+    pub fly_up: bool,
+    pub fly_down: bool,
+}
+
+pub struct VerticalBody {
+    // height of the feet
+    pub z: f32,
+    // units per tick, up is positive
+    pub velocity: f32,
+    pub on_ground: bool,
+    pub crouching: bool,
+    // height of the eye above the feet, when standing and crouching
+    pub stand_eye_height: f32,
+    pub crouch_eye_height: f32,
+    // units per tick added to the velocity every tick, going down
+    pub gravity: f32,
+    // velocity when the jump starts
+    pub jump_speed: f32,
+    // movement speed multiplier while crouching
+    pub crouch_speed: f32,
+    // no gravity, fly_up and fly_down move the player. Debug only
+    pub fly: bool,
+    // the eye moves towards the standing or crouching height, instead of
+    // jumping there in a single tick
+    eye_height: f32,
+}
+
+impl Default for VerticalBody {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// the head doesn't go closer than this to the ceiling
+const HEAD_ROOM: f32 = 5.0;
+// units the eye moves per tick when crouching or standing up
+const CROUCH_SPEED: f32 = 2.0;
+// units per tick in fly mode
+const FLY_SPEED: f32 = 1.0;
+
+impl VerticalBody {
+    pub fn new() -> Self {
+        VerticalBody {
+            z: 0.0,
+            velocity: 0.0,
+            on_ground: true,
+            crouching: false,
+            stand_eye_height: 32.0,
+            crouch_eye_height: 16.0,
+            gravity: 0.5,
+            // 14 units high, with this gravity
+            jump_speed: 4.0,
+            crouch_speed: 0.5,
+            fly: false,
+            eye_height: 32.0,
+        }
+    }
+
+    // the height of the eye, the camera goes there
+    pub fn eye(&self) -> f32 {
+        self.z + self.eye_height
+    }
+
+    // how fast the player can walk, compared to standing
+    pub fn speed_factor(&self) -> f32 {
+        if self.crouching && self.on_ground && !self.fly {
+            self.crouch_speed
+        } else {
+            1.0
+        }
+    }
+
+    /**
+     * Moves the body one tick. `floor` is the height of the floor under
+     * it and `ceiling` the height of the ceiling. When the floor is lower
+     * than the feet the body falls until it lands on it.
+     */
+    pub fn tick(&mut self, input: VerticalInput, floor: f32, ceiling: f32) {
+        if self.fly {
+            self.velocity = 0.0;
+            if input.fly_up {
+                self.z += FLY_SPEED;
+            } else if input.fly_down {
+                self.z -= FLY_SPEED;
+            }
+            // the eye can go a bit under the floor, like it always could
+            self.z = self.z.clamp(
+                floor - HEAD_ROOM - self.eye_height,
+                ceiling - HEAD_ROOM - self.eye_height,
+            );
+            self.on_ground = self.z <= floor;
+            return;
+        }
+
+        // it can't stand up under a low ceiling
+        let can_stand = ceiling - self.z >= self.stand_eye_height + HEAD_ROOM;
+        self.crouching = input.crouch || (self.crouching && !can_stand);
+        let target_eye = if self.crouching {
+            self.crouch_eye_height
+        } else {
+            self.stand_eye_height
+        };
+        self.eye_height += (target_eye - self.eye_height).clamp(-CROUCH_SPEED, CROUCH_SPEED);
+
+        if self.on_ground && input.jump && !self.crouching {
+            self.velocity = self.jump_speed;
+            self.on_ground = false;
+        }
+        if self.z > floor {
+            // walked off a ledge
+            self.on_ground = false;
+        }
+        if !self.on_ground {
+            self.velocity -= self.gravity;
+            self.z += self.velocity;
+        }
+        // bumped the head
+        if self.eye() > ceiling - HEAD_ROOM {
+            self.z = ceiling - HEAD_ROOM - self.eye_height;
+            self.velocity = self.velocity.min(0.0);
+        }
+        // landed, or the floor went up under it
+        if self.z <= floor {
+            self.z = floor;
+            self.velocity = 0.0;
+            self.on_ground = true;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const JUMP: VerticalInput = VerticalInput {
+        jump: true,
+        crouch: false,
+        fly_up: false,
+        fly_down: false,
+    };
+
+    #[test]
+    fn jumps_go_up_and_land_back() {
+        let mut body = VerticalBody::new();
+        body.tick(JUMP, 0.0, 64.0);
+        assert!(!body.on_ground && body.z > 0.0);
+        let mut highest = body.z;
+        let mut ticks = 1;
+        while !body.on_ground {
+            // holding jump doesn't jump again in the air
+            body.tick(JUMP, 0.0, 64.0);
+            highest = highest.max(body.z);
+            ticks += 1;
+        }
+        assert_eq!((body.z, ticks), (0.0, 15));
+        assert_eq!(highest, 14.0);
+
+        // a lower floor makes it fall until it lands there
+        body.tick(VerticalInput::default(), -20.0, 64.0);
+        assert!(!body.on_ground);
+        for _ in 0..20 {
+            body.tick(VerticalInput::default(), -20.0, 64.0);
+        }
+        assert_eq!((body.z, body.on_ground), (-20.0, true));
+    }
+
+    #[test]
+    fn crouching_lowers_the_eye_and_slows_down() {
+        let mut body = VerticalBody::new();
+        let crouch = VerticalInput {
+            crouch: true,
+            ..VerticalInput::default()
+        };
+        for _ in 0..10 {
+            body.tick(crouch, 0.0, 64.0);
+        }
+        assert_eq!(body.eye(), 16.0);
+        assert_eq!(body.speed_factor(), 0.5);
+        // no jumping while crouching
+        body.tick(
+            VerticalInput {
+                crouch: true,
+                ..JUMP
+            },
+            0.0,
+            64.0,
+        );
+        assert!(body.on_ground);
+
+        // under a low ceiling it stays crouched
+        body.tick(VerticalInput::default(), 0.0, 30.0);
+        assert!(body.crouching);
+        body.tick(VerticalInput::default(), 0.0, 64.0);
+        assert!(!body.crouching && body.eye() == 18.0);
+
+        body.fly = true;
+        let up = VerticalInput {
+            fly_up: true,
+            ..VerticalInput::default()
+        };
+        for _ in 0..100 {
+            body.tick(up, 0.0, 64.0);
+        }
+        assert_eq!(body.eye(), 64.0 - 5.0);
+    }
+}
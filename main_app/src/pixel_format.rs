@@ -0,0 +1,78 @@
+// The layout of the u32 pixels the renderer writes. It's chosen when the
+// renderer is created, so the same build can draw for minifb on the
+// desktop, a browser canvas or an image encoder. Everything that writes
+// or reads the frame goes through pack and unpack.
+// Alpha is always the highest byte, so effects that scale the three color
+// channels the same way don't need to know the order of the other three.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PixelFormat {
+    // 0xAARRGGBB. In memory (little endian) the bytes are B, G, R, A.
+    // This is what minifb expects on the desktop.
+    Bgra8,
+    // 0xAABBGGRR. In memory (little endian) the bytes are R, G, B, A,
+    // the order of a canvas ImageData and of most image encoders.
+    Rgba8,
+}
+
+impl PixelFormat {
+    // the format minifb expects on this platform
+    pub fn minifb() -> Self {
+        #[cfg(not(feature = "web"))]
+        {
+            PixelFormat::Bgra8
+        }
+        #[cfg(feature = "web")]
+        {
+            PixelFormat::Rgba8
+        }
+    }
+
+    #[inline]
+    pub fn pack(self, alpha: u8, red: u8, green: u8, blue: u8) -> u32 {
+        match self {
+            PixelFormat::Bgra8 => {
+                ((alpha as u32) << 24) | ((red as u32) << 16) | ((green as u32) << 8) | blue as u32
+            }
+            PixelFormat::Rgba8 => {
+                ((alpha as u32) << 24) | ((blue as u32) << 16) | ((green as u32) << 8) | red as u32
+            }
+        }
+    }
+
+    // The opposite of pack. Returns (alpha, red, green, blue)
+    #[inline]
+    pub fn unpack(self, color: u32) -> (u8, u8, u8, u8) {
+        let alpha = (color >> 24) as u8;
+        let green = (color >> 8) as u8;
+        match self {
+            PixelFormat::Bgra8 => (alpha, (color >> 16) as u8, green, color as u8),
+            PixelFormat::Rgba8 => (alpha, color as u8, green, (color >> 16) as u8),
+        }
+    }
+
+    // converts RGBA bytes (the order textures are stored in) to pixels
+    pub fn pack_rgba_bytes(self, bytes: &[u8]) -> Vec<u32> {
+        bytes
+            .chunks_exact(4)
+            .map(|p| self.pack(p[3], p[0], p[1], p[2]))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_roundtrip_and_match_their_byte_order() {
+        for format in [PixelFormat::Bgra8, PixelFormat::Rgba8] {
+            let color = format.pack(255, 10, 20, 30);
+            assert_eq!(format.unpack(color), (255, 10, 20, 30));
+        }
+        let bgra = PixelFormat::Bgra8.pack(255, 10, 20, 30).to_le_bytes();
+        assert_eq!(bgra, [30, 20, 10, 255]);
+        let rgba = PixelFormat::Rgba8.pack_rgba_bytes(&[10, 20, 30, 255]);
+        assert_eq!(rgba[0].to_le_bytes(), [10, 20, 30, 255]);
+    }
+}
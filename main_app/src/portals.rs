@@ -0,0 +1,220 @@
+// Teleporters and portals. A teleporter is a cell of the floor that sends
+// what steps on it to another cell. A portal links two wall faces: a ray
+// going into one of them comes out of the other one, turned as much as the
+// faces are turned, so the two sides look like a single place. Portals are
+// only seen through, walking through them needs a teleporter.
+
+use crate::decals::{Side, WallFace};
+
+// the floor of the teleporters, see tools/gen_teleporter.py
+pub const TELEPORTER_TEXTURE_ID: u32 = 190;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Teleporter {
+    pub cell_x: i32,
+    pub cell_y: i32,
+    // the cell things appear in, at its center
+    pub destination: (i32, i32),
+    // the direction the player looks after it, in radians. None keeps it
+    pub angle: Option<f32>,
+    // the actors can use it too, not only the player
+    pub actors: bool,
+}
+
+impl Teleporter {
+    pub fn new(cell_x: i32, cell_y: i32, destination: (i32, i32)) -> Self {
+        Teleporter {
+            cell_x,
+            cell_y,
+            destination,
+            angle: None,
+            actors: false,
+        }
+    }
+
+    // the center of the destination cell
+    pub fn destination_point(&self, tile_size: f32) -> (f32, f32) {
+        (
+            (self.destination.0 as f32 + 0.5) * tile_size,
+            (self.destination.1 as f32 + 0.5) * tile_size,
+        )
+    }
+}
+
+// a part of a ray that went through portals, see GameWindow::cast_ray_path
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RaySegment {
+    // the distance along the whole ray where this part starts
+    pub start: f32,
+    // where it starts and where it goes
+    pub x: f32,
+    pub y: f32,
+    pub angle: f32,
+    pub cos: f32,
+    pub sin: f32,
+    // the face the ray went into to get here. None for the first part
+    pub portal: Option<WallFace>,
+}
+
+impl RaySegment {
+    pub fn new(start: f32, x: f32, y: f32, angle: f32, portal: Option<WallFace>) -> Self {
+        let (sin, cos) = angle.sin_cos();
+        RaySegment {
+            start,
+            x,
+            y,
+            angle,
+            cos,
+            sin,
+            portal,
+        }
+    }
+
+    // the point at `distance` along the whole ray
+    pub fn point(&self, distance: f32) -> (f32, f32) {
+        let along = distance - self.start;
+        (self.x + self.cos * along, self.y + self.sin * along)
+    }
+}
+
+// the direction a face looks at, in radians
+fn normal_angle(side: Side) -> f32 {
+    use std::f32::consts::{FRAC_PI_2, PI};
+    match side {
+        Side::East => 0.0,
+        Side::South => FRAC_PI_2,
+        Side::West => PI,
+        Side::North => -FRAC_PI_2,
+    }
+}
+
+// the middle of a face, in world units
+fn face_center(face: WallFace, tile_size: f32) -> (f32, f32) {
+    let (x, y) = (face.cell_x as f32, face.cell_y as f32);
+    let (x, y) = match face.side {
+        Side::North => (x + 0.5, y),
+        Side::South => (x + 0.5, y + 1.0),
+        Side::West => (x, y + 0.5),
+        Side::East => (x + 1.0, y + 0.5),
+    };
+    (x * tile_size, y * tile_size)
+}
+
+pub struct Portals {
+    links: Vec<(WallFace, WallFace)>,
+    // how many portals a ray goes through. After that portals are drawn
+    // as the walls they are on
+    pub max_hops: usize,
+}
+
+impl Default for Portals {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Portals {
+    pub fn new() -> Self {
+        Portals {
+            links: Vec::new(),
+            max_hops: 4,
+        }
+    }
+
+    // links two faces, both ways. The faces must look at empty cells
+    pub fn link(&mut self, a: WallFace, b: WallFace) {
+        self.links.push((a, b));
+    }
+
+    pub fn clear(&mut self) {
+        self.links.clear();
+    }
+
+    // the face linked to this one
+    pub fn exit(&self, face: WallFace) -> Option<WallFace> {
+        self.links.iter().find_map(|&(a, b)| {
+            if a == face {
+                Some(b)
+            } else if b == face {
+                Some(a)
+            } else {
+                None
+            }
+        })
+    }
+
+    /**
+     * Where a ray that hit `entry` at x, y going in the direction of
+     * `angle` comes out of `exit`, as (x, y, angle). It's turned around
+     * the centers of the faces, so it leaves the exit face the way it
+     * went into the entry face.
+     */
+    pub fn through(
+        entry: WallFace,
+        exit: WallFace,
+        tile_size: f32,
+        x: f32,
+        y: f32,
+        angle: f32,
+    ) -> (f32, f32, f32) {
+        let turn = normal_angle(exit.side) - normal_angle(entry.side) + std::f32::consts::PI;
+        let (sin, cos) = turn.sin_cos();
+        let (entry_x, entry_y) = face_center(entry, tile_size);
+        let (exit_x, exit_y) = face_center(exit, tile_size);
+        let (dx, dy) = (x - entry_x, y - entry_y);
+        (
+            exit_x + dx * cos - dy * sin,
+            exit_y + dx * sin + dy * cos,
+            angle + turn,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::{FRAC_PI_2, PI};
+
+    fn face(cell_x: i32, cell_y: i32, side: Side) -> WallFace {
+        WallFace {
+            cell_x,
+            cell_y,
+            side,
+        }
+    }
+
+    fn assert_near((x, y, angle): (f32, f32, f32), expected: (f32, f32, f32)) {
+        assert!(
+            (x - expected.0).abs() < 0.001
+                && (y - expected.1).abs() < 0.001
+                && (angle - expected.2)
+                    .rem_euclid(2.0 * PI)
+                    .min((expected.2 - angle).rem_euclid(2.0 * PI))
+                    < 0.001,
+            "{:?} is not {:?}",
+            (x, y, angle),
+            expected
+        );
+    }
+
+    #[test]
+    fn rays_come_out_of_the_linked_face() {
+        let mut portals = Portals::new();
+        let west = face(1, 1, Side::West);
+        let south = face(5, 2, Side::South);
+        portals.link(west, south);
+        assert_eq!(portals.exit(south), Some(west));
+        assert_eq!(portals.exit(face(1, 1, Side::East)), None);
+
+        // going east into the west face of the cell at 1, 1, a bit north of
+        // its middle. It leaves the south face going south, a bit east of
+        // the middle, like it turned right
+        let out = Portals::through(west, south, 64.0, 64.0, 90.0, 0.0);
+        assert_near(out, (358.0, 192.0, FRAC_PI_2));
+
+        // opposite faces don't turn at all
+        let east = face(9, 1, Side::East);
+        let out = Portals::through(west, east, 64.0, 64.0, 90.0, 0.3);
+        assert_near(out, (640.0, 90.0, 0.3));
+    }
+}
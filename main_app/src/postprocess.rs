@@ -0,0 +1,511 @@
+// Post-processing runs on the finished 3D view, after the walls and the
+// sprites are drawn. The minimap and the HUD are drawn after it.
+// Effects are applied in the order they were added. Each one can be
+// enabled, disabled or tweaked at any time, so gameplay code can trigger
+// them (a red flash when the player gets hit, a shake on explosions...)
+use crate::pixel_format::PixelFormat;
+use std::any::Any;
+
+pub trait PostEffect: Any {
+    // Used to find the effect in the pipeline
+    fn name(&self) -> &'static str;
+    fn apply(&mut self, frame: &mut [u32], width: usize, height: usize, format: PixelFormat);
+    // Called once per game tick, for effects that change over time. They
+    // must not depend on how many frames are drawn
+    fn tick(&mut self) {}
+    // Needed to change the parameters of a concrete effect. See PostProcess::get_mut
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+struct PostProcessEntry {
+    effect: Box<dyn PostEffect>,
+    enabled: bool,
+}
+
+#[derive(Default)]
+pub struct PostProcess {
+    effects: Vec<PostProcessEntry>,
+}
+
+impl PostProcess {
+    pub fn new() -> Self {
+        PostProcess::default()
+    }
+
+    // adds an effect at the end of the chain
+    pub fn push(&mut self, effect: Box<dyn PostEffect>, enabled: bool) {
+        self.effects.push(PostProcessEntry { effect, enabled });
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<Box<dyn PostEffect>> {
+        let position = self.effects.iter().position(|e| e.effect.name() == name)?;
+        Some(self.effects.remove(position).effect)
+    }
+
+    pub fn set_enabled(&mut self, name: &str, enabled: bool) {
+        for entry in self.effects.iter_mut() {
+            if entry.effect.name() == name {
+                entry.enabled = enabled;
+            }
+        }
+    }
+
+    pub fn is_enabled(&self, name: &str) -> bool {
+        self.effects
+            .iter()
+            .any(|e| e.enabled && e.effect.name() == name)
+    }
+
+    /**
+     * Returns the first effect of the given type, so its parameters
+     * can be changed. e.g.: get_mut::<DamageFlash>().unwrap().trigger(0.5)
+     */
+    pub fn get_mut<T: PostEffect>(&mut self) -> Option<&mut T> {
+        self.effects
+            .iter_mut()
+            .find_map(|e| e.effect.as_any_mut().downcast_mut::<T>())
+    }
+
+    pub fn apply(&mut self, frame: &mut [u32], width: usize, height: usize, format: PixelFormat) {
+        for entry in self.effects.iter_mut().filter(|e| e.enabled) {
+            entry.effect.apply(frame, width, height, format);
+        }
+    }
+
+    // advances the effects that fade or run out, see PostEffect::tick
+    pub fn tick(&mut self) {
+        for entry in self.effects.iter_mut() {
+            entry.effect.tick();
+        }
+    }
+}
+
+macro_rules! impl_as_any {
+    () => {
+        fn as_any_mut(&mut self) -> &mut dyn Any {
+            self
+        }
+    };
+}
+
+#[inline]
+fn scale_color(color: u32, factor: f32, format: PixelFormat) -> u32 {
+    let (a, r, g, b) = format.unpack(color);
+    format.pack(
+        a,
+        (r as f32 * factor) as u8,
+        (g as f32 * factor) as u8,
+        (b as f32 * factor) as u8,
+    )
+}
+
+#[inline]
+fn mix(from: u8, to: u8, amount: f32) -> u8 {
+    (from as f32 + (to as f32 - from as f32) * amount) as u8
+}
+
+//*******************************************************************//
+//* Blends the whole screen with a color, and fades out on every tick
+//*******************************************************************//
+pub struct DamageFlash {
+    pub color: (u8, u8, u8),
+    // 0 is invisible, 1 replaces the frame with the color
+    pub intensity: f32,
+    // how much intensity is lost on every tick
+    pub fade: f32,
+}
+
+impl DamageFlash {
+    pub const NAME: &'static str = "damage_flash";
+
+    pub fn new() -> Self {
+        DamageFlash {
+            color: (255, 0, 0),
+            intensity: 0.0,
+            fade: 0.05,
+        }
+    }
+
+    pub fn trigger(&mut self, intensity: f32) {
+        self.intensity = intensity.clamp(0.0, 1.0);
+    }
+}
+
+impl Default for DamageFlash {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PostEffect for DamageFlash {
+    fn name(&self) -> &'static str {
+        Self::NAME
+    }
+
+    fn apply(&mut self, frame: &mut [u32], _width: usize, _height: usize, format: PixelFormat) {
+        if self.intensity <= 0.0 {
+            return;
+        }
+        let amount = self.intensity;
+        for pixel in frame.iter_mut() {
+            let (a, r, g, b) = format.unpack(*pixel);
+            *pixel = format.pack(
+                a,
+                mix(r, self.color.0, amount),
+                mix(g, self.color.1, amount),
+                mix(b, self.color.2, amount),
+            );
+        }
+    }
+
+    fn tick(&mut self) {
+        self.intensity = (self.intensity - self.fade).max(0.0);
+    }
+
+    impl_as_any!();
+}
+
+//*******************************************************************//
+//* Adds a fixed amount to each channel. Think of the green tint
+//* of a radiation suit
+//*******************************************************************//
+pub struct PaletteShift {
+    pub red: i16,
+    pub green: i16,
+    pub blue: i16,
+}
+
+impl PaletteShift {
+    pub const NAME: &'static str = "palette_shift";
+
+    pub fn new(red: i16, green: i16, blue: i16) -> Self {
+        PaletteShift { red, green, blue }
+    }
+}
+
+impl PostEffect for PaletteShift {
+    fn name(&self) -> &'static str {
+        Self::NAME
+    }
+
+    fn apply(&mut self, frame: &mut [u32], _width: usize, _height: usize, format: PixelFormat) {
+        let shift = |value: u8, delta: i16| (value as i16 + delta).clamp(0, 255) as u8;
+        for pixel in frame.iter_mut() {
+            let (a, r, g, b) = format.unpack(*pixel);
+            *pixel = format.pack(
+                a,
+                shift(r, self.red),
+                shift(g, self.green),
+                shift(b, self.blue),
+            );
+        }
+    }
+
+    impl_as_any!();
+}
+
+//*******************************************************************//
+//* Moves the whole frame a few pixels in a random direction.
+//* The pixels that are left uncovered repeat the border of the frame
+//*******************************************************************//
+pub struct ScreenShake {
+    // maximum offset in pixels
+    pub amplitude: f32,
+    // ticks left
+    pub duration: u32,
+    seed: u32,
+    scratch: Vec<u32>,
+}
+
+impl ScreenShake {
+    pub const NAME: &'static str = "screen_shake";
+
+    pub fn new() -> Self {
+        ScreenShake {
+            amplitude: 0.0,
+            duration: 0,
+            seed: 0x2545_f491,
+            scratch: Vec::new(),
+        }
+    }
+
+    pub fn trigger(&mut self, amplitude: f32, duration: u32) {
+        self.amplitude = amplitude;
+        self.duration = duration;
+    }
+
+    // small xorshift, we don't need anything better for this
+    fn next_offset(&mut self) -> i32 {
+        self.seed ^= self.seed << 13;
+        self.seed ^= self.seed >> 17;
+        self.seed ^= self.seed << 5;
+        let range = self.amplitude as i32 * 2 + 1;
+        (self.seed % range as u32) as i32 - self.amplitude as i32
+    }
+}
+
+impl Default for ScreenShake {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PostEffect for ScreenShake {
+    fn name(&self) -> &'static str {
+        Self::NAME
+    }
+
+    fn apply(&mut self, frame: &mut [u32], width: usize, height: usize, _format: PixelFormat) {
+        if self.duration == 0 || self.amplitude < 1.0 {
+            return;
+        }
+        let dx = self.next_offset();
+        let dy = self.next_offset();
+        self.scratch.clear();
+        self.scratch.extend_from_slice(frame);
+        for y in 0..height {
+            let src_y = (y as i32 - dy).clamp(0, height as i32 - 1) as usize;
+            for x in 0..width {
+                let src_x = (x as i32 - dx).clamp(0, width as i32 - 1) as usize;
+                frame[y * width + x] = self.scratch[src_y * width + src_x];
+            }
+        }
+    }
+
+    fn tick(&mut self) {
+        self.duration = self.duration.saturating_sub(1);
+    }
+
+    impl_as_any!();
+}
+
+//*******************************************************************//
+//* CRT look: darkens every few rows
+//*******************************************************************//
+pub struct Scanlines {
+    // 0 leaves the row untouched, 1 makes it black
+    pub darkness: f32,
+    // a row out of every `spacing` rows is darkened
+    pub spacing: usize,
+}
+
+impl Scanlines {
+    pub const NAME: &'static str = "scanlines";
+
+    pub fn new() -> Self {
+        Scanlines {
+            darkness: 0.4,
+            spacing: 2,
+        }
+    }
+}
+
+impl Default for Scanlines {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PostEffect for Scanlines {
+    fn name(&self) -> &'static str {
+        Self::NAME
+    }
+
+    fn apply(&mut self, frame: &mut [u32], width: usize, _height: usize, format: PixelFormat) {
+        let factor = 1.0 - self.darkness.clamp(0.0, 1.0);
+        for row in frame.chunks_exact_mut(width).step_by(self.spacing.max(1)) {
+            for pixel in row.iter_mut() {
+                *pixel = scale_color(*pixel, factor, format);
+            }
+        }
+    }
+
+    impl_as_any!();
+}
+
+//*******************************************************************//
+//* Darkens the corners of the screen
+//*******************************************************************//
+pub struct Vignette {
+    // how dark the corners get (0 to 1)
+    pub strength: f32,
+    // distance from the center (0 center, 1 corner) where the darkening starts
+    pub radius: f32,
+    // factors are cached, they only change with the parameters or the size
+    cache: Vec<f32>,
+    cache_key: (usize, usize, u32, u32),
+}
+
+impl Vignette {
+    pub const NAME: &'static str = "vignette";
+
+    pub fn new() -> Self {
+        Vignette {
+            strength: 0.6,
+            radius: 0.5,
+            cache: Vec::new(),
+            cache_key: (0, 0, 0, 0),
+        }
+    }
+
+    fn update_cache(&mut self, width: usize, height: usize) {
+        let key = (
+            width,
+            height,
+            self.strength.to_bits(),
+            self.radius.to_bits(),
+        );
+        if key == self.cache_key {
+            return;
+        }
+        self.cache_key = key;
+        self.cache.clear();
+        let half_width = width as f32 / 2.0;
+        let half_height = height as f32 / 2.0;
+        let max_distance = half_width.hypot(half_height);
+        for y in 0..height {
+            for x in 0..width {
+                let distance = (x as f32 + 0.5 - half_width).hypot(y as f32 + 0.5 - half_height)
+                    / max_distance;
+                let t = ((distance - self.radius) / (1.0 - self.radius).max(0.001)).clamp(0.0, 1.0);
+                self.cache.push(1.0 - self.strength * t * t);
+            }
+        }
+    }
+}
+
+impl Default for Vignette {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PostEffect for Vignette {
+    fn name(&self) -> &'static str {
+        Self::NAME
+    }
+
+    fn apply(&mut self, frame: &mut [u32], width: usize, height: usize, format: PixelFormat) {
+        self.update_cache(width, height);
+        for (pixel, factor) in frame.iter_mut().zip(self.cache.iter()) {
+            if *factor < 1.0 {
+                *pixel = scale_color(*pixel, *factor, format);
+            }
+        }
+    }
+
+    impl_as_any!();
+}
+
+//*******************************************************************//
+//* Color grading through a 3D lookup table.
+//* The table has size^3 entries, indexed by [red][green][blue]
+//*******************************************************************//
+pub struct ColorGrading {
+    size: usize,
+    table: Vec<(u8, u8, u8)>,
+}
+
+impl ColorGrading {
+    pub const NAME: &'static str = "color_grading";
+
+    // a table that leaves the colors as they are
+    pub fn identity(size: usize) -> Self {
+        ColorGrading::from_fn(size, |r, g, b| (r, g, b))
+    }
+
+    /**
+     * Builds the table calling `grade` for every entry. It receives
+     * and returns (red, green, blue)
+     */
+    pub fn from_fn<F: Fn(u8, u8, u8) -> (u8, u8, u8)>(size: usize, grade: F) -> Self {
+        let size = size.clamp(2, 256);
+        let to_channel = |i: usize| (i * 255 / (size - 1)) as u8;
+        let mut table = Vec::with_capacity(size * size * size);
+        for r in 0..size {
+            for g in 0..size {
+                for b in 0..size {
+                    table.push(grade(to_channel(r), to_channel(g), to_channel(b)));
+                }
+            }
+        }
+        ColorGrading { size, table }
+    }
+}
+
+impl PostEffect for ColorGrading {
+    fn name(&self) -> &'static str {
+        Self::NAME
+    }
+
+    fn apply(&mut self, frame: &mut [u32], _width: usize, _height: usize, format: PixelFormat) {
+        let size = self.size;
+        // nearest entry of the table
+        let to_index = |value: u8| (value as usize * (size - 1) + 127) / 255;
+        for pixel in frame.iter_mut() {
+            let (a, r, g, b) = format.unpack(*pixel);
+            let index = (to_index(r) * size + to_index(g)) * size + to_index(b);
+            let (r, g, b) = self.table[index];
+            *pixel = format.pack(a, r, g, b);
+        }
+    }
+
+    impl_as_any!();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FORMAT: PixelFormat = PixelFormat::Bgra8;
+
+    fn frame(color: u32) -> Vec<u32> {
+        vec![color; 4 * 4]
+    }
+
+    #[test]
+    fn disabled_effects_are_skipped() {
+        let mut post_process = PostProcess::new();
+        post_process.push(Box::new(PaletteShift::new(100, 0, 0)), true);
+        post_process.push(Box::new(Scanlines::new()), false);
+        let mut pixels = frame(FORMAT.pack(255, 0, 0, 0));
+        post_process.apply(&mut pixels, 4, 4, FORMAT);
+        assert!(pixels.iter().all(|p| FORMAT.unpack(*p) == (255, 100, 0, 0)));
+
+        post_process.set_enabled(PaletteShift::NAME, false);
+        let mut pixels = frame(FORMAT.pack(255, 0, 0, 0));
+        post_process.apply(&mut pixels, 4, 4, FORMAT);
+        assert!(pixels.iter().all(|p| FORMAT.unpack(*p) == (255, 0, 0, 0)));
+    }
+
+    #[test]
+    fn damage_flash_fades_out() {
+        let mut post_process = PostProcess::new();
+        post_process.push(Box::new(DamageFlash::new()), true);
+        post_process.get_mut::<DamageFlash>().unwrap().trigger(1.0);
+        let mut pixels = frame(FORMAT.pack(255, 0, 0, 0));
+        post_process.apply(&mut pixels, 4, 4, FORMAT);
+        assert_eq!(FORMAT.unpack(pixels[0]), (255, 255, 0, 0));
+        // drawing doesn't fade it, only the ticks do
+        post_process.apply(&mut pixels, 4, 4, FORMAT);
+        assert_eq!(
+            post_process.get_mut::<DamageFlash>().unwrap().intensity,
+            1.0
+        );
+        for _ in 0..100 {
+            post_process.tick();
+        }
+        assert_eq!(
+            post_process.get_mut::<DamageFlash>().unwrap().intensity,
+            0.0
+        );
+    }
+
+    #[test]
+    fn identity_color_grading_keeps_colors() {
+        let mut grading = ColorGrading::identity(256);
+        let mut pixels = vec![FORMAT.pack(255, 10, 128, 250); 4];
+        grading.apply(&mut pixels, 2, 2, FORMAT);
+        assert_eq!(FORMAT.unpack(pixels[0]), (255, 10, 128, 250));
+    }
+}
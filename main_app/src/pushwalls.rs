@@ -0,0 +1,303 @@
+// Pushwalls, secret walls that slide away when the player pushes them.
+// A pushwall cell of the map (type 3) looks like any other wall and has in
+// the bits 8 to 15 how many cells it moves, 0 moves it until it runs into
+// something. When it's pushed its cell is emptied and the wall becomes a
+// block that slides a cell at a time, somewhere between two cells while it
+// moves. When it stops it goes back into the map as a plain wall, in the
+// cell where it stopped.
+
+use crate::collision::{Body, BodyShape};
+use crate::decals::Side;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct PushWall {
+    // the cell the block is leaving
+    pub cell_x: i32,
+    pub cell_y: i32,
+    // one of the four directions, (1, 0) goes east
+    pub direction: (i32, i32),
+    // how far it went into the next cell, from 0 to 1
+    pub offset: f32,
+    // cells it can still move. None moves until something stops it
+    pub cells_left: Option<u32>,
+    pub texture_id: u32,
+}
+
+impl PushWall {
+    // the top left corner of the block, in world units
+    pub fn corner(&self, tile_size: f32) -> (f32, f32) {
+        (
+            (self.cell_x as f32 + self.direction.0 as f32 * self.offset) * tile_size,
+            (self.cell_y as f32 + self.direction.1 as f32 * self.offset) * tile_size,
+        )
+    }
+
+    // the cell it's leaving, and the one it's going into once it started
+    pub fn cells(&self) -> Vec<(i32, i32)> {
+        let mut cells = vec![(self.cell_x, self.cell_y)];
+        if self.offset > 0.0 {
+            cells.push((
+                self.cell_x + self.direction.0,
+                self.cell_y + self.direction.1,
+            ));
+        }
+        cells
+    }
+
+    // the block as something that collides
+    pub fn body(&self, tile_size: f32) -> Body {
+        let (left, top) = self.corner(tile_size);
+        let half = tile_size / 2.0;
+        Body::new(left + half, top + half, BodyShape::Square(half))
+    }
+
+    /**
+     * Where a ray from x, y going in the direction of (cos, sin) hits the
+     * block, as (distance, side of the block, texture u). The texture moves
+     * with the block.
+     */
+    pub fn intersect(
+        &self,
+        tile_size: f32,
+        x: f32,
+        y: f32,
+        sin: f32,
+        cos: f32,
+    ) -> Option<(f32, Side, f32)> {
+        let (left, top) = self.corner(tile_size);
+        // the distances along the ray where it's between the two sides of
+        // the block on one axis
+        let span = |origin: f32, direction: f32, from: f32| {
+            if direction.abs() <= f32::EPSILON {
+                (from..from + tile_size)
+                    .contains(&origin)
+                    .then_some((f32::NEG_INFINITY, f32::INFINITY))
+            } else {
+                let near = (from - origin) / direction;
+                let far = (from + tile_size - origin) / direction;
+                Some((near.min(far), near.max(far)))
+            }
+        };
+        let (x_in, x_out) = span(x, cos, left)?;
+        let (y_in, y_out) = span(y, sin, top)?;
+        let distance = x_in.max(y_in);
+        // it starts inside the block, or it misses it
+        if distance <= 0.0 || distance > x_out.min(y_out) {
+            return None;
+        }
+        let (hit_x, hit_y) = (x + cos * distance, y + sin * distance);
+        Some(if x_in > y_in {
+            let side = if cos > 0.0 { Side::West } else { Side::East };
+            (
+                distance,
+                side,
+                (hit_y - top).rem_euclid(tile_size) / tile_size,
+            )
+        } else {
+            let side = if sin > 0.0 { Side::North } else { Side::South };
+            (
+                distance,
+                side,
+                (hit_x - left).rem_euclid(tile_size) / tile_size,
+            )
+        })
+    }
+}
+
+// how many cells the pushwall of a map cell moves, None until it's stopped
+pub fn push_distance(cell: u32) -> Option<u32> {
+    match (cell >> 8) & 0xff {
+        0 => None,
+        cells => Some(cells),
+    }
+}
+
+pub struct PushWalls {
+    moving: Vec<PushWall>,
+    // how much the offset of a block changes per tick
+    pub speed: f32,
+}
+
+impl Default for PushWalls {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PushWalls {
+    // nothing moving, a cell takes half a second
+    pub fn new() -> Self {
+        PushWalls {
+            moving: Vec::new(),
+            speed: 1.0 / 32.0,
+        }
+    }
+
+    /**
+     * Starts moving the wall of a cell in `direction`. The caller takes
+     * the wall out of the map.
+     */
+    pub fn push(
+        &mut self,
+        cell_x: i32,
+        cell_y: i32,
+        direction: (i32, i32),
+        cells: Option<u32>,
+        texture_id: u32,
+    ) {
+        self.moving.push(PushWall {
+            cell_x,
+            cell_y,
+            direction,
+            offset: 0.0,
+            cells_left: cells,
+            texture_id,
+        });
+    }
+
+    // the blocks that are moving
+    pub fn moving(&self) -> &[PushWall] {
+        &self.moving
+    }
+
+    // the block leaving a cell, if any
+    pub fn at(&self, cell_x: i32, cell_y: i32) -> Option<&PushWall> {
+        self.moving
+            .iter()
+            .find(|wall| wall.cell_x == cell_x && wall.cell_y == cell_y)
+    }
+
+    // a moving block is in the cell, or going into it
+    pub fn is_taken(&self, cell_x: i32, cell_y: i32) -> bool {
+        self.moving
+            .iter()
+            .any(|wall| wall.cells().contains(&(cell_x, cell_y)))
+    }
+
+    pub fn clear(&mut self) {
+        self.moving.clear();
+    }
+
+    /**
+     * Moves the blocks one tick. `is_free(cell_x, cell_y)` tells if a block
+     * can go into a cell, and `blocked(body)` if something is in the way of
+     * a block there, then it waits for it to move. The moving blocks
+     * aren't in the map, so a block also stops before a cell another one
+     * is in or going into. Returns the blocks that stopped, the caller
+     * puts them back in the map.
+     */
+    pub fn tick(
+        &mut self,
+        tile_size: f32,
+        is_free: impl Fn(i32, i32) -> bool,
+        blocked: impl Fn(&Body) -> bool,
+    ) -> Vec<PushWall> {
+        let speed = self.speed;
+        let mut stopped = Vec::new();
+        let mut taken: Vec<(i32, i32)> = self.moving.iter().flat_map(PushWall::cells).collect();
+        self.moving.retain_mut(|wall| {
+            let (dx, dy) = wall.direction;
+            let next_cell = (wall.cell_x + dx, wall.cell_y + dy);
+            if wall.offset <= 0.0 {
+                if wall.cells_left == Some(0)
+                    || !is_free(next_cell.0, next_cell.1)
+                    || taken.contains(&next_cell)
+                {
+                    stopped.push(wall.clone());
+                    return false;
+                }
+                taken.push(next_cell);
+            }
+            let mut next = wall.clone();
+            next.offset = (wall.offset + speed).min(1.0);
+            if blocked(&next.body(tile_size)) {
+                return true;
+            }
+            *wall = next;
+            if wall.offset >= 1.0 {
+                wall.cell_x += dx;
+                wall.cell_y += dy;
+                wall.offset = 0.0;
+                wall.cells_left = wall.cells_left.map(|cells| cells - 1);
+                if wall.cells_left == Some(0) {
+                    stopped.push(wall.clone());
+                    return false;
+                }
+            }
+            true
+        });
+        stopped
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pushwalls_move_and_stop() {
+        let mut walls = PushWalls::new();
+        walls.speed = 0.5;
+        walls.push(2, 2, (1, 0), Some(2), 7);
+        let free = |x: i32, _: i32| x < 10;
+        assert!(walls.tick(64.0, free, |_| false).is_empty());
+        assert_eq!(walls.moving()[0].corner(64.0), (160.0, 128.0));
+        assert!(walls.tick(64.0, free, |_| false).is_empty());
+        assert!(walls.at(3, 2).is_some());
+
+        // something in the way stops it for a while
+        assert!(walls.tick(64.0, free, |_| true).is_empty());
+        assert_eq!(walls.moving()[0].offset, 0.0);
+
+        walls.tick(64.0, free, |_| false);
+        let stopped = walls.tick(64.0, free, |_| false);
+        assert_eq!((stopped[0].cell_x, stopped[0].texture_id), (4, 7));
+        assert!(walls.moving().is_empty());
+
+        // one without a distance goes until the next cell isn't free
+        walls.push(8, 2, (1, 0), None, 7);
+        let mut stopped = Vec::new();
+        for _ in 0..10 {
+            stopped.extend(walls.tick(64.0, free, |_| false));
+        }
+        assert_eq!((stopped[0].cell_x, stopped[0].cell_y), (9, 2));
+    }
+
+    #[test]
+    fn blocks_do_not_move_into_the_same_cell() {
+        let mut walls = PushWalls::new();
+        walls.speed = 0.5;
+        // both are pushed towards 3, 2
+        walls.push(2, 2, (1, 0), Some(1), 7);
+        walls.push(4, 2, (-1, 0), Some(1), 8);
+        let mut stopped = Vec::new();
+        for _ in 0..4 {
+            stopped.extend(walls.tick(64.0, |_, _| true, |_| false));
+        }
+        let cells: Vec<_> = stopped
+            .iter()
+            .map(|wall| (wall.cell_x, wall.cell_y))
+            .collect();
+        assert_eq!(cells, vec![(4, 2), (3, 2)]);
+    }
+
+    #[test]
+    fn rays_hit_the_block_between_cells() {
+        let wall = PushWall {
+            cell_x: 1,
+            cell_y: 0,
+            direction: (0, 1),
+            offset: 0.25,
+            cells_left: None,
+            texture_id: 0,
+        };
+        // going down, it hits the top of the block a quarter into the cell
+        let hit = wall.intersect(64.0, 80.0, 0.0, 1.0, 0.0);
+        assert_eq!(hit, Some((16.0, Side::North, 0.25)));
+        // going right below it, it hits the west side
+        let hit = wall.intersect(64.0, 0.0, 40.0, 0.0, 1.0);
+        assert_eq!(hit, Some((64.0, Side::West, 0.375)));
+        // and above it, it misses
+        assert_eq!(wall.intersect(64.0, 0.0, 8.0, 0.0, 1.0), None);
+    }
+}
@@ -0,0 +1,43 @@
+// Saves frames as farbfeld images, the same format the textures use.
+// Farbfeld stores 16 bits per channel, big endian, in RGBA order, so each
+// 8 bit channel is repeated in both bytes (0xAB becomes 0xABAB).
+use crate::pixel_format::PixelFormat;
+use farfarbfeld::Encoder;
+use std::io::Write;
+
+pub fn encode_farbfeld<W: Write>(
+    writer: W,
+    pixels: &[u32],
+    width: u32,
+    height: u32,
+    format: PixelFormat,
+) -> farfarbfeld::Result<()> {
+    let mut data = Vec::with_capacity(pixels.len() * 8);
+    for pixel in pixels.iter() {
+        let (alpha, red, green, blue) = format.unpack(*pixel);
+        for channel in [red, green, blue, alpha] {
+            data.extend_from_slice(&[channel, channel]);
+        }
+    }
+    Encoder(writer).encode(width, height, &data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use farfarbfeld::Decoder;
+    use std::io::Cursor;
+
+    #[test]
+    fn frames_roundtrip_through_farbfeld() {
+        let format = PixelFormat::Rgba8;
+        let pixels = vec![format.pack(255, 1, 2, 3), format.pack(255, 200, 100, 50)];
+        let mut file = Vec::new();
+        encode_farbfeld(&mut file, &pixels, 2, 1, format).unwrap();
+        let mut decoder = Decoder::new(Cursor::new(file)).unwrap();
+        assert_eq!(decoder.dimensions(), (2, 1));
+        let data = decoder.read_image().unwrap();
+        assert_eq!(&data[0..8], &[1, 1, 2, 2, 3, 3, 255, 255]);
+        assert_eq!(&data[8..16], &[200, 200, 100, 100, 50, 50, 255, 255]);
+    }
+}
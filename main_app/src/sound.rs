@@ -0,0 +1,20 @@
+// Sounds the game wants played. The game doesn't play anything itself: it
+// queues events, and the frontends take them after each frame and play
+// them with whatever audio they have.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SoundEvent {
+    // the player tried a door without its key
+    DoorLocked,
+    KeyPickup,
+    Teleport,
+    // an enemy saw the player, attacks it, or died
+    EnemyAlert,
+    EnemyAttack,
+    EnemyDeath,
+    // the player's weapon
+    WeaponFire,
+    WeaponSwing,
+    WeaponEmpty,
+    WeaponReload,
+}
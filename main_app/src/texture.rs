@@ -0,0 +1,301 @@
+// Textures are stored as RGBA bytes (the farbfeld order, 8 bits per channel).
+// Each texture keeps a chain of mip levels, generated once at load time, so
+// the renderers can pick a smaller version of the image for distant surfaces.
+// Sampling from the full size image when a texel is smaller than a screen
+// pixel is what makes distant floors shimmer while moving.
+const BYTES_PER_PIXEL: usize = 4;
+
+// Used to decide if a filtered pixel is drawn or not. The renderer only
+// knows about fully transparent pixels, so we keep alpha binary.
+const ALPHA_THRESHOLD: u32 = 128;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TextureFilter {
+    // Nearest neighbour. The cheapest option, and the only one on the web.
+    Nearest,
+    // Blend the four closest texels
+    #[cfg(not(feature = "web"))]
+    Bilinear,
+}
+
+#[derive(Clone)]
+pub struct MipLevel {
+    pub width: u32,
+    pub height: u32,
+    pub data: Vec<u8>,
+}
+
+#[derive(Clone)]
+pub struct Texture {
+    pub width: u32,
+    pub height: u32,
+    pub data: Vec<u8>,
+    // level 1 and smaller. Level 0 is the texture itself.
+    pub mips: Vec<MipLevel>,
+}
+
+impl Texture {
+    pub fn new(width: u32, height: u32, data: Vec<u8>) -> Self {
+        let mut texture = Texture {
+            width,
+            height,
+            data,
+            mips: Vec::new(),
+        };
+        texture.generate_mipmaps();
+        texture
+    }
+
+    /**
+     * Builds the mip chain by halving the previous level until it
+     * reaches 1x1. Each texel is the alpha weighted average of a 2x2 block,
+     * so transparent texels don't bleed dark borders into sprites.
+     */
+    pub fn generate_mipmaps(&mut self) {
+        self.mips.clear();
+        let (mut width, mut height) = (self.width, self.height);
+        while width > 1 || height > 1 {
+            let (src_width, src_height, src) = self.level(self.mips.len());
+            let next = downsample(src_width, src_height, src);
+            width = next.width;
+            height = next.height;
+            self.mips.push(next);
+        }
+    }
+
+    // total amount of levels, including the full size one
+    #[inline]
+    pub fn mip_count(&self) -> usize {
+        self.mips.len() + 1
+    }
+
+    #[inline]
+    pub fn level(&self, level: usize) -> (u32, u32, &[u8]) {
+        if level == 0 || self.mips.is_empty() {
+            (self.width, self.height, &self.data)
+        } else {
+            let mip = &self.mips[(level - 1).min(self.mips.len() - 1)];
+            (mip.width, mip.height, &mip.data)
+        }
+    }
+
+    /**
+     * Returns the mip level to use when one screen pixel covers
+     * `texels_per_pixel` texels of the full size image.
+     */
+    #[inline]
+    pub fn mip_level_for(&self, texels_per_pixel: f32) -> usize {
+        if texels_per_pixel <= 1.0 || texels_per_pixel.is_nan() {
+            return 0;
+        }
+        (texels_per_pixel.log2().floor() as usize).min(self.mip_count() - 1)
+    }
+
+    /**
+     * Samples the texture at (u, v). Coordinates are normalized, 0 to 1
+     * covers the whole image whatever its size is, and they wrap around.
+     * Returns the pixel as [red, green, blue, alpha].
+     */
+    #[inline]
+    pub fn sample(&self, level: usize, u: f32, v: f32, filter: TextureFilter) -> [u8; 4] {
+        let (width, height, data) = self.level(level);
+        // scale to the texels of this level
+        let u = u * width as f32;
+        let v = v * height as f32;
+        match filter {
+            TextureFilter::Nearest => {
+                texel(width, height, data, u.floor() as i32, v.floor() as i32)
+            }
+            #[cfg(not(feature = "web"))]
+            TextureFilter::Bilinear => sample_bilinear(width, height, data, u, v),
+        }
+    }
+}
+
+// an image that failed to load can be empty, there's nothing to wrap around
+const EMPTY_TEXEL: [u8; 4] = [0, 0, 0, 0];
+
+#[inline]
+fn texel(width: u32, height: u32, data: &[u8], x: i32, y: i32) -> [u8; 4] {
+    if width == 0 || height == 0 {
+        return EMPTY_TEXEL;
+    }
+    let x = x.rem_euclid(width as i32) as usize;
+    let y = y.rem_euclid(height as i32) as usize;
+    let index = (y * width as usize + x) * BYTES_PER_PIXEL;
+    [
+        data[index],
+        data[index + 1],
+        data[index + 2],
+        data[index + 3],
+    ]
+}
+
+#[cfg(not(feature = "web"))]
+fn sample_bilinear(width: u32, height: u32, data: &[u8], u: f32, v: f32) -> [u8; 4] {
+    // texel centers are at .5, so shift before splitting integer and fraction
+    let u = u - 0.5;
+    let v = v - 0.5;
+    let x = u.floor();
+    let y = v.floor();
+    let fx = u - x;
+    let fy = v - y;
+    let (x, y) = (x as i32, y as i32);
+    let samples = [
+        (texel(width, height, data, x, y), (1.0 - fx) * (1.0 - fy)),
+        (texel(width, height, data, x + 1, y), fx * (1.0 - fy)),
+        (texel(width, height, data, x, y + 1), (1.0 - fx) * fy),
+        (texel(width, height, data, x + 1, y + 1), fx * fy),
+    ];
+    blend(&samples)
+}
+
+// alpha weighted average of a few texels
+fn blend(samples: &[([u8; 4], f32)]) -> [u8; 4] {
+    let mut color = [0.0f32; 3];
+    let mut alpha = 0.0f32;
+    let mut total_weight = 0.0f32;
+    for (pixel, weight) in samples {
+        let a = pixel[3] as f32 * weight;
+        color[0] += pixel[0] as f32 * a;
+        color[1] += pixel[1] as f32 * a;
+        color[2] += pixel[2] as f32 * a;
+        alpha += a;
+        total_weight += weight;
+    }
+    if alpha <= 0.0 {
+        return [0, 0, 0, 0];
+    }
+    let alpha_out = (alpha / total_weight).round() as u32;
+    [
+        (color[0] / alpha).round() as u8,
+        (color[1] / alpha).round() as u8,
+        (color[2] / alpha).round() as u8,
+        if alpha_out >= ALPHA_THRESHOLD { 255 } else { 0 },
+    ]
+}
+
+fn downsample(width: u32, height: u32, data: &[u8]) -> MipLevel {
+    let next_width = (width / 2).max(1);
+    let next_height = (height / 2).max(1);
+    let mut next_data = Vec::with_capacity((next_width * next_height) as usize * BYTES_PER_PIXEL);
+    for y in 0..next_height as i32 {
+        for x in 0..next_width as i32 {
+            // odd sizes: the last texel is reused instead of reading outside the image
+            let x0 = (x * 2).min(width as i32 - 1);
+            let y0 = (y * 2).min(height as i32 - 1);
+            let x1 = (x0 + 1).min(width as i32 - 1);
+            let y1 = (y0 + 1).min(height as i32 - 1);
+            let pixel = blend(&[
+                (texel(width, height, data, x0, y0), 1.0),
+                (texel(width, height, data, x1, y0), 1.0),
+                (texel(width, height, data, x0, y1), 1.0),
+                (texel(width, height, data, x1, y1), 1.0),
+            ]);
+            next_data.extend_from_slice(&pixel);
+        }
+    }
+    MipLevel {
+        width: next_width,
+        height: next_height,
+        data: next_data,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn checkerboard(width: u32, height: u32) -> Texture {
+        let mut data = Vec::new();
+        for y in 0..height {
+            for x in 0..width {
+                let c = if (x + y) % 2 == 0 { 255 } else { 0 };
+                data.extend_from_slice(&[c, c, c, 255]);
+            }
+        }
+        Texture::new(width, height, data)
+    }
+
+    #[test]
+    fn mip_chain_goes_down_to_one_texel() {
+        let texture = checkerboard(64, 64);
+        assert_eq!(texture.mip_count(), 7);
+        assert_eq!(texture.level(6).0, 1);
+        assert_eq!(texture.level(6).1, 1);
+        // a checkerboard averages to gray
+        let (_, _, data) = texture.level(1);
+        assert!(data.chunks_exact(4).all(|p| p[0] == 128 && p[3] == 255));
+    }
+
+    #[test]
+    fn mip_level_selection() {
+        let texture = checkerboard(64, 64);
+        assert_eq!(texture.mip_level_for(0.5), 0);
+        assert_eq!(texture.mip_level_for(1.0), 0);
+        assert_eq!(texture.mip_level_for(2.0), 1);
+        assert_eq!(texture.mip_level_for(5.0), 2);
+        assert_eq!(texture.mip_level_for(1000.0), 6);
+    }
+
+    #[test]
+    fn transparent_texels_dont_darken_mips() {
+        let data = vec![200, 100, 50, 255, 0, 0, 0, 0, 0, 0, 0, 0, 200, 100, 50, 255];
+        let texture = Texture::new(2, 2, data);
+        assert_eq!(texture.level(1).2, &[200, 100, 50, 255]);
+    }
+
+    #[test]
+    fn nearest_sampling_wraps() {
+        let texture = checkerboard(4, 4);
+        let filter = TextureFilter::Nearest;
+        assert_eq!(texture.sample(0, 0.1, 0.1, filter)[0], 255);
+        assert_eq!(texture.sample(0, 0.3, 0.1, filter)[0], 0);
+        assert_eq!(texture.sample(0, 1.1, 0.1, filter)[0], 255);
+        assert_eq!(texture.sample(0, -0.1, 0.1, filter)[0], 0);
+    }
+
+    #[test]
+    fn empty_textures_are_transparent() {
+        let texture = Texture::new(0, 0, Vec::new());
+        let filter = TextureFilter::Nearest;
+        assert_eq!(texture.sample(0, 0.5, 0.5, filter), [0, 0, 0, 0]);
+        #[cfg(not(feature = "web"))]
+        assert_eq!(
+            texture.sample(0, 0.5, 0.5, TextureFilter::Bilinear),
+            [0, 0, 0, 0]
+        );
+    }
+
+    // red on the left half, blue on the right half, green on the bottom row
+    fn quadrants(width: u32, height: u32) -> Texture {
+        let mut data = Vec::new();
+        for y in 0..height {
+            for x in 0..width {
+                if y == height - 1 {
+                    data.extend_from_slice(&[0, 255, 0, 255]);
+                } else if x < width / 2 {
+                    data.extend_from_slice(&[255, 0, 0, 255]);
+                } else {
+                    data.extend_from_slice(&[0, 0, 255, 255]);
+                }
+            }
+        }
+        Texture::new(width, height, data)
+    }
+
+    #[test]
+    fn mixed_sizes_map_the_same_uvs() {
+        let filter = TextureFilter::Nearest;
+        for (width, height) in [(32, 32), (64, 64), (128, 128), (64, 16), (16, 128), (3, 5)] {
+            let texture = quadrants(width, height);
+            let last_row = 1.0 - 0.5 / height as f32;
+            assert_eq!(texture.sample(0, 0.1, 0.0, filter), [255, 0, 0, 255]);
+            assert_eq!(texture.sample(0, 0.9, 0.0, filter), [0, 0, 255, 255]);
+            assert_eq!(texture.sample(0, 0.5, last_row, filter), [0, 255, 0, 255]);
+            // the smallest level is always 1x1
+            let (w, h, _) = texture.level(texture.mip_count() - 1);
+            assert_eq!((w, h), (1, 1));
+        }
+    }
+}
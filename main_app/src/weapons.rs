@@ -0,0 +1,408 @@
+// The weapons the player carries. Weapons only keeps track of the one in
+// the hands: what it is doing (firing, reloading, being switched), the
+// rounds in the magazines and how far it sways while walking. The game
+// resolves the attacks it asks for against the walls and the entities,
+// and draws it over the 3D view, see GameWindow::use_weapon.
+// The weapons in the hands are sheets of frames made from the weapon art
+// by tools/gen_weapon_frames.py.
+
+use crate::entities::Sprite;
+use std::f32::consts::PI;
+
+// ticks to lower a weapon, and the same to raise the next one
+const SWITCH_TICKS: u32 = 12;
+// how much the bob phase moves every tick walking
+const BOB_SPEED: f32 = 0.2;
+// how fast the bob grows when walking and fades when stopping
+const BOB_EASE: f32 = 0.1;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AttackKind {
+    // hits the first thing straight ahead, as far as it is
+    Hitscan,
+    // hits in front of the player, if it's closer than range (world units)
+    Melee { range: f32 },
+}
+
+// the frames of the sheet of a weapon for every state, by index in the sheet
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct WeaponFrames {
+    pub ready: u32,
+    // played once over the cooldown
+    pub attack: &'static [u32],
+    // played once while reloading
+    pub reload: &'static [u32],
+    // while it's being lowered or raised
+    pub lowered: u32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Weapon {
+    pub name: &'static str,
+    // the sheet in the player's hands. Its width and height are only
+    // the proportions of a frame
+    pub sprite: Sprite,
+    pub frames: WeaponFrames,
+    pub crosshair_id: u32,
+    pub kind: AttackKind,
+    pub damage: i32,
+    // ticks from an attack to the next one
+    pub cooldown: u32,
+    // rounds in a full magazine, None doesn't use ammo
+    pub magazine: Option<u32>,
+    pub reload_ticks: u32,
+}
+
+impl Weapon {
+    // crossbow_frames (from arma_9) and crosshair_1
+    pub fn crossbow() -> Self {
+        Weapon {
+            name: "crossbow",
+            sprite: Sprite {
+                sheet: (4, 1),
+                ..Sprite::new(192, 32, 32)
+            },
+            // ready, fired, tilted down and a new bolt going in
+            frames: WeaponFrames {
+                ready: 0,
+                attack: &[1, 1, 3],
+                reload: &[2, 2, 3],
+                lowered: 2,
+            },
+            crosshair_id: 99,
+            kind: AttackKind::Hitscan,
+            damage: 25,
+            cooldown: 20,
+            magazine: Some(8),
+            reload_ticks: 60,
+        }
+    }
+
+    // sword_frames (from espada_1) and crosshair_2
+    pub fn sword() -> Self {
+        Weapon {
+            name: "sword",
+            sprite: Sprite {
+                sheet: (4, 1),
+                ..Sprite::new(191, 24, 24)
+            },
+            // held up, and a swing down to the left
+            frames: WeaponFrames {
+                ready: 0,
+                attack: &[1, 2, 3],
+                reload: &[],
+                lowered: 3,
+            },
+            crosshair_id: 130,
+            kind: AttackKind::Melee { range: 72.0 },
+            damage: 40,
+            cooldown: 30,
+            magazine: None,
+            reload_ticks: 0,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WeaponState {
+    Ready,
+    Attacking,
+    Reloading,
+    // going down, the weapon with this index comes up after it
+    Lowering(usize),
+    Raising,
+}
+
+// what the player asked for since the last tick
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct WeaponInput {
+    pub fire: bool,
+    pub reload: bool,
+    // the index of the weapon to take out
+    pub switch_to: Option<usize>,
+}
+
+// what happened in a tick, for the game to apply and play
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WeaponEvent {
+    Attack(Weapon),
+    // fired without rounds and nothing to reload
+    Empty,
+    Reload,
+    // the new weapon is coming up
+    Switch,
+}
+
+pub struct Weapons {
+    weapons: Vec<Weapon>,
+    current: usize,
+    state: WeaponState,
+    // ticks in the current state
+    ticks: u32,
+    // the rounds in the magazine of every weapon
+    loaded: Vec<u32>,
+    // the rounds carried besides the ones in the magazines
+    pub ammo: u32,
+    bob: f32,
+    // 0 standing, 1 walking
+    bob_amount: f32,
+}
+
+impl Default for Weapons {
+    fn default() -> Self {
+        Weapons::new(vec![Weapon::crossbow(), Weapon::sword()], 24)
+            .expect("there are default weapons")
+    }
+}
+
+impl Weapons {
+    /**
+     * The first weapon is in the hands, and every magazine is full.
+     * None without weapons, there is always one in the hands.
+     */
+    pub fn new(weapons: Vec<Weapon>, ammo: u32) -> Option<Self> {
+        if weapons.is_empty() {
+            return None;
+        }
+        let loaded = weapons
+            .iter()
+            .map(|weapon| weapon.magazine.unwrap_or(0))
+            .collect();
+        Some(Weapons {
+            weapons,
+            current: 0,
+            state: WeaponState::Ready,
+            ticks: 0,
+            loaded,
+            ammo,
+            bob: 0.0,
+            bob_amount: 0.0,
+        })
+    }
+
+    pub fn current(&self) -> Weapon {
+        self.weapons[self.current]
+    }
+
+    pub fn current_index(&self) -> usize {
+        self.current
+    }
+
+    pub fn state(&self) -> WeaponState {
+        self.state
+    }
+
+    // the rounds in the magazine of the weapon in the hands, None without one
+    pub fn loaded(&self) -> Option<u32> {
+        self.current().magazine.map(|_| self.loaded[self.current])
+    }
+
+    fn can_reload(&self) -> bool {
+        self.current()
+            .magazine
+            .is_some_and(|magazine| self.loaded[self.current] < magazine && self.ammo > 0)
+    }
+
+    fn start(&mut self, state: WeaponState) {
+        self.state = state;
+        self.ticks = 0;
+    }
+
+    /**
+     * Moves the weapon one tick. Orders only start from Ready, the rest
+     * of the time they are ignored. Firing an empty magazine reloads it
+     * when there are rounds left.
+     */
+    pub fn tick(&mut self, input: WeaponInput, moving: bool) -> Option<WeaponEvent> {
+        self.bob_amount = if moving {
+            (self.bob_amount + BOB_EASE).min(1.0)
+        } else {
+            (self.bob_amount - BOB_EASE).max(0.0)
+        };
+        if moving {
+            self.bob = (self.bob + BOB_SPEED) % (2.0 * PI);
+        }
+        self.ticks += 1;
+        let weapon = self.current();
+        match self.state {
+            WeaponState::Ready => {
+                let switch_to = input
+                    .switch_to
+                    .filter(|&next| next != self.current && next < self.weapons.len());
+                if let Some(next) = switch_to {
+                    self.start(WeaponState::Lowering(next));
+                } else if input.reload && self.can_reload() {
+                    self.start(WeaponState::Reloading);
+                    return Some(WeaponEvent::Reload);
+                } else if input.fire {
+                    if weapon.magazine.is_some() {
+                        if self.loaded[self.current] == 0 {
+                            if self.can_reload() {
+                                self.start(WeaponState::Reloading);
+                                return Some(WeaponEvent::Reload);
+                            }
+                            return Some(WeaponEvent::Empty);
+                        }
+                        self.loaded[self.current] -= 1;
+                    }
+                    self.start(WeaponState::Attacking);
+                    return Some(WeaponEvent::Attack(weapon));
+                }
+            }
+            WeaponState::Attacking if self.ticks >= weapon.cooldown => {
+                self.start(WeaponState::Ready);
+            }
+            WeaponState::Reloading if self.ticks >= weapon.reload_ticks => {
+                let missing = weapon.magazine.unwrap_or(0) - self.loaded[self.current];
+                let rounds = missing.min(self.ammo);
+                self.loaded[self.current] += rounds;
+                self.ammo -= rounds;
+                self.start(WeaponState::Ready);
+            }
+            WeaponState::Lowering(next) if self.ticks >= SWITCH_TICKS => {
+                self.current = next;
+                self.start(WeaponState::Raising);
+                return Some(WeaponEvent::Switch);
+            }
+            WeaponState::Raising if self.ticks >= SWITCH_TICKS => {
+                self.start(WeaponState::Ready);
+            }
+            _ => {}
+        }
+        None
+    }
+
+    // how far the current state went, from 0 to 1 after `ticks`
+    fn progress(&self, ticks: u32) -> f32 {
+        (self.ticks as f32 / ticks.max(1) as f32).min(1.0)
+    }
+
+    // the sheet of the weapon in the hands, at the frame of what it's doing
+    pub fn sprite(&self) -> Sprite {
+        let weapon = self.current();
+        let frames = weapon.frames;
+        // the frames are played once over the state
+        let play = |played: &[u32], ticks: u32| {
+            let frame = (self.progress(ticks) * played.len() as f32) as usize;
+            played
+                .get(frame.min(played.len().saturating_sub(1)))
+                .copied()
+                .unwrap_or(frames.ready)
+        };
+        let frame = match self.state {
+            WeaponState::Ready => frames.ready,
+            WeaponState::Attacking => play(frames.attack, weapon.cooldown),
+            WeaponState::Reloading => play(frames.reload, weapon.reload_ticks),
+            WeaponState::Lowering(_) | WeaponState::Raising => frames.lowered,
+        };
+        let columns = weapon.sprite.sheet.0.max(1);
+        Sprite {
+            frame: (frame % columns, frame / columns),
+            ..weapon.sprite
+        }
+    }
+
+    /**
+     * How far the weapon is moved from where it rests, in weapon sizes
+     * (1 is its whole width or height). y goes down, like the screen.
+     */
+    pub fn offset(&self) -> (f32, f32) {
+        // the sway of walking, side to side and a bit up and down
+        let x = self.bob.cos() * 0.08 * self.bob_amount;
+        let mut y = self.bob.sin().abs() * 0.06 * self.bob_amount;
+        match self.state {
+            // a shot kicks it down, and it comes back
+            WeaponState::Attacking if self.current().kind == AttackKind::Hitscan => {
+                y += 0.15 * (1.0 - self.progress(self.current().cooldown));
+            }
+            WeaponState::Lowering(_) => y += self.progress(SWITCH_TICKS),
+            WeaponState::Raising => y += 1.0 - self.progress(SWITCH_TICKS),
+            _ => {}
+        }
+        (x, y)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fire() -> WeaponInput {
+        WeaponInput {
+            fire: true,
+            ..WeaponInput::default()
+        }
+    }
+
+    // ticks until the weapon is ready again, returning what happened
+    fn settle(weapons: &mut Weapons) -> Vec<WeaponEvent> {
+        let mut events = Vec::new();
+        while weapons.state() != WeaponState::Ready {
+            events.extend(weapons.tick(WeaponInput::default(), false));
+        }
+        events
+    }
+
+    #[test]
+    fn firing_uses_rounds_and_empty_magazines_reload() {
+        assert!(Weapons::new(Vec::new(), 3).is_none());
+        let mut weapons = Weapons::new(vec![Weapon::crossbow()], 3).unwrap();
+        for round in (0..8).rev() {
+            assert_eq!(
+                weapons.tick(fire(), false),
+                Some(WeaponEvent::Attack(Weapon::crossbow()))
+            );
+            assert_eq!(weapons.loaded(), Some(round));
+            // it doesn't fire again before the cooldown
+            assert_eq!(weapons.tick(fire(), false), None);
+            settle(&mut weapons);
+        }
+        assert_eq!(weapons.tick(fire(), false), Some(WeaponEvent::Reload));
+        // tilted down, and the new bolt going in at the end
+        assert_eq!(weapons.sprite().frame, (2, 0));
+        for _ in 0..50 {
+            weapons.tick(WeaponInput::default(), false);
+        }
+        assert_eq!(weapons.sprite().frame, (3, 0));
+        settle(&mut weapons);
+        assert_eq!((weapons.loaded(), weapons.ammo), (Some(3), 0));
+
+        for _ in 0..3 {
+            weapons.tick(fire(), false);
+            settle(&mut weapons);
+        }
+        assert_eq!(weapons.tick(fire(), false), Some(WeaponEvent::Empty));
+        assert_eq!(weapons.state(), WeaponState::Ready);
+    }
+
+    #[test]
+    fn switching_lowers_one_weapon_and_raises_the_other() {
+        let mut weapons = Weapons::default();
+        let input = WeaponInput {
+            switch_to: Some(1),
+            ..WeaponInput::default()
+        };
+        assert_eq!(weapons.tick(input, false), None);
+        assert_eq!(weapons.state(), WeaponState::Lowering(1));
+        // it can't fire while switching
+        assert_eq!(weapons.tick(fire(), false), None);
+        assert_eq!(settle(&mut weapons), vec![WeaponEvent::Switch]);
+        assert_eq!(weapons.current(), Weapon::sword());
+        assert_eq!(weapons.loaded(), None);
+        assert_eq!(weapons.offset(), (0.0, 0.0));
+
+        // swords don't need ammo
+        assert_eq!(
+            weapons.tick(fire(), true),
+            Some(WeaponEvent::Attack(Weapon::sword()))
+        );
+        // the swing goes through the frames of the sheet
+        let mut frames = vec![weapons.sprite().frame];
+        for _ in 0..30 {
+            weapons.tick(WeaponInput::default(), false);
+            frames.push(weapons.sprite().frame);
+        }
+        frames.dedup();
+        assert_eq!(frames, vec![(1, 0), (2, 0), (3, 0), (0, 0)]);
+    }
+}
@@ -78,12 +78,14 @@ pub fn main() {
         if textures_loaded {
             // game step
             raycast.game_step(&window);
-
-            raycast.move_doors_demo();
-            // as the buffer is referenced from inside the ImageData, and
-            // we push that to the canvas, so we could call update() and
-            // avoid all this. I don't think it's possible to get artifacts
-            // on the web side, but I definitely see them on the desktop app
+            // there is no audio yet. The sounds are taken anyway, or they
+            // would pile up for the whole game
+            let _ = raycast.take_sound_events();
+            // the same with the pickups touched, nothing reacts to them here
+            let _ = raycast.take_touch_events();
+            // game_step presents the frame, so the buffer we hand over
+            // is always a complete one. The front and back buffers swap
+            // on every frame, that's why we can't just call update()
             let result = window.update_with_buffer(raycast.get_buffer_to_print(), WIDTH, HEIGHT);
             match result {
                 Ok(_) => {
@@ -149,6 +151,7 @@ pub fn main() {
                 if raycast.assets.textures.len() == resources.images.len() {
                     console::log_1(&"All initial textures have been loaded. Time to start the game.".into());
                     textures_loaded = true;
+                    raycast.convert_background();
                     worker_handle.as_ref().borrow_mut().terminate();
                 }
             }
@@ -0,0 +1,53 @@
+#!/usr/bin/env python3
+# Generates the decal images: images/bullet_hole.ff and images/blood.ff
+# (and png previews). Run it from the root of the project:
+# python3 tools/gen_decals.py
+import math
+import random
+
+from gen_font import write_farbfeld, write_png
+
+
+def bullet_hole(size=16):
+    center = (size - 1) / 2.0
+    pixels = []
+    for y in range(size):
+        row = []
+        for x in range(size):
+            d = math.hypot(x - center, y - center) / (size / 2.0)
+            if d < 0.35:
+                row.append((10, 10, 10, 255))
+            elif d < 0.6:
+                # burnt ring around the hole
+                row.append((60, 50, 40, 255))
+            else:
+                row.append((0, 0, 0, 0))
+        pixels.append(row)
+    return size, size, pixels
+
+
+def blood(size=32, seed=7):
+    rng = random.Random(seed)
+    # a big drop and a few small ones around it
+    drops = [(size / 2.0, size / 2.0, size / 4.0)]
+    for _ in range(10):
+        angle = rng.uniform(0, 2 * math.pi)
+        distance = rng.uniform(size / 5.0, size / 2.3)
+        drops.append((size / 2.0 + math.cos(angle) * distance,
+                      size / 2.0 + math.sin(angle) * distance,
+                      rng.uniform(1.0, size / 12.0)))
+    pixels = []
+    for y in range(size):
+        row = []
+        for x in range(size):
+            inside = any(math.hypot(x - dx, y - dy) < r for dx, dy, r in drops)
+            row.append((120 + (x * y) % 40, 0, 0, 255) if inside else (0, 0, 0, 0))
+        pixels.append(row)
+    return size, size, pixels
+
+
+if __name__ == '__main__':
+    for name, image in (('bullet_hole', bullet_hole()), ('blood', blood())):
+        width, height, pixels = image
+        write_farbfeld('images/%s.ff' % name, width, height, pixels)
+        write_png('images/%s.png' % name, width, height, pixels)
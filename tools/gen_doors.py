@@ -0,0 +1,75 @@
+#!/usr/bin/env python3
+# Generates the door textures: images/door_split.ff (a wooden double door,
+# it opens from the seam in the middle), images/door_lift.ff (a metal
+# shutter that goes up) and images/door_jamb.ff (the steel frame around
+# the doorways), with png previews. Run it from the root of the project:
+# python3 tools/gen_doors.py
+from gen_font import write_farbfeld, write_png
+
+SIZE = 64
+
+
+def blank(color):
+    return [[color] * SIZE for _ in range(SIZE)]
+
+
+def fill(pixels, x0, y0, x1, y1, color):
+    for y in range(y0, y1):
+        for x in range(x0, x1):
+            pixels[y][x] = color
+
+
+def split_door():
+    pixels = blank((120, 78, 40, 255))
+    # the planks, a darker line between them and a bit of grain
+    for x in range(SIZE):
+        for y in range(SIZE):
+            if x % 8 == 0:
+                pixels[y][x] = (70, 44, 20, 255)
+            elif (x * 7 + y // 5) % 11 == 0:
+                pixels[y][x] = (104, 66, 32, 255)
+    # the seam where the halves meet
+    fill(pixels, 31, 0, 33, SIZE, (30, 18, 8, 255))
+    # iron bands with rivets, and the handles next to the seam
+    for y0 in (10, 50):
+        fill(pixels, 0, y0, SIZE, y0 + 4, (60, 60, 66, 255))
+        for x in range(3, SIZE, 8):
+            pixels[y0 + 1][x] = (150, 150, 160, 255)
+    fill(pixels, 26, 28, 29, 36, (180, 160, 60, 255))
+    fill(pixels, 35, 28, 38, 36, (180, 160, 60, 255))
+    return pixels
+
+
+def lift_door():
+    pixels = blank((110, 115, 125, 255))
+    # the slats, lit from above
+    for y in range(SIZE):
+        shade = y % 8
+        if shade == 0:
+            fill(pixels, 0, y, SIZE, y + 1, (160, 165, 175, 255))
+        elif shade == 7:
+            fill(pixels, 0, y, SIZE, y + 1, (60, 62, 70, 255))
+    # the bottom bar with warning stripes
+    for y in range(56, SIZE):
+        for x in range(SIZE):
+            stripe = (x + y) // 4 % 2 == 0
+            pixels[y][x] = (220, 180, 20, 255) if stripe else (30, 30, 30, 255)
+    return pixels
+
+
+def door_jamb():
+    pixels = blank((70, 72, 80, 255))
+    # a frame with rivets down both sides
+    fill(pixels, 0, 0, 4, SIZE, (40, 40, 46, 255))
+    fill(pixels, SIZE - 4, 0, SIZE, SIZE, (40, 40, 46, 255))
+    for y in range(6, SIZE, 12):
+        for x in (10, SIZE - 11):
+            fill(pixels, x, y, x + 2, y + 2, (150, 152, 160, 255))
+    return pixels
+
+
+if __name__ == '__main__':
+    for name, pixels in (('door_split', split_door()), ('door_lift', lift_door()),
+                         ('door_jamb', door_jamb())):
+        write_farbfeld('images/%s.ff' % name, SIZE, SIZE, pixels)
+        write_png('images/%s.png' % name, SIZE, SIZE, pixels)
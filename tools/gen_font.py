@@ -0,0 +1,90 @@
+#!/usr/bin/env python3
+# Generates the bitmap font used by the HUD: images/font.ff (and a png preview)
+# Glyphs are the classic 5x7 font, stored as 5 columns per character where
+# the lowest bit is the top row. Each glyph cell is 6x8 pixels (one column of
+# spacing), 16 glyphs per row, from ' ' (32) to '~' (126).
+# Run it from the root of the project: python3 tools/gen_font.py
+import struct, zlib
+
+GLYPH_WIDTH = 6
+GLYPH_HEIGHT = 8
+GLYPHS_PER_ROW = 16
+FIRST_CHAR = 32
+
+FONT_5X7 = [
+    (0x00, 0x00, 0x00, 0x00, 0x00), (0x00, 0x00, 0x5F, 0x00, 0x00), (0x00, 0x07, 0x00, 0x07, 0x00),
+    (0x14, 0x7F, 0x14, 0x7F, 0x14), (0x24, 0x2A, 0x7F, 0x2A, 0x12), (0x23, 0x13, 0x08, 0x64, 0x62),
+    (0x36, 0x49, 0x56, 0x20, 0x50), (0x00, 0x08, 0x07, 0x03, 0x00), (0x00, 0x1C, 0x22, 0x41, 0x00),
+    (0x00, 0x41, 0x22, 0x1C, 0x00), (0x2A, 0x1C, 0x7F, 0x1C, 0x2A), (0x08, 0x08, 0x3E, 0x08, 0x08),
+    (0x00, 0x80, 0x70, 0x30, 0x00), (0x08, 0x08, 0x08, 0x08, 0x08), (0x00, 0x00, 0x60, 0x60, 0x00),
+    (0x20, 0x10, 0x08, 0x04, 0x02), (0x3E, 0x51, 0x49, 0x45, 0x3E), (0x00, 0x42, 0x7F, 0x40, 0x00),
+    (0x72, 0x49, 0x49, 0x49, 0x46), (0x21, 0x41, 0x49, 0x4D, 0x33), (0x18, 0x14, 0x12, 0x7F, 0x10),
+    (0x27, 0x45, 0x45, 0x45, 0x39), (0x3C, 0x4A, 0x49, 0x49, 0x31), (0x41, 0x21, 0x11, 0x09, 0x07),
+    (0x36, 0x49, 0x49, 0x49, 0x36), (0x46, 0x49, 0x49, 0x29, 0x1E), (0x00, 0x00, 0x14, 0x00, 0x00),
+    (0x00, 0x40, 0x34, 0x00, 0x00), (0x00, 0x08, 0x14, 0x22, 0x41), (0x14, 0x14, 0x14, 0x14, 0x14),
+    (0x00, 0x41, 0x22, 0x14, 0x08), (0x02, 0x01, 0x59, 0x09, 0x06), (0x3E, 0x41, 0x5D, 0x59, 0x4E),
+    (0x7C, 0x12, 0x11, 0x12, 0x7C), (0x7F, 0x49, 0x49, 0x49, 0x36), (0x3E, 0x41, 0x41, 0x41, 0x22),
+    (0x7F, 0x41, 0x41, 0x41, 0x3E), (0x7F, 0x49, 0x49, 0x49, 0x41), (0x7F, 0x09, 0x09, 0x09, 0x01),
+    (0x3E, 0x41, 0x41, 0x51, 0x73), (0x7F, 0x08, 0x08, 0x08, 0x7F), (0x00, 0x41, 0x7F, 0x41, 0x00),
+    (0x20, 0x40, 0x41, 0x3F, 0x01), (0x7F, 0x08, 0x14, 0x22, 0x41), (0x7F, 0x40, 0x40, 0x40, 0x40),
+    (0x7F, 0x02, 0x1C, 0x02, 0x7F), (0x7F, 0x04, 0x08, 0x10, 0x7F), (0x3E, 0x41, 0x41, 0x41, 0x3E),
+    (0x7F, 0x09, 0x09, 0x09, 0x06), (0x3E, 0x41, 0x51, 0x21, 0x5E), (0x7F, 0x09, 0x19, 0x29, 0x46),
+    (0x26, 0x49, 0x49, 0x49, 0x32), (0x03, 0x01, 0x7F, 0x01, 0x03), (0x3F, 0x40, 0x40, 0x40, 0x3F),
+    (0x1F, 0x20, 0x40, 0x20, 0x1F), (0x3F, 0x40, 0x38, 0x40, 0x3F), (0x63, 0x14, 0x08, 0x14, 0x63),
+    (0x03, 0x04, 0x78, 0x04, 0x03), (0x61, 0x59, 0x49, 0x4D, 0x43), (0x00, 0x7F, 0x41, 0x41, 0x41),
+    (0x02, 0x04, 0x08, 0x10, 0x20), (0x00, 0x41, 0x41, 0x41, 0x7F), (0x04, 0x02, 0x01, 0x02, 0x04),
+    (0x40, 0x40, 0x40, 0x40, 0x40), (0x00, 0x03, 0x07, 0x08, 0x00), (0x20, 0x54, 0x54, 0x78, 0x40),
+    (0x7F, 0x28, 0x44, 0x44, 0x38), (0x38, 0x44, 0x44, 0x44, 0x28), (0x38, 0x44, 0x44, 0x28, 0x7F),
+    (0x38, 0x54, 0x54, 0x54, 0x18), (0x00, 0x08, 0x7E, 0x09, 0x02), (0x18, 0xA4, 0xA4, 0x9C, 0x78),
+    (0x7F, 0x08, 0x04, 0x04, 0x78), (0x00, 0x44, 0x7D, 0x40, 0x00), (0x20, 0x40, 0x40, 0x3D, 0x00),
+    (0x7F, 0x10, 0x28, 0x44, 0x00), (0x00, 0x41, 0x7F, 0x40, 0x00), (0x7C, 0x04, 0x78, 0x04, 0x78),
+    (0x7C, 0x08, 0x04, 0x04, 0x78), (0x38, 0x44, 0x44, 0x44, 0x38), (0xFC, 0x18, 0x24, 0x24, 0x18),
+    (0x18, 0x24, 0x24, 0x18, 0xFC), (0x7C, 0x08, 0x04, 0x04, 0x08), (0x48, 0x54, 0x54, 0x54, 0x24),
+    (0x04, 0x04, 0x3F, 0x44, 0x24), (0x3C, 0x40, 0x40, 0x20, 0x7C), (0x1C, 0x20, 0x40, 0x20, 0x1C),
+    (0x3C, 0x40, 0x30, 0x40, 0x3C), (0x44, 0x28, 0x10, 0x28, 0x44), (0x4C, 0x90, 0x90, 0x90, 0x7C),
+    (0x44, 0x64, 0x54, 0x4C, 0x44), (0x00, 0x08, 0x36, 0x41, 0x00), (0x00, 0x00, 0x77, 0x00, 0x00),
+    (0x00, 0x41, 0x36, 0x08, 0x00), (0x02, 0x01, 0x02, 0x04, 0x02),
+]
+
+
+def build_sheet():
+    rows = (len(FONT_5X7) + GLYPHS_PER_ROW - 1) // GLYPHS_PER_ROW
+    width = GLYPHS_PER_ROW * GLYPH_WIDTH
+    height = rows * GLYPH_HEIGHT
+    # white glyphs on a transparent background, RGBA 8 bits
+    pixels = [[(0, 0, 0, 0)] * width for _ in range(height)]
+    for index, columns in enumerate(FONT_5X7):
+        cell_x = (index % GLYPHS_PER_ROW) * GLYPH_WIDTH
+        cell_y = (index // GLYPHS_PER_ROW) * GLYPH_HEIGHT
+        for column, bits in enumerate(columns):
+            for row in range(GLYPH_HEIGHT):
+                if bits & (1 << row):
+                    pixels[cell_y + row][cell_x + column] = (255, 255, 255, 255)
+    return width, height, pixels
+
+
+def write_farbfeld(path, width, height, pixels):
+    with open(path, 'wb') as f:
+        f.write(b'farbfeld')
+        f.write(struct.pack('>II', width, height))
+        for row in pixels:
+            for pixel in row:
+                # 16 bits per channel, big endian
+                f.write(struct.pack('>HHHH', *[c * 257 for c in pixel]))
+
+
+def write_png(path, width, height, pixels):
+    def chunk(kind, data):
+        return struct.pack('>I', len(data)) + kind + data + struct.pack('>I', zlib.crc32(kind + data))
+    raw = b''.join(b'\x00' + bytes(c for pixel in row for c in pixel) for row in pixels)
+    with open(path, 'wb') as f:
+        f.write(b'\x89PNG\r\n\x1a\n')
+        f.write(chunk(b'IHDR', struct.pack('>IIBBBBB', width, height, 8, 6, 0, 0, 0)))
+        f.write(chunk(b'IDAT', zlib.compress(raw, 9)))
+        f.write(chunk(b'IEND', b''))
+
+
+if __name__ == '__main__':
+    width, height, pixels = build_sheet()
+    write_farbfeld('images/font.ff', width, height, pixels)
+    write_png('images/font.png', width, height, pixels)
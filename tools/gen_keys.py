@@ -0,0 +1,44 @@
+#!/usr/bin/env python3
+# Generates the key sprites: images/key_red.ff, images/key_blue.ff and
+# images/key_yellow.ff (and png previews). Run it from the root of the project:
+# python3 tools/gen_keys.py
+from gen_font import write_farbfeld, write_png
+
+COLORS = {
+    'red': (220, 30, 30),
+    'blue': (40, 80, 230),
+    'yellow': (240, 210, 30),
+}
+
+
+def key(rgb, size=32):
+    red, green, blue = rgb
+    dark = (red // 2, green // 2, blue // 2, 255)
+    light = (red, green, blue, 255)
+    pixels = [[(0, 0, 0, 0)] * size for _ in range(size)]
+
+    def fill(x0, y0, x1, y1, color):
+        for y in range(y0, y1):
+            for x in range(x0, x1):
+                pixels[y][x] = color
+
+    # the ring, with a hole in the middle
+    for y in range(4, 16):
+        for x in range(2, 14):
+            d = (x - 7.5) ** 2 + (y - 9.5) ** 2
+            if d < 36:
+                pixels[y][x] = light if d >= 9 else (0, 0, 0, 0)
+    # the shaft and the teeth, lying on the floor at the bottom of the image
+    fill(13, 8, 30, 11, light)
+    fill(13, 11, 30, 12, dark)
+    fill(22, 12, 25, 16, light)
+    fill(27, 12, 30, 18, light)
+    # the sprite stands on the floor, so the key is moved to the bottom
+    return size, size, pixels[-13:] + pixels[:-13]
+
+
+if __name__ == '__main__':
+    for name, rgb in COLORS.items():
+        width, height, pixels = key(rgb)
+        write_farbfeld('images/key_%s.ff' % name, width, height, pixels)
+        write_png('images/key_%s.png' % name, width, height, pixels)
@@ -0,0 +1,52 @@
+#!/usr/bin/env python3
+# Generates the wall switch textures: images/switch_off.ff and
+# images/switch_on.ff (and png previews), a lever on a metal plate over the
+# brick wall (images/brick2.ff). Run it from the root of the project:
+# python3 tools/gen_switches.py
+import struct
+
+from gen_font import write_farbfeld, write_png
+
+
+def read_farbfeld(path):
+    with open(path, 'rb') as f:
+        data = f.read()
+    width, height = struct.unpack('>II', data[8:16])
+    values = struct.unpack('>%dH' % (width * height * 4), data[16:])
+    pixels = []
+    for y in range(height):
+        row = []
+        for x in range(width):
+            i = (y * width + x) * 4
+            row.append(tuple(v // 257 for v in values[i:i + 4]))
+        pixels.append(row)
+    return width, height, pixels
+
+
+def switch(on):
+    width, height, pixels = read_farbfeld('images/brick2.ff')
+
+    def fill(x0, y0, x1, y1, color):
+        for y in range(y0, y1):
+            for x in range(x0, x1):
+                pixels[y][x] = color
+
+    # the plate, with a dark border
+    fill(20, 14, 44, 50, (40, 40, 45, 255))
+    fill(21, 15, 43, 49, (130, 130, 140, 255))
+    # the slot and the lever, up when it's on
+    fill(30, 20, 34, 44, (30, 30, 30, 255))
+    if on:
+        fill(29, 18, 35, 30, (200, 200, 210, 255))
+    else:
+        fill(29, 34, 35, 46, (200, 200, 210, 255))
+    # the light, green when it's on
+    fill(38, 18, 42, 22, (40, 220, 40, 255) if on else (220, 40, 40, 255))
+    return width, height, pixels
+
+
+if __name__ == '__main__':
+    for name, on in (('switch_off', False), ('switch_on', True)):
+        width, height, pixels = switch(on)
+        write_farbfeld('images/%s.ff' % name, width, height, pixels)
+        write_png('images/%s.png' % name, width, height, pixels)
@@ -0,0 +1,32 @@
+#!/usr/bin/env python3
+# Generates the floor texture of the teleporters: images/teleporter.ff (and a
+# png preview), glowing rings on a metal plate. Run it from the root of the
+# project: python3 tools/gen_teleporter.py
+import math
+
+from gen_font import write_farbfeld, write_png
+
+
+def teleporter(size=64):
+    center = (size - 1) / 2.0
+    pixels = []
+    for y in range(size):
+        row = []
+        for x in range(size):
+            d = math.hypot(x - center, y - center)
+            # the rings glow brighter in the middle of each one
+            ring = abs(math.sin(d * math.pi / 8.0))
+            if d < size / 2.0 - 2:
+                glow = ring ** 4
+                row.append((int(20 + 60 * glow), int(40 + 120 * glow), int(90 + 165 * glow), 255))
+            else:
+                # the border of the plate
+                row.append((70, 70, 80, 255))
+        pixels.append(row)
+    return size, size, pixels
+
+
+if __name__ == '__main__':
+    width, height, pixels = teleporter()
+    write_farbfeld('images/teleporter.ff', width, height, pixels)
+    write_png('images/teleporter.png', width, height, pixels)
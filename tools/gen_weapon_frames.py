@@ -0,0 +1,77 @@
+#!/usr/bin/env python3
+# Generates the sheets of the weapons in the player's hands, from the art
+# that is already there: images/crossbow_frames.ff from the crossbow icon
+# (arma_9) and images/sword_frames.ff from the sword sheet (espada_1), with
+# png previews. The frames are in a row, all the same size, see
+# weapons::WeaponFrames for what every one is. Run it from the root of the
+# project: python3 tools/gen_weapon_frames.py
+import math
+
+from gen_font import write_farbfeld, write_png
+from png2ff import read_png
+
+CLEAR = (0, 0, 0, 0)
+
+# where the sword is in espada_1 (left, top, right, bottom, included): held
+# up, and the swing going down to the left
+SWORD_POSES = [(60, 136, 72, 155), (34, 18, 50, 32), (0, 22, 8, 42), (4, 113, 16, 132)]
+SWORD_CELL = 24
+
+# the bolt of arma_9 is in this corner, above and left of the string
+BOLT_RIGHT, BOLT_BOTTOM = 10, 10
+
+
+def rotate(pixels, degrees):
+    # nearest neighbour around the center, it keeps the pixel art look
+    height, width = len(pixels), len(pixels[0])
+    cx, cy = (width - 1) / 2.0, (height - 1) / 2.0
+    sin, cos = math.sin(math.radians(degrees)), math.cos(math.radians(degrees))
+    rotated = []
+    for y in range(height):
+        row = []
+        for x in range(width):
+            sx = round(cx + (x - cx) * cos + (y - cy) * sin)
+            sy = round(cy - (x - cx) * sin + (y - cy) * cos)
+            row.append(pixels[sy][sx] if 0 <= sx < width and 0 <= sy < height else CLEAR)
+        rotated.append(row)
+    return rotated
+
+
+def without_bolt(pixels):
+    return [[CLEAR if x < BOLT_RIGHT and y < BOLT_BOTTOM else pixel
+             for x, pixel in enumerate(row)] for y, row in enumerate(pixels)]
+
+
+def crop(pixels, left, top, right, bottom, size):
+    # centered in a square cell
+    cell = [[CLEAR] * size for _ in range(size)]
+    x0 = (size - (right - left + 1)) // 2
+    y0 = (size - (bottom - top + 1)) // 2
+    for y in range(top, bottom + 1):
+        for x in range(left, right + 1):
+            cell[y0 + y - top][x0 + x - left] = pixels[y][x]
+    return cell
+
+
+def sheet(frames):
+    # side by side, in one row
+    return [sum((frame[y] for frame in frames), []) for y in range(len(frames[0]))]
+
+
+def crossbow():
+    _, _, icon = read_png('images/arma_9.png')
+    empty = without_bolt(icon)
+    # ready, fired, and tilted down while a new bolt goes in
+    return sheet([icon, empty, rotate(empty, 30), rotate(icon, 15)])
+
+
+def sword():
+    _, _, poses = read_png('images/espada_1.png')
+    return sheet([crop(poses, *pose, SWORD_CELL) for pose in SWORD_POSES])
+
+
+if __name__ == '__main__':
+    for name, pixels in [('crossbow_frames', crossbow()), ('sword_frames', sword())]:
+        width, height = len(pixels[0]), len(pixels)
+        write_farbfeld('images/%s.ff' % name, width, height, pixels)
+        write_png('images/%s.png' % name, width, height, pixels)
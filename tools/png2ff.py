@@ -0,0 +1,66 @@
+#!/usr/bin/env python3
+# Converts 8 bit RGB and RGBA pngs (not interlaced) to farbfeld, for when
+# png2ff (see png2ff.sh) is not installed. Run it from the root of the
+# project: python3 tools/png2ff.py images/stone_golem.png
+import struct
+import sys
+import zlib
+
+from gen_font import write_farbfeld
+
+
+def paeth(a, b, c):
+    p = a + b - c
+    pa, pb, pc = abs(p - a), abs(p - b), abs(p - c)
+    if pa <= pb and pa <= pc:
+        return a
+    return b if pb <= pc else c
+
+
+def read_png(path):
+    with open(path, 'rb') as f:
+        data = f.read()
+    assert data[:8] == b'\x89PNG\r\n\x1a\n', '%s is not a png' % path
+    position, idat = 8, b''
+    while position < len(data):
+        length, kind = struct.unpack('>I4s', data[position:position + 8])
+        body = data[position + 8:position + 8 + length]
+        if kind == b'IHDR':
+            width, height, depth, color, _, _, interlace = struct.unpack('>IIBBBBB', body)
+            assert depth == 8 and color in (2, 6) and interlace == 0, 'unsupported png'
+        elif kind == b'IDAT':
+            idat += body
+        position += length + 12
+    channels = 4 if color == 6 else 3
+    raw = zlib.decompress(idat)
+    stride = width * channels
+    previous = bytearray(stride)
+    pixels = []
+    for y in range(height):
+        start = y * (stride + 1)
+        kind, line = raw[start], bytearray(raw[start + 1:start + 1 + stride])
+        for i in range(stride):
+            left = line[i - channels] if i >= channels else 0
+            up = previous[i]
+            up_left = previous[i - channels] if i >= channels else 0
+            if kind == 1:
+                line[i] = (line[i] + left) & 0xff
+            elif kind == 2:
+                line[i] = (line[i] + up) & 0xff
+            elif kind == 3:
+                line[i] = (line[i] + (left + up) // 2) & 0xff
+            elif kind == 4:
+                line[i] = (line[i] + paeth(left, up, up_left)) & 0xff
+        previous = line
+        row = []
+        for x in range(width):
+            pixel = tuple(line[x * channels:(x + 1) * channels])
+            row.append(pixel if channels == 4 else pixel + (255,))
+        pixels.append(row)
+    return width, height, pixels
+
+
+if __name__ == '__main__':
+    for path in sys.argv[1:]:
+        width, height, pixels = read_png(path)
+        write_farbfeld(path[:-len('.png')] + '.ff', width, height, pixels)
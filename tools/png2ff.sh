@@ -1,4 +1,8 @@
 #!/usr/bin/env bash
+# Converts every png in images/ with png2ff from farbfeld (suckless). If it
+# isn't installed, convert the images one by one with tools/png2ff.py, it
+# only reads 8 bit RGB and RGBA pngs. The image generators in tools/ use
+# its png reader too.
 
 for f in images/*.png;
 do